@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // the proto is compiled in rust so building the grpc feature does not need protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/cleaner.proto");
+        let descriptors = protox::compile(["proto/cleaner.proto"], ["proto"])?;
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+/* c interface of hls-fragment-cleaner, build with `cargo build --release --features ffi` */
+#ifndef HLS_CLEANER_H
+#define HLS_CLEANER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define HLS_CLEANER_OK 0
+#define HLS_CLEANER_ERR_NULL -1
+#define HLS_CLEANER_ERR_INVALID_ARG -2
+#define HLS_CLEANER_ERR_CLEAN -3
+#define HLS_CLEANER_ERR_PANIC -4
+
+typedef struct HlsCleaner HlsCleaner;
+
+typedef struct HlsCleanReport {
+    uint64_t scanned;
+    uint64_t skipped;
+    uint64_t deleted;
+    uint64_t errors;
+    uint64_t bytes_freed;
+} HlsCleanReport;
+
+/* returns NULL on failure */
+HlsCleaner *hls_cleaner_new(const char *root);
+
+/* keys: "root", "orphan_max_age_secs", "action" ("delete", "trash" or "archive"),
+ * "archive_template" (see Policy::archive_template) */
+int hls_cleaner_configure(HlsCleaner *cleaner, const char *key, const char *value);
+
+/* blocks until the cycle is done, report may be NULL */
+int hls_cleaner_clean_once(HlsCleaner *cleaner, HlsCleanReport *report);
+
+/* NULL if no error, valid until the next call on the same handle */
+const char *hls_cleaner_last_error(const HlsCleaner *cleaner);
+
+void hls_cleaner_free(HlsCleaner *cleaner);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,209 @@
+//! read-only disk usage attribution per stream
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use crate::{
+    segment::{list_segments, parse_segment_name, playlist_path_for},
+    serde_time,
+    storage::Storage,
+    Playlist, Result,
+};
+
+/// usage of one root, see [`analyze`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Analysis {
+    pub root: PathBuf,
+    pub streams: Vec<StreamAnalysis>,
+    /// `.ts` files whose name does not follow `<stream>-<sequence>.ts`
+    pub invalid_segments: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StreamAnalysis {
+    pub stream: String,
+    pub segment_count: usize,
+    pub total_bytes: u64,
+    pub oldest: Option<SegmentSummary>,
+    pub newest: Option<SegmentSummary>,
+    /// bytes of fragments listed in the playlist
+    pub referenced_bytes: u64,
+    /// bytes of fragments the playlist does not list, all of them without a usable playlist
+    pub orphaned_bytes: u64,
+    pub playlist: PlaylistHealth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SegmentSummary {
+    pub path: PathBuf,
+    pub sequence: u32,
+    #[serde(serialize_with = "serde_time::opt_unix_secs")]
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PlaylistHealth {
+    Ok {
+        segments: usize,
+        /// referenced fragments not found on disk
+        missing_segments: usize,
+    },
+    Empty,
+    Missing,
+    Unparsable {
+        error: String,
+    },
+}
+
+impl PlaylistHealth {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok { .. })
+    }
+}
+
+impl std::fmt::Display for PlaylistHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok {
+                missing_segments: 0,
+                ..
+            } => write!(f, "ok"),
+            Self::Ok {
+                missing_segments, ..
+            } => write!(f, "ok, {} missing", missing_segments),
+            Self::Empty => write!(f, "empty"),
+            Self::Missing => write!(f, "missing"),
+            Self::Unparsable { .. } => write!(f, "unparsable"),
+        }
+    }
+}
+
+/// walk `root` without modifying anything and attribute every fragment to its stream
+pub fn analyze(storage: &dyn Storage, root: &Path, playlist_template: &str) -> Result<Analysis> {
+    let mut invalid_segments = Vec::new();
+    let mut by_stream: BTreeMap<String, Vec<(PathBuf, u32)>> = BTreeMap::new();
+    for path in list_segments(storage, root)? {
+        match parse_segment_name(&path) {
+            Ok((stream, sequence)) => {
+                let stream = stream.to_owned();
+                by_stream.entry(stream).or_default().push((path, sequence));
+            }
+            Err(_) => invalid_segments.push(path),
+        }
+    }
+    let streams = by_stream
+        .into_iter()
+        .map(|(stream, segments)| analyze_stream(storage, stream, segments, playlist_template))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Analysis {
+        root: root.to_owned(),
+        streams,
+        invalid_segments,
+    })
+}
+
+fn analyze_stream(
+    storage: &dyn Storage,
+    stream: String,
+    segments: Vec<(PathBuf, u32)>,
+    playlist_template: &str,
+) -> Result<StreamAnalysis> {
+    let playlist_path = playlist_path_for(&segments[0].0, &stream, playlist_template)?;
+    let (playlist, playlist_health) = match storage.exists(&playlist_path) {
+        false => (None, PlaylistHealth::Missing),
+        true => match Playlist::read(storage, &playlist_path) {
+            Ok(playlist) if playlist.segments.is_empty() => (None, PlaylistHealth::Empty),
+            Ok(playlist) => (Some(playlist), PlaylistHealth::Missing),
+            Err(e) => (
+                None,
+                PlaylistHealth::Unparsable {
+                    error: crate::report::error_chain(&e),
+                },
+            ),
+        },
+    };
+    let mut analysis = StreamAnalysis {
+        stream,
+        segment_count: 0,
+        total_bytes: 0,
+        oldest: None,
+        newest: None,
+        referenced_bytes: 0,
+        orphaned_bytes: 0,
+        playlist: playlist_health,
+    };
+    for (path, sequence) in segments {
+        // fragments removed since the listing are not counted
+        let metadata = match storage.metadata(&path) {
+            Err(e) if e.is_not_found() => continue,
+            metadata => metadata?,
+        };
+        analysis.segment_count += 1;
+        analysis.total_bytes += metadata.size;
+        let referenced = match (&playlist, path.file_name().and_then(|n| n.to_str())) {
+            (Some(playlist), Some(name)) => playlist.references(name),
+            _ => false,
+        };
+        match referenced {
+            true => analysis.referenced_bytes += metadata.size,
+            false => analysis.orphaned_bytes += metadata.size,
+        }
+        let summary = SegmentSummary {
+            path,
+            sequence,
+            modified: metadata.modified,
+        };
+        if analysis
+            .oldest
+            .as_ref()
+            .is_none_or(|o| summary.modified < o.modified)
+        {
+            analysis.oldest = Some(summary.clone());
+        }
+        if analysis
+            .newest
+            .as_ref()
+            .is_none_or(|n| summary.modified > n.modified)
+        {
+            analysis.newest = Some(summary);
+        }
+    }
+    if let Some(playlist) = &playlist {
+        let dir = playlist.path.parent().unwrap_or(Path::new(""));
+        let missing_segments = playlist
+            .segments
+            .iter()
+            .filter(|seg| !storage.exists(&dir.join(&seg.uri)))
+            .count();
+        analysis.playlist = PlaylistHealth::Ok {
+            segments: playlist.segments.len(),
+            missing_segments,
+        };
+    }
+    Ok(analysis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+
+    #[test]
+    fn fragments_removed_since_the_listing_are_skipped() {
+        let storage = MemoryStore::new();
+        let root = Path::new("/hls");
+        storage.insert(root.join("live-1.ts"), "x", SystemTime::UNIX_EPOCH);
+        let segments = vec![(root.join("live-0.ts"), 0), (root.join("live-1.ts"), 1)];
+        let analysis =
+            analyze_stream(&storage, "live".to_owned(), segments, "{stream}.m3u8").unwrap();
+        assert_eq!(analysis.segment_count, 1);
+        assert_eq!(analysis.total_bytes, 1);
+        assert_eq!(analysis.oldest.unwrap().sequence, 1);
+    }
+}
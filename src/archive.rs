@@ -0,0 +1,949 @@
+//! destination paths of [`Action::Archive`](crate::Action::Archive)
+//!
+//! the policy archive template is expanded per fragment, `%Y %m %d %H %M %S %j %%` are taken
+//! from the archive time in utc and `{stream}` is the stream name. templates are relative to
+//! the cleaner root and must start with a fixed directory below it, such as `.archive`, so
+//! archived content never mixes with the live fragments or ends up outside the root.
+//!
+//! with a compression level set, fragments are stored zstd compressed as `<name>.ts.zst`, which
+//! needs the `zstd` feature, and with a key they are then sealed as `<name>.enc`, see
+//! [`encrypt`](crate::encrypt). every archived fragment is appended to `<stream>.manifest.ndjson`
+//! in its archive directory along with the sha-256 of the stored file, which [`verify`]
+//! checks again later, opening sealed files too when given the key. [`restore`] copies archived
+//! fragments back to where they were archived from, opened and decompressed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    encrypt::{self, EncryptionKey},
+    serde_time,
+    storage::{self, Storage},
+    trash::RestoreReport,
+    CleanerError, CleanerObserver, Result, SegmentInfo,
+};
+
+/// default archive template, relative to the root
+pub const DEFAULT_TEMPLATE: &str = ".archive/%Y/%m/%d/{stream}";
+
+pub(crate) fn archive_path(
+    template: &str,
+    root: &Path,
+    segment: &SegmentInfo,
+    now: SystemTime,
+) -> Result<PathBuf> {
+    let mut path = expand_dir(template, root, &segment.stream, now)?;
+    if let Some(name) = segment.path.file_name() {
+        path.push(name);
+    }
+    Ok(path)
+}
+
+/// one line of an archive manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// file name inside the archive directory
+    pub file: String,
+    /// size of the original fragment
+    pub size: u64,
+    /// size on disk, smaller than `size` when compressed
+    pub stored_size: u64,
+    /// unix seconds
+    pub archived_at: u64,
+    /// hex sha-256 of the stored file, absent in manifests written before checksums
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// where the fragment was archived from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// the stored file is sealed with the archive key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// `#EXT-X-DATERANGE` tags of the fragments, in sequence order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub date_ranges: Vec<String>,
+}
+
+/// appended to a manifest once a fragment was uploaded and its local copy removed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadRecord {
+    pub file: String,
+    /// remote location, `s3://bucket/key`
+    pub uploaded: String,
+    /// object key in the remote store
+    #[serde(default)]
+    pub key: String,
+    /// unix seconds
+    pub uploaded_at: u64,
+}
+
+/// appended to a manifest once the remote copy of a fragment was deleted for good
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiryRecord {
+    pub file: String,
+    /// unix seconds
+    pub expired_at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestLine {
+    Archived(ManifestEntry),
+    Uploaded(UploadRecord),
+    Expired(ExpiryRecord),
+}
+
+/// where an archived fragment currently lives, derived from the manifest records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    Local,
+    Remote,
+    Expired,
+}
+
+/// manifest of the fragments of `stream` archived into `dir`
+pub fn manifest_path(dir: &Path, stream: &str) -> PathBuf {
+    dir.join(format!("{}.manifest.ndjson", stream))
+}
+
+/// move, or compress then remove, `segment` to `to` and record it in the manifest
+///
+/// returns the path the fragment ended up at. a name archived before, by a packager that
+/// restarted its sequence numbers, is never overwritten, the fragment is stored as
+/// `<stem>.<n>.ts` with the first free `n` instead.
+pub(crate) fn archive(
+    storage: &dyn Storage,
+    segment: &SegmentInfo,
+    to: &Path,
+    compression: Option<i32>,
+    key: Option<&EncryptionKey>,
+    now: SystemTime,
+    observer: &dyn CleanerObserver,
+) -> Result<PathBuf> {
+    let mut suffix = String::new();
+    if compression.is_some() {
+        suffix.push_str(".zst");
+    }
+    if key.is_some() {
+        suffix.push_str(".enc");
+    }
+    let dest = unused_path(storage, to, &segment.stream, &suffix)?;
+    let (size, stored) = match (compression, key) {
+        (None, None) => {
+            storage.rename(&segment.path, &dest)?;
+            let stored = storage.read(&dest)?;
+            (stored.len() as u64, stored)
+        }
+        _ => {
+            let contents = storage.read(&segment.path)?;
+            let mut stored = match compression {
+                Some(level) => compress(&contents, level)?,
+                None => contents.clone(),
+            };
+            if let Some(key) = key {
+                stored = encrypt::seal(key, &stored)?;
+            }
+            storage::write_atomic(storage, &dest, &stored)?;
+            storage.remove(&segment.path)?;
+            (contents.len() as u64, stored)
+        }
+    };
+    let entry = ManifestEntry {
+        file: dest
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size,
+        stored_size: stored.len() as u64,
+        archived_at: serde_time::to_unix_secs(now),
+        sha256: Some(sha256_hex(&stored)),
+        source: Some(segment.path.clone()),
+        encrypted: key.is_some(),
+        date_ranges: segment.date_range.iter().cloned().collect(),
+    };
+    if let Some(dir) = dest.parent() {
+        append_manifest(storage, &manifest_path(dir, &segment.stream), &entry)?;
+    }
+    observer.on_archive(&[segment], &dest, &entry);
+    Ok(dest)
+}
+
+/// `to` with `suffix` appended, or with a counter before the extension when a file of that name
+/// is on disk or listed by the manifest of `stream`, uploaded ones included
+fn unused_path(storage: &dyn Storage, to: &Path, stream: &str, suffix: &str) -> Result<PathBuf> {
+    let (Some(dir), Some(name)) = (to.parent(), to.file_name()) else {
+        return Err(CleanerError::Storage(format!(
+            "{} has no file name",
+            to.display()
+        )));
+    };
+    let name = name.to_string_lossy();
+    let listed = tiers(storage, &manifest_path(dir, stream))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(entry, _)| entry.file)
+        .collect::<HashSet<_>>();
+    let taken = |file: &str| listed.contains(file) || storage.exists(&dir.join(file));
+    let first = format!("{}{}", name, suffix);
+    if !taken(&first) {
+        return Ok(dir.join(first));
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (&*name, String::new()),
+    };
+    let file = (1u32..)
+        .map(|n| format!("{}.{}{}{}", stem, n, extension, suffix))
+        .find(|file| !taken(file))
+        .unwrap_or_default();
+    tracing::warn!(
+        "{} was archived before, archiving {} as {}",
+        dir.join(&first).display(),
+        name,
+        file
+    );
+    Ok(dir.join(file))
+}
+
+/// files of a manifest whose local copy was removed after upload and that still exist remotely
+pub(crate) fn uploaded_files(storage: &dyn Storage, manifest: &Path) -> Vec<String> {
+    uploads(storage, manifest)
+        .into_iter()
+        .filter(|(_, expired)| !expired)
+        .map(|(record, _)| record.file)
+        .collect()
+}
+
+/// upload records of a manifest, paired with whether the remote copy has expired since
+pub(crate) fn uploads(storage: &dyn Storage, manifest: &Path) -> Vec<(UploadRecord, bool)> {
+    let Ok(content) = storage.read_to_string(manifest) else {
+        return Vec::new();
+    };
+    let mut uploads = Vec::new();
+    let mut expired = HashSet::new();
+    for line in content.lines() {
+        match serde_json::from_str(line) {
+            Ok(ManifestLine::Uploaded(record)) => uploads.push(record),
+            Ok(ManifestLine::Expired(record)) => {
+                expired.insert(record.file);
+            }
+            _ => {}
+        }
+    }
+    uploads
+        .into_iter()
+        .map(|record| {
+            let expired = expired.contains(&record.file);
+            (record, expired)
+        })
+        .collect()
+}
+
+/// current tier of every fragment listed in a manifest, in manifest order
+pub fn tiers(storage: &dyn Storage, manifest: &Path) -> Result<Vec<(ManifestEntry, Tier)>> {
+    let content = storage.read_to_string(manifest)?;
+    let mut entries = Vec::new();
+    let mut tiers = HashMap::new();
+    for line in content.lines() {
+        match serde_json::from_str(line) {
+            Ok(ManifestLine::Archived(entry)) => {
+                tiers.insert(entry.file.clone(), Tier::Local);
+                entries.push(entry);
+            }
+            Ok(ManifestLine::Uploaded(record)) => {
+                tiers.insert(record.file, Tier::Remote);
+            }
+            Ok(ManifestLine::Expired(record)) => {
+                tiers.insert(record.file, Tier::Expired);
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let tier = tiers.get(&entry.file).copied().unwrap_or(Tier::Local);
+            (entry, tier)
+        })
+        .collect())
+}
+
+pub(crate) fn append_manifest(
+    storage: &dyn Storage,
+    manifest: &Path,
+    line: &impl Serialize,
+) -> Result<()> {
+    let mut line = serde_json::to_vec(line)
+        .map_err(|e| CleanerError::Storage(format!("unable to serialize manifest - {}", e)))?;
+    line.push(b'\n');
+    storage.append(manifest, &line)
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    hex(&Sha256::digest(contents))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// a manifest entry that no longer matches the archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ArchiveIssue {
+    UnreadableManifest {
+        manifest: PathBuf,
+        error: String,
+    },
+    MissingFile {
+        manifest: PathBuf,
+        file: PathBuf,
+    },
+    SizeMismatch {
+        file: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch {
+        file: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// a sealed file that does not open with the archive key
+    Undecryptable {
+        file: PathBuf,
+        error: String,
+    },
+}
+
+impl std::fmt::Display for ArchiveIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnreadableManifest { manifest, error } => {
+                write!(
+                    f,
+                    "unable to read manifest {} - {}",
+                    manifest.display(),
+                    error
+                )
+            }
+            Self::MissingFile { manifest, file } => write!(
+                f,
+                "{} listed in {} is missing",
+                file.display(),
+                manifest.display()
+            ),
+            Self::SizeMismatch {
+                file,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {} bytes, expected {}",
+                file.display(),
+                actual,
+                expected
+            ),
+            Self::ChecksumMismatch { file, .. } => {
+                write!(f, "{} does not match its sha-256", file.display())
+            }
+            Self::Undecryptable { file, error } => {
+                write!(f, "unable to open {} - {}", file.display(), error)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveVerification {
+    pub dir: PathBuf,
+    pub manifests: usize,
+    /// entries whose file matched size and checksum
+    pub verified: usize,
+    /// entries without a checksum, only their size was checked
+    pub unchecked: usize,
+    /// sealed entries that also opened with the archive key
+    pub decrypted: usize,
+    /// entries uploaded to a remote store, not checked locally
+    pub remote: usize,
+    /// entries whose remote copy was deleted after the remote retention
+    pub expired: usize,
+    pub issues: Vec<ArchiveIssue>,
+}
+
+/// directory every path of `template` lives under, the part before the first placeholder
+///
+/// fails for templates without a fixed directory below the root, walking the root itself as
+/// the archive would take the live fragments for archived ones.
+pub fn archive_root(template: &str, root: &Path) -> Result<PathBuf> {
+    let fixed = Path::new(template)
+        .components()
+        .take_while(|c| {
+            let c = c.as_os_str().to_string_lossy();
+            !c.contains('%') && !c.contains("{stream}")
+        })
+        .collect::<PathBuf>();
+    check_relative(&format!("archive template {}", template), &fixed)?;
+    if !fixed
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(CleanerError::Config(format!(
+            "archive template {} has no fixed directory below the root",
+            template
+        )));
+    }
+    Ok(root.join(fixed))
+}
+
+/// every manifest found below `dir`
+fn manifests(storage: &dyn Storage, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut manifests = Vec::new();
+    if !storage.exists(dir) {
+        return Ok(manifests);
+    }
+    let mut pending = vec![dir.to_owned()];
+    while let Some(current) = pending.pop() {
+        for entry in storage.list(&current)? {
+            if entry.is_dir {
+                pending.push(entry.path);
+            } else if entry.path.to_string_lossy().ends_with(".manifest.ndjson") {
+                manifests.push(entry.path);
+            }
+        }
+    }
+    Ok(manifests)
+}
+
+/// re-check every manifest found below `dir` against the files it lists, sealed files are
+/// opened as well when `key` is given
+pub fn verify(
+    storage: &dyn Storage,
+    dir: &Path,
+    key: Option<&EncryptionKey>,
+) -> Result<ArchiveVerification> {
+    let mut verification = ArchiveVerification {
+        dir: dir.to_owned(),
+        ..Default::default()
+    };
+    for manifest in manifests(storage, dir)? {
+        verification.manifests += 1;
+        verify_manifest(storage, &manifest, key, &mut verification);
+    }
+    Ok(verification)
+}
+
+fn verify_manifest(
+    storage: &dyn Storage,
+    manifest: &Path,
+    key: Option<&EncryptionKey>,
+    verification: &mut ArchiveVerification,
+) {
+    let content = match storage.read_to_string(manifest) {
+        Ok(content) => content,
+        Err(e) => {
+            verification.issues.push(ArchiveIssue::UnreadableManifest {
+                manifest: manifest.to_owned(),
+                error: crate::report::error_chain(&e),
+            });
+            return;
+        }
+    };
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let mut entries = Vec::new();
+    let mut tiers = HashMap::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(ManifestLine::Archived(entry)) => entries.push(entry),
+            Ok(ManifestLine::Uploaded(record)) => {
+                tiers.insert(record.file, Tier::Remote);
+            }
+            Ok(ManifestLine::Expired(record)) => {
+                tiers.insert(record.file, Tier::Expired);
+            }
+            Err(e) => verification.issues.push(ArchiveIssue::UnreadableManifest {
+                manifest: manifest.to_owned(),
+                error: e.to_string(),
+            }),
+        }
+    }
+    for entry in entries {
+        match tiers.get(&entry.file) {
+            Some(Tier::Remote) => {
+                verification.remote += 1;
+                continue;
+            }
+            Some(Tier::Expired) => {
+                verification.expired += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let file = dir.join(&entry.file);
+        let contents = match storage.read(&file) {
+            Ok(contents) => contents,
+            Err(_) => {
+                verification.issues.push(ArchiveIssue::MissingFile {
+                    manifest: manifest.to_owned(),
+                    file,
+                });
+                continue;
+            }
+        };
+        if contents.len() as u64 != entry.stored_size {
+            verification.issues.push(ArchiveIssue::SizeMismatch {
+                file,
+                expected: entry.stored_size,
+                actual: contents.len() as u64,
+            });
+            continue;
+        }
+        match entry.sha256 {
+            None => verification.unchecked += 1,
+            Some(expected) => {
+                let actual = sha256_hex(&contents);
+                if actual != expected {
+                    verification.issues.push(ArchiveIssue::ChecksumMismatch {
+                        file,
+                        expected,
+                        actual,
+                    });
+                    continue;
+                }
+                verification.verified += 1;
+            }
+        }
+        if let (true, Some(key)) = (entry.encrypted, key) {
+            match encrypt::open(key, &contents) {
+                Ok(_) => verification.decrypted += 1,
+                Err(e) => verification.issues.push(ArchiveIssue::Undecryptable {
+                    file,
+                    error: crate::report::error_chain(&e),
+                }),
+            }
+        }
+    }
+}
+
+/// copy the archived fragments of `stream` below `dir` back to where they were archived from,
+/// only the ones archived at or after `since` when given
+///
+/// sealed fragments need `key`. the archived copies and their manifest are left as they are,
+/// a fragment whose original path is taken again is reported as a conflict.
+pub(crate) fn restore(
+    storage: &dyn Storage,
+    dir: &Path,
+    stream: &str,
+    key: Option<&EncryptionKey>,
+    since: Option<SystemTime>,
+) -> Result<RestoreReport> {
+    let mut report = RestoreReport::default();
+    let name = manifest_path(Path::new(""), stream);
+    let since = since.map(serde_time::to_unix_secs);
+    for manifest in manifests(storage, dir)? {
+        if manifest.file_name() != name.file_name() {
+            continue;
+        }
+        let archive_dir = manifest.parent().unwrap_or(Path::new(""));
+        for (entry, tier) in tiers(storage, &manifest)? {
+            let Some(source) = entry.source.filter(|_| tier == Tier::Local) else {
+                continue;
+            };
+            if since.is_some_and(|since| entry.archived_at < since) {
+                continue;
+            }
+            let file = archive_dir.join(&entry.file);
+            if storage.exists(&source) {
+                tracing::warn!(
+                    "{} already exists, leaving {} archived only",
+                    source.display(),
+                    file.display()
+                );
+                report.conflicts.push(file);
+                continue;
+            }
+            let restored = storage
+                .read(&file)
+                .and_then(|stored| unpack(&entry.file, entry.encrypted, stored, key))
+                .and_then(|contents| storage::write_atomic(storage, &source, &contents));
+            match restored {
+                Ok(()) => {
+                    tracing::info!("restored {} from {}", source.display(), file.display());
+                    report.restored.push(source);
+                }
+                Err(e) => report.errors.push(format!("{} - {}", file.display(), e)),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// the original contents of the archived file `name`, opened then decompressed
+fn unpack(
+    name: &str,
+    encrypted: bool,
+    stored: Vec<u8>,
+    key: Option<&EncryptionKey>,
+) -> Result<Vec<u8>> {
+    let (name, stored) = match (encrypted, key) {
+        (false, _) => (name, stored),
+        (true, Some(key)) => (
+            name.strip_suffix(".enc").unwrap_or(name),
+            encrypt::open(key, &stored)?,
+        ),
+        (true, None) => {
+            return Err(CleanerError::Config(
+                "the file is sealed, restoring it needs the archive key".to_owned(),
+            ))
+        }
+    };
+    match name.ends_with(".zst") {
+        true => decompress(&stored),
+        false => Ok(stored),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compress(contents: &[u8], level: i32) -> Result<Vec<u8>> {
+    zstd::bulk::compress(contents, level)
+        .map_err(|e| CleanerError::Storage(format!("zstd compression failed - {}", e)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_contents: &[u8], _level: i32) -> Result<Vec<u8>> {
+    Err(CleanerError::Config(
+        "archive compression needs the zstd feature".to_owned(),
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(stored: &[u8]) -> Result<Vec<u8>> {
+    zstd::stream::decode_all(stored)
+        .map_err(|e| CleanerError::Storage(format!("zstd decompression failed - {}", e)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_stored: &[u8]) -> Result<Vec<u8>> {
+    Err(CleanerError::Config(
+        "compressed archives need the zstd feature".to_owned(),
+    ))
+}
+
+/// check a template expands below a fixed directory of the root, so a typo fails at startup
+/// rather than on every fragment
+pub fn check_template(template: &str) -> Result<()> {
+    archive_root(template, Path::new(""))?;
+    expand_dir(template, Path::new(""), "stream", SystemTime::UNIX_EPOCH).map(drop)
+}
+
+/// the directory `template` expands to for `stream` below `root`
+///
+/// fails when the expansion is not a directory below the root, a stream named `..` included.
+pub(crate) fn expand_dir(
+    template: &str,
+    root: &Path,
+    stream: &str,
+    now: SystemTime,
+) -> Result<PathBuf> {
+    let expanded = PathBuf::from(expand(template, stream, now)?);
+    check_relative(&format!("archive template {}", template), &expanded)?;
+    if !expanded
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(CleanerError::Config(format!(
+            "archive template {} expands to the root itself",
+            template
+        )));
+    }
+    Ok(root.join(expanded))
+}
+
+/// `path` stays below the root it is joined to, `what` names it in the error
+pub(crate) fn check_relative(what: &str, path: &Path) -> Result<()> {
+    let escapes = path.has_root()
+        || path
+            .components()
+            .any(|c| matches!(c, Component::Prefix(_) | Component::ParentDir));
+    match escapes {
+        true => Err(CleanerError::Config(format!(
+            "{} leads outside the root",
+            what
+        ))),
+        false => Ok(()),
+    }
+}
+
+pub(crate) fn expand(template: &str, stream: &str, now: SystemTime) -> Result<String> {
+    let secs = serde_time::to_unix_secs(now);
+    let (year, month, day, yday) = civil_from_days((secs / 86400) as i64);
+    let time_of_day = secs % 86400;
+    let mut out = String::with_capacity(template.len() + stream.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('j') => out.push_str(&format!("{:03}", yday)),
+            Some('H') => out.push_str(&format!("{:02}", time_of_day / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", time_of_day % 3600 / 60)),
+            Some('S') => out.push_str(&format!("{:02}", time_of_day % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                return Err(CleanerError::Config(format!(
+                    "unsupported archive template specifier %{} in {}",
+                    other, template
+                )))
+            }
+            None => {
+                return Err(CleanerError::Config(format!(
+                    "dangling % in archive template {}",
+                    template
+                )))
+            }
+        }
+    }
+    Ok(out.replace("{stream}", stream))
+}
+
+/// year, month, day and day of the year of a day count since the unix epoch
+///
+/// howard hinnant's days_from_civil inverse, valid for the whole proleptic gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    const CUMULATIVE: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let yday = CUMULATIVE[month as usize - 1] + day + u32::from(leap && month > 2);
+    (year, month, day, yday)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{observer::Observers, storage::MemoryStore};
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// archive `/hls/<name>` to `/hls/.archive/<name>` at `secs`, returns where it ended up
+    fn archived(
+        store: &MemoryStore,
+        name: &str,
+        compression: Option<i32>,
+        key: Option<&EncryptionKey>,
+        secs: u64,
+    ) -> PathBuf {
+        let path = Path::new("/hls").join(name);
+        store.insert(&path, format!("contents of {}", name), at(secs));
+        let segment = SegmentInfo {
+            path,
+            stream: "live".to_owned(),
+            sequence: 1,
+            size: 0,
+            modified: Some(at(secs)),
+            date_range: None,
+        };
+        let to = Path::new("/hls/.archive").join(name);
+        archive(
+            store,
+            &segment,
+            &to,
+            compression,
+            key,
+            at(secs),
+            &Observers::default(),
+        )
+        .unwrap()
+    }
+
+    fn restore_all(store: &MemoryStore, key: Option<&EncryptionKey>) -> RestoreReport {
+        restore(store, Path::new("/hls/.archive"), "live", key, None).unwrap()
+    }
+
+    fn contents(store: &MemoryStore, name: &str) -> String {
+        store.read_to_string(&Path::new("/hls").join(name)).unwrap()
+    }
+
+    #[test]
+    fn restore_copies_archived_fragments_back() {
+        let store = MemoryStore::new();
+        let file = archived(&store, "live-1.ts", None, None, 100);
+        archived(&store, "live-2.ts", None, None, 200);
+        let report = restore(
+            &store,
+            Path::new("/hls/.archive"),
+            "live",
+            None,
+            Some(at(150)),
+        )
+        .unwrap();
+        assert_eq!(report.restored, [PathBuf::from("/hls/live-2.ts")]);
+        let report = restore_all(&store, None);
+        assert_eq!(report.restored, [PathBuf::from("/hls/live-1.ts")]);
+        assert_eq!(report.conflicts, [PathBuf::from("/hls/.archive/live-2.ts")]);
+        assert_eq!(contents(&store, "live-1.ts"), "contents of live-1.ts");
+        // the archive is left as it is
+        assert!(store.exists(&file));
+        assert!(
+            restore(&store, Path::new("/hls/.archive"), "other", None, None)
+                .unwrap()
+                .restored
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn archiving_the_same_name_again_keeps_both() {
+        let store = MemoryStore::new();
+        let first = archived(&store, "live-0.ts", None, None, 100);
+        // the packager restarted at sequence 0 with other contents
+        let name = "live-0.ts";
+        store.insert(Path::new("/hls").join(name), "after the restart", at(200));
+        let segment = SegmentInfo {
+            path: Path::new("/hls").join(name),
+            stream: "live".to_owned(),
+            sequence: 0,
+            size: 0,
+            modified: Some(at(200)),
+            date_range: None,
+        };
+        let to = Path::new("/hls/.archive").join(name);
+        let archive_again = || {
+            archive(
+                &store,
+                &segment,
+                &to,
+                None,
+                None,
+                at(200),
+                &Observers::default(),
+            )
+        };
+        let second = archive_again().unwrap();
+        assert_eq!(first, Path::new("/hls/.archive/live-0.ts"));
+        assert_eq!(second, Path::new("/hls/.archive/live-0.1.ts"));
+        assert_eq!(
+            store.read_to_string(&first).unwrap(),
+            "contents of live-0.ts"
+        );
+        assert_eq!(store.read_to_string(&second).unwrap(), "after the restart");
+        // names of uploaded fragments stay taken after their local copy is gone
+        store.remove(&first).unwrap();
+        store.insert(Path::new("/hls").join(name), "third", at(300));
+        assert_eq!(
+            archive_again().unwrap(),
+            Path::new("/hls/.archive/live-0.2.ts")
+        );
+        store.insert(&first, "contents of live-0.ts", at(100));
+        let verification = verify(&store, Path::new("/hls/.archive"), None).unwrap();
+        assert_eq!(verification.verified, 3);
+        assert!(verification.issues.is_empty());
+    }
+
+    #[test]
+    fn templates_stay_in_a_directory_below_the_root() {
+        for template in [
+            DEFAULT_TEMPLATE,
+            crate::vod::DEFAULT_TEMPLATE,
+            "archive/{stream}",
+            "./a",
+        ] {
+            assert!(check_template(template).is_ok(), "{}", template);
+        }
+        for template in [
+            "",
+            ".",
+            "./",
+            "{stream}",
+            "%Y/{stream}",
+            "/srv/archive",
+            "../archive",
+            "a/../..",
+            ".archive/{stream}/..",
+            ".archive/%Y/../..",
+        ] {
+            assert!(check_template(template).is_err(), "{}", template);
+        }
+        assert!(archive_root("{stream}/.archive", Path::new("/hls")).is_err());
+        assert_eq!(
+            archive_root(DEFAULT_TEMPLATE, Path::new("/hls")).unwrap(),
+            Path::new("/hls/.archive")
+        );
+    }
+
+    #[test]
+    fn streams_cannot_lead_the_archive_outside_the_root() {
+        let segment = SegmentInfo {
+            path: "/hls/..-1.ts".into(),
+            stream: "..".to_owned(),
+            sequence: 1,
+            size: 0,
+            modified: None,
+            date_range: None,
+        };
+        assert!(archive_path("archive/{stream}/..", Path::new("/hls"), &segment, at(0)).is_err());
+        assert!(archive_path(".a/{stream}", Path::new("/hls"), &segment, at(0)).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn sealed_fragments_are_opened_by_verify_and_restore() {
+        let store = MemoryStore::new();
+        let key = EncryptionKey::new([3; 32]);
+        let file = archived(&store, "live-1.ts", None, Some(&key), 100);
+        assert_eq!(file, Path::new("/hls/.archive/live-1.ts.enc"));
+        assert_ne!(store.read(&file).unwrap(), b"contents of live-1.ts");
+
+        let verification = verify(&store, Path::new("/hls/.archive"), Some(&key)).unwrap();
+        assert_eq!((verification.verified, verification.decrypted), (1, 1));
+        assert!(verification.issues.is_empty());
+        let other = EncryptionKey::new([4; 32]);
+        let verification = verify(&store, Path::new("/hls/.archive"), Some(&other)).unwrap();
+        assert!(matches!(
+            &verification.issues[..],
+            [ArchiveIssue::Undecryptable { file: f, .. }] if *f == file
+        ));
+        let verification = verify(&store, Path::new("/hls/.archive"), None).unwrap();
+        assert_eq!((verification.verified, verification.decrypted), (1, 0));
+
+        assert_eq!(restore_all(&store, None).errors.len(), 1);
+        assert_eq!(restore_all(&store, Some(&other)).errors.len(), 1);
+        assert!(!store.exists(Path::new("/hls/live-1.ts")));
+        assert_eq!(restore_all(&store, Some(&key)).restored.len(), 1);
+        assert_eq!(contents(&store, "live-1.ts"), "contents of live-1.ts");
+    }
+
+    #[cfg(all(feature = "encryption", feature = "zstd"))]
+    #[test]
+    fn compressed_and_sealed_fragments_round_trip() {
+        let store = MemoryStore::new();
+        let key = EncryptionKey::new([3; 32]);
+        let file = archived(&store, "live-1.ts", Some(3), Some(&key), 100);
+        assert_eq!(file, Path::new("/hls/.archive/live-1.ts.zst.enc"));
+        assert_eq!(restore_all(&store, Some(&key)).restored.len(), 1);
+        assert_eq!(contents(&store, "live-1.ts"), "contents of live-1.ts");
+    }
+}
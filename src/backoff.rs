@@ -0,0 +1,60 @@
+//! exponential backoff of streams failing cycle after cycle
+//!
+//! a stream whose fragments cannot be evaluated, typically because of a corrupt playlist, is
+//! skipped for 1, 3, 7... cycles after its second, third, fourth... failure in a row, at most
+//! [`MAX_BACKOFF_CYCLES`], and retried normally again once a cycle evaluates it cleanly.
+
+use std::{collections::HashMap, sync::Mutex};
+
+/// longest a failing stream is left alone, in cycles
+pub(crate) const MAX_BACKOFF_CYCLES: u32 = 63;
+
+#[derive(Debug)]
+struct Failures {
+    /// failed cycles in a row
+    count: u32,
+    /// cycles left to skip
+    skip: u32,
+}
+
+/// per stream failures, shared by the clones of a cleaner
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    streams: Mutex<HashMap<String, Failures>>,
+}
+
+impl Backoff {
+    /// whether `stream` is skipped this cycle, counts the skipped cycle down
+    pub(crate) fn skips(&self, stream: &str) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        match streams.get_mut(stream) {
+            Some(failures) if failures.skip > 0 => {
+                failures.skip -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// record a failed cycle of `stream`, returns how many cycles it is skipped for
+    pub(crate) fn failed(&self, stream: &str) -> u32 {
+        let mut streams = self.streams.lock().unwrap();
+        let failures = streams
+            .entry(stream.to_owned())
+            .or_insert(Failures { count: 0, skip: 0 });
+        failures.count = failures.count.saturating_add(1);
+        failures.skip = 2u32
+            .saturating_pow(failures.count - 1)
+            .saturating_sub(1)
+            .min(MAX_BACKOFF_CYCLES);
+        failures.skip
+    }
+
+    /// `stream` was evaluated without errors
+    pub(crate) fn recovered(&self, stream: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        if streams.remove(stream).is_some() {
+            tracing::info!("{} recovered, no longer backing off", stream);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! ad and program boundaries signaled by `#EXT-X-DATERANGE` tags
+//!
+//! tags leave the live playlist along with their segment, before the cleaner gets to the
+//! fragment, so every tag seen in a live playlist is remembered until its fragment is removed.
+//! a fragment inside an ad break, from the segment carrying `SCTE35-OUT` up to the one
+//! carrying `SCTE35-IN`, is only removed once the whole break scrolled out of the playlist so
+//! the archive never ends in the middle of a break. breaks without an `SCTE35-IN` are held for
+//! at most the policy's maximum break hold.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{segment::parse_segment_name, DateRange, Playlist};
+
+#[derive(Debug)]
+struct Mark {
+    range: DateRange,
+    /// when the tag was first seen
+    seen: SystemTime,
+}
+
+/// date range tags of the fragments still on disk, per stream and sequence number
+#[derive(Debug, Default)]
+pub(crate) struct Boundaries {
+    streams: Mutex<HashMap<String, BTreeMap<u32, Mark>>>,
+}
+
+impl Boundaries {
+    /// remember the date ranges of `playlist`, the first sighting of a tag is kept
+    pub(crate) fn observe(&self, stream: &str, playlist: &Playlist, now: SystemTime) {
+        let mut tagged = playlist
+            .segments
+            .iter()
+            .filter_map(|s| Some((s.date_range.as_ref()?, &s.uri)))
+            .peekable();
+        if tagged.peek().is_none() {
+            return;
+        }
+        let mut streams = self.streams.lock().unwrap();
+        let marks = streams.entry(stream.to_owned()).or_default();
+        for (range, uri) in tagged {
+            let Ok((_, sequence)) = parse_segment_name(Path::new(uri)) else {
+                continue;
+            };
+            match marks.get(&sequence) {
+                Some(mark) if mark.range == *range => {}
+                _ => {
+                    marks.insert(
+                        sequence,
+                        Mark {
+                            range: range.clone(),
+                            seen: now,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// the date range tag preceding the fragment
+    pub(crate) fn tag(&self, stream: &str, sequence: u32) -> Option<String> {
+        let streams = self.streams.lock().unwrap();
+        Some(streams.get(stream)?.get(&sequence)?.range.tag.clone())
+    }
+
+    /// whether the fragment is part of an ad break that is still partly listed by the playlist
+    /// starting at `min_sequence`
+    pub(crate) fn holds(
+        &self,
+        stream: &str,
+        sequence: u32,
+        min_sequence: u32,
+        now: SystemTime,
+        max_hold: Duration,
+    ) -> bool {
+        let streams = self.streams.lock().unwrap();
+        let Some(marks) = streams.get(stream) else {
+            return false;
+        };
+        // the closest cue at or before the fragment decides whether it is inside a break
+        let cue = marks
+            .range(..=sequence)
+            .rev()
+            .find(|(_, m)| m.range.scte35_out || m.range.scte35_in);
+        let Some((&out, mark)) = cue.filter(|(_, m)| !m.range.scte35_in) else {
+            return false;
+        };
+        let end = marks
+            .range(out + 1..)
+            .find(|(_, m)| m.range.scte35_in)
+            .map(|(&sequence, _)| sequence);
+        match end {
+            Some(end) => end > min_sequence,
+            None => now.duration_since(mark.seen).unwrap_or_default() < max_hold,
+        }
+    }
+
+    /// drop the tag of a removed fragment
+    pub(crate) fn forget(&self, stream: &str, sequence: u32) {
+        let mut streams = self.streams.lock().unwrap();
+        if let Some(marks) = streams.get_mut(stream) {
+            marks.remove(&sequence);
+            if marks.is_empty() {
+                streams.remove(stream);
+            }
+        }
+    }
+}
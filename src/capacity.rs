@@ -0,0 +1,139 @@
+//! time until the filesystem of a root fills up
+//!
+//! the used space of the filesystem is sampled after every cycle. what was written since the
+//! previous sample is the change in used space plus what the cycle freed, the growth and
+//! reclaim rates are averaged over about [`SMOOTHING`] so a burst of fragments or a cycle
+//! catching up on a backlog does not swing the estimate.
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{serde_time, storage::DiskSpace};
+
+/// time constant of the rate averages
+pub const SMOOTHING: Duration = Duration::from_secs(600);
+
+/// estimate attached to [`CleanReport::capacity`](crate::CleanReport::capacity)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapacityForecast {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    /// bytes per second written to the filesystem
+    pub growth_rate: f64,
+    /// bytes per second freed by the cleaner
+    pub reclaim_rate: f64,
+    /// until the filesystem is full at the current rates, `None` while it is not filling up
+    /// or before the second cycle
+    #[serde(serialize_with = "serde_time::opt_secs")]
+    pub time_to_full: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Sample {
+    at: SystemTime,
+    used: u64,
+    /// growth and reclaim rates, from the second sample on
+    rates: Option<(f64, f64)>,
+}
+
+/// rate averages of one root, shared by the clones of a cleaner
+#[derive(Debug)]
+pub(crate) struct Forecaster {
+    /// warn once the filesystem fills up sooner than this, zero never warns
+    warn_below: Duration,
+    last: Mutex<Option<Sample>>,
+    warned: AtomicBool,
+}
+
+impl Forecaster {
+    pub(crate) fn new(warn_below: Duration) -> Self {
+        Self {
+            warn_below,
+            last: Mutex::default(),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// fold in the space left on the filesystem of `root` after a cycle that freed `freed` bytes
+    pub(crate) fn sample(
+        &self,
+        root: &Path,
+        space: DiskSpace,
+        freed: u64,
+        now: SystemTime,
+    ) -> CapacityForecast {
+        let used = space.used();
+        let mut last = self.last.lock().unwrap();
+        let rates = match last.as_ref() {
+            Some(prev) => match now.duration_since(prev.at) {
+                Ok(elapsed) if !elapsed.is_zero() => {
+                    let secs = elapsed.as_secs_f64();
+                    let written = (used + freed).saturating_sub(prev.used);
+                    let sample = (written as f64 / secs, freed as f64 / secs);
+                    Some(match prev.rates {
+                        Some((growth, reclaim)) => {
+                            let weight = 1.0 - (-secs / SMOOTHING.as_secs_f64()).exp();
+                            (
+                                growth + weight * (sample.0 - growth),
+                                reclaim + weight * (sample.1 - reclaim),
+                            )
+                        }
+                        None => sample,
+                    })
+                }
+                // the clock went backwards or no time passed, keep the previous estimate
+                _ => prev.rates,
+            },
+            None => None,
+        };
+        *last = Some(Sample {
+            at: now,
+            used,
+            rates,
+        });
+        drop(last);
+        let (growth_rate, reclaim_rate) = rates.unwrap_or_default();
+        let net = growth_rate - reclaim_rate;
+        let time_to_full = match net > 0.0 {
+            true => Duration::try_from_secs_f64(space.available as f64 / net).ok(),
+            false => None,
+        };
+        let forecast = CapacityForecast {
+            total_bytes: space.total,
+            available_bytes: space.available,
+            growth_rate,
+            reclaim_rate,
+            time_to_full,
+        };
+        self.warn(root, &forecast);
+        forecast
+    }
+
+    /// warn when the estimate drops below the threshold, once until it recovers
+    fn warn(&self, root: &Path, forecast: &CapacityForecast) {
+        let low = forecast.time_to_full.filter(|t| *t < self.warn_below);
+        match (low, self.warned.swap(low.is_some(), Ordering::Relaxed)) {
+            (Some(left), false) => tracing::warn!(
+                "filesystem of {} full in about {} at {:.0} B/s written and {:.0} B/s freed, {} \
+                 bytes available",
+                root.display(),
+                humantime::format_duration(Duration::from_secs(left.as_secs())),
+                forecast.growth_rate,
+                forecast.reclaim_rate,
+                forecast.available_bytes
+            ),
+            (None, true) => {
+                tracing::info!("filesystem of {} no longer filling up fast", root.display())
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,1816 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio_util::sync::CancellationToken;
+use tracing::instrument;
+
+use crate::{
+    analyze::{self, Analysis},
+    archive::{self, ArchiveVerification},
+    backoff::Backoff,
+    boundary::Boundaries,
+    capacity::Forecaster,
+    control::Control,
+    dedup::LogDedup,
+    hook::{ApprovalHook, ApprovalRequest, CycleHook},
+    lifecycle::{self, Lifecycle, LifecycleStore, MemoryLifecycle},
+    lock::{self, DistributedLock},
+    observer::Observers,
+    playlist::Playlist,
+    purge::{self, PurgeReport},
+    remote::{self, RemoteStore},
+    report::error_chain,
+    segment::{self, list_segments, parse_segment_os, playlist_path_for},
+    stats::{self, StreamStats},
+    storage::{FileMeta, FsStore, LockGuard, Storage, TimeSource},
+    stuck::{self, StuckStream},
+    trash::{self, GcReport, RestoreReport},
+    validate::{self, Validation},
+    vod, Action, CleanReport, CleanerError, CleanerObserver, Clock, PlannedAction, Policy, Reason,
+    Result, SegmentInfo, ShadowReport, Shard, SkipReason, SystemClock, DEFAULT_ROOT,
+};
+
+/// name of the single instance lock file inside the root, sharded cleaners lock
+/// `.hls-cleaner.<index>-of-<count>.lock` so that the shards of a root run side by side
+pub const LOCK_FILE: &str = ".hls-cleaner.lock";
+
+/// a file of this name in a directory, or `<stream>.nodelete` next to a playlist, keeps every
+/// fragment of the streams it covers until it is removed
+pub const NODELETE_MARKER: &str = ".nodelete";
+
+/// builder for [`Cleaner`], obtained from [`Cleaner::builder`]
+#[derive(Clone)]
+pub struct CleanerBuilder {
+    root: PathBuf,
+    interval: Duration,
+    policy: Policy,
+    observers: Observers,
+    storage: Arc<dyn Storage>,
+    clock: Arc<dyn Clock>,
+    remote: Option<Arc<dyn RemoteStore>>,
+    lock: Option<Arc<dyn DistributedLock>>,
+    max_failed_cycles: Option<u32>,
+    max_deletions_per_cycle: Option<usize>,
+    shadow: Option<Policy>,
+    lifecycle: Arc<dyn LifecycleStore>,
+    log_dedup_window: Duration,
+    time_to_full_warning: Duration,
+    control: Option<Arc<Control>>,
+    approval: Option<Arc<dyn ApprovalHook>>,
+    cycle_hook: Option<Arc<dyn CycleHook>>,
+    cancel: CancellationToken,
+}
+
+impl Default for CleanerBuilder {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from(DEFAULT_ROOT),
+            interval: Duration::from_secs(15),
+            policy: Policy::default(),
+            observers: Observers::default(),
+            storage: Arc::new(FsStore),
+            clock: Arc::new(SystemClock),
+            remote: None,
+            lock: None,
+            max_failed_cycles: None,
+            max_deletions_per_cycle: None,
+            shadow: None,
+            lifecycle: Arc::new(MemoryLifecycle::default()),
+            log_dedup_window: Duration::from_secs(60),
+            time_to_full_warning: Duration::from_secs(3600),
+            control: None,
+            approval: None,
+            cycle_hook: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl CleanerBuilder {
+    /// directory containing the playlists and ts fragments
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// delay between two cleanup cycles in [`Cleaner::run`]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// also evaluate every fragment against this candidate policy without acting on it, the
+    /// disagreements end up in [`CleanReport::shadow`](crate::CleanReport::shadow)
+    pub fn shadow_policy(mut self, policy: Policy) -> Self {
+        self.shadow = Some(policy);
+        self
+    }
+
+    /// register an observer, may be called multiple times
+    pub fn observer(mut self, observer: Arc<dyn CleanerObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// backend used for every file access, the local filesystem by default
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// time source for fragment ages, the system clock by default
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// remember fragment lifecycles in this store, in memory by default, see
+    /// [`lifecycle`](crate::lifecycle)
+    pub fn lifecycle_store(mut self, store: Arc<dyn LifecycleStore>) -> Self {
+        self.lifecycle = store;
+        self
+    }
+
+    /// push archived content to this store after every cycle, see [`remote`](crate::remote)
+    pub fn remote(mut self, remote: Arc<dyn RemoteStore>) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// take this lock on the root before every cycle, see [`lock`](crate::lock)
+    pub fn distributed_lock(mut self, lock: Arc<dyn DistributedLock>) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// give up once this many cycles in a row failed as a whole, [`Cleaner::run`] then returns
+    /// [`CleanerError::FailedCycles`] instead of retrying forever
+    pub fn max_failed_cycles(mut self, cycles: u32) -> Self {
+        self.max_failed_cycles = Some(cycles);
+        self
+    }
+
+    /// act on at most this many fragments per cycle, the oldest first, so the backlog after an
+    /// outage is worked off over several cycles instead of hammering the origin at once
+    pub fn max_deletions_per_cycle(mut self, max: usize) -> Self {
+        self.max_deletions_per_cycle = Some(max);
+        self
+    }
+
+    /// log an error of a kind on a stream once per `window` and summarize the repeats, a
+    /// minute by default, zero logs every error
+    pub fn log_dedup_window(mut self, window: Duration) -> Self {
+        self.log_dedup_window = window;
+        self
+    }
+
+    /// warn once the filesystem of the root is estimated to fill up within `within`, an hour by
+    /// default, zero never warns. see [`CleanReport::capacity`](crate::CleanReport::capacity)
+    pub fn time_to_full_warning(mut self, within: Duration) -> Self {
+        self.time_to_full_warning = within;
+        self
+    }
+
+    /// let this handle pause and trigger the cycles of [`Cleaner::run`], it also observes them,
+    /// see [`control`](crate::control)
+    pub fn control(mut self, control: Arc<Control>) -> Self {
+        self.observers.push(control.clone());
+        self.control = Some(control);
+        self
+    }
+
+    /// ask this hook before acting on each fragment of a cycle, see [`hook`](crate::hook)
+    ///
+    /// [`Cleaner::apply`] does not ask, its plan is already vetted by the caller.
+    pub fn approval_hook(mut self, hook: Arc<dyn ApprovalHook>) -> Self {
+        self.approval = Some(hook);
+        self
+    }
+
+    /// hand the report of every cycle that ran to this hook once the observers saw it, see
+    /// [`hook`](crate::hook)
+    pub fn cycle_hook(mut self, hook: Arc<dyn CycleHook>) -> Self {
+        self.cycle_hook = Some(hook);
+        self
+    }
+
+    /// token the host uses to stop the cleaner, cancelling it makes [`Cleaner::run`] return
+    /// once the stream being processed is done
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    pub fn build(self) -> Cleaner {
+        Cleaner {
+            root: self.root,
+            interval: self.interval,
+            policy: self.policy,
+            observers: self.observers,
+            storage: self.storage,
+            clock: self.clock,
+            remote: self.remote,
+            lock: self.lock,
+            max_failed_cycles: self.max_failed_cycles,
+            max_deletions_per_cycle: self.max_deletions_per_cycle,
+            shadow: self.shadow,
+            lifecycle: self.lifecycle,
+            control: self.control,
+            approval: self.approval,
+            cycle_hook: self.cycle_hook,
+            cancel: self.cancel,
+            boundaries: Arc::default(),
+            backoff: Arc::default(),
+            protected: Arc::default(),
+            misnamed: Arc::default(),
+            skewed: Arc::default(),
+            log_dedup: Arc::new(LogDedup::new(self.log_dedup_window)),
+            capacity: Arc::new(Forecaster::new(self.time_to_full_warning)),
+            stuck: Arc::default(),
+            frozen: Arc::default(),
+        }
+    }
+}
+
+/// what to do with a single fragment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Delete(Reason),
+    Skip(SkipReason),
+}
+
+impl Decision {
+    /// the action taken under a policy acting with `action`, `None` when kept, and the reason
+    fn verdict(&self, action: Action) -> (Option<Action>, &'static str) {
+        match self {
+            Self::Delete(reason) => (Some(action), reason.name()),
+            Self::Skip(reason) => (None, reason.name()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Cleaner {
+    root: PathBuf,
+    interval: Duration,
+    policy: Policy,
+    observers: Observers,
+    storage: Arc<dyn Storage>,
+    clock: Arc<dyn Clock>,
+    remote: Option<Arc<dyn RemoteStore>>,
+    lock: Option<Arc<dyn DistributedLock>>,
+    max_failed_cycles: Option<u32>,
+    max_deletions_per_cycle: Option<usize>,
+    shadow: Option<Policy>,
+    lifecycle: Arc<dyn LifecycleStore>,
+    control: Option<Arc<Control>>,
+    approval: Option<Arc<dyn ApprovalHook>>,
+    cycle_hook: Option<Arc<dyn CycleHook>>,
+    cancel: CancellationToken,
+    boundaries: Arc<Boundaries>,
+    backoff: Arc<Backoff>,
+    /// protected fragments already reported, each is only logged once
+    protected: Arc<Reported>,
+    /// fragments ignored because their name is not utf-8 and cannot be parsed
+    misnamed: Arc<Reported>,
+    /// fragments with timestamps in the future
+    skewed: Arc<Reported>,
+    log_dedup: Arc<LogDedup>,
+    capacity: Arc<Forecaster>,
+    /// streams reported as stuck, warned about again once they recovered
+    stuck: Arc<Mutex<BTreeSet<String>>>,
+    /// markers found and the streams they froze, reported again once removed
+    frozen: Arc<Mutex<BTreeSet<(PathBuf, String)>>>,
+}
+
+/// fragments already warned about, a condition lasting for many cycles is logged once
+#[derive(Debug, Default)]
+struct Reported(Mutex<HashSet<PathBuf>>);
+
+impl Reported {
+    /// whether `path` is reported for the first time
+    fn first(&self, path: &Path) -> bool {
+        self.0.lock().unwrap().insert(path.to_owned())
+    }
+
+    /// the condition no longer holds for `path`, report it again should it come back
+    fn clear(&self, path: &Path) {
+        self.0.lock().unwrap().remove(path);
+    }
+}
+
+impl std::fmt::Debug for Cleaner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cleaner")
+            .field("root", &self.root)
+            .field("interval", &self.interval)
+            .field("policy", &self.policy)
+            .field("shadow_policy", &self.shadow)
+            .field("observers", &self.observers.len())
+            .field("remote", &self.remote.is_some())
+            .field("distributed_lock", &self.lock.is_some())
+            .field("cancelled", &self.cancel.is_cancelled())
+            .finish()
+    }
+}
+
+impl Cleaner {
+    pub fn builder() -> CleanerBuilder {
+        CleanerBuilder::default()
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// disk usage of the root per stream, nothing is modified
+    pub fn analyze(&self) -> Result<Analysis> {
+        analyze::analyze(&*self.storage, &self.root, &self.policy.playlist_template)
+    }
+
+    /// current segment counts and playlist windows per stream, nothing is modified
+    pub fn stream_stats(&self) -> Result<Vec<StreamStats>> {
+        stats::stream_stats(&*self.storage, &self.root, &self.policy.playlist_template)
+    }
+
+    /// remove every artifact of `stream` right away, ignoring the policy
+    ///
+    /// fragments, init segments, the playlist, keys, thumbnails and a `<root>/<stream>`
+    /// directory are all removed, a playlist shared by the root only once no other stream has
+    /// fragments next to it. with `dry_run` nothing is touched and the report lists what would go.
+    /// artifacts the policy would never remove, on another filesystem or with a foreign owner,
+    /// are reported as errors and left in place.
+    pub fn purge_stream(&self, stream: &str, dry_run: bool) -> Result<PurgeReport> {
+        purge::purge(
+            &*self.storage,
+            &self.root,
+            stream,
+            dry_run,
+            self.root_device(),
+            &self.policy,
+        )
+    }
+
+    /// check playlists against the fragments on disk, nothing is modified
+    pub fn validate(&self) -> Result<Validation> {
+        validate::validate(&*self.storage, &self.root, &self.policy.playlist_template)
+    }
+
+    /// move trashed fragments of `stream` back into the root and copy archived ones back, opened
+    /// with the policy archive key and decompressed, only the ones trashed or archived at or
+    /// after `since` when given
+    pub fn restore(&self, stream: &str, since: Option<SystemTime>) -> Result<RestoreReport> {
+        let mut report = trash::restore(&*self.storage, &self.root, stream, since)?;
+        let archived = archive::restore(
+            &*self.storage,
+            &archive::archive_root(&self.policy.archive_template, &self.root)?,
+            stream,
+            self.policy.archive_key.as_ref(),
+            since,
+        )?;
+        report.restored.extend(archived.restored);
+        report.conflicts.extend(archived.conflicts);
+        report.errors.extend(archived.errors);
+        Ok(report)
+    }
+
+    /// purge trashed fragments older than the policy trash retention and remove empty trash
+    /// directories
+    pub fn gc(&self) -> Result<GcReport> {
+        trash::gc(
+            &*self.storage,
+            &self.root,
+            self.policy.trash_retention,
+            self.clock.now(),
+        )
+    }
+
+    /// re-check the archive manifests below the fixed prefix of the policy archive template,
+    /// sealed fragments are opened with the policy archive key
+    pub fn verify_archive(&self) -> Result<ArchiveVerification> {
+        archive::verify(
+            &*self.storage,
+            &archive::archive_root(&self.policy.archive_template, &self.root)?,
+            self.policy.archive_key.as_ref(),
+        )
+    }
+
+    /// take the single instance lock of the root, [`LOCK_FILE`] inside it
+    ///
+    /// the lock is advisory and released when the guard is dropped, it fails with
+    /// [`CleanerError::Locked`] while another cleaner holds it.
+    pub fn lock(&self) -> Result<LockGuard> {
+        let path = self.root.join(lock_name(self.policy.shard));
+        match self.storage.try_lock(&path)? {
+            Some(guard) => Ok(guard),
+            None => Err(CleanerError::Locked {
+                holder: self
+                    .storage
+                    .read_to_string(&path)
+                    .ok()
+                    .and_then(|pid| pid.trim().parse().ok()),
+                path,
+            }),
+        }
+    }
+
+    /// run a cleanup cycle every interval until the cancellation token fires
+    ///
+    /// with a [`Control`] the scheduled cycles are skipped while the root is paused and a
+    /// triggered one starts right away. the root is locked for the whole run, see
+    /// [`Cleaner::lock`]. a cycle fails as a whole
+    /// when the root cannot be listed, errors on single fragments do not count towards
+    /// [`CleanerBuilder::max_failed_cycles`].
+    pub async fn run(&self) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut failed = 0;
+        loop {
+            tokio::select! {
+                _ = self.cancel.cancelled() => {
+                    tracing::info!("cleaner cancelled, exiting");
+                    self.observers.flush();
+                    return Ok(());
+                }
+                _ = interval.tick() => {
+                    if self.control.as_ref().is_some_and(|c| c.is_paused(&self.root)) {
+                        tracing::trace!("{} is paused", self.root.display());
+                        continue;
+                    }
+                }
+                _ = self.triggered() => {
+                    tracing::info!("cycle of {} triggered", self.root.display());
+                    interval.reset();
+                }
+            }
+            tracing::trace!("launching task");
+            match self.clean_once().await {
+                Ok(report) => {
+                    failed = 0;
+                    tracing::debug!(
+                        "cycle done, {} deleted, {} bytes freed, {} errors",
+                        report.deleted,
+                        report.bytes_freed,
+                        report.errors.len()
+                    )
+                }
+                Err(e) => {
+                    tracing::error!("{}", error_chain(&e));
+                    failed += 1;
+                    if self.max_failed_cycles.is_some_and(|max| failed >= max) {
+                        self.observers.flush();
+                        return Err(CleanerError::FailedCycles {
+                            cycles: failed,
+                            last: Box::new(e),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// run a single cleanup cycle over the root directory, [`Cleaner::plan`] then
+    /// [`Cleaner::apply`]
+    ///
+    /// errors on individual fragments are reported to the observers and collected in the report,
+    /// they do not abort the cycle. once cancelled the plan stops early and the stream being
+    /// applied is finished but no other, nothing is uploaded.
+    ///
+    /// with a [`DistributedLock`] the cycle is skipped while another replica holds the lock of
+    /// the root, and stops early once a renewal fails.
+    #[instrument(level = "trace", skip(self), fields(root = %self.root.display()))]
+    pub async fn clean_once(&self) -> Result<CleanReport> {
+        let started = Instant::now();
+        let mut report = CleanReport::new(&self.root, self.clock.now());
+        let cycle = self.cancel.child_token();
+        let key = match self.policy.shard {
+            Some(shard) => format!("{}#{}", self.root.display(), shard),
+            None => self.root.display().to_string(),
+        };
+        let renewal = match &self.lock {
+            Some(lock) if !lock.acquire(&key).await? => {
+                tracing::debug!("another replica holds the lock of {}", self.root.display());
+                report.lock_contended = true;
+                report.duration = started.elapsed();
+                self.observers.on_cycle_end(&report);
+                return Ok(report);
+            }
+            Some(lock) => {
+                let (lock, key, cycle) = (lock.clone(), key.clone(), cycle.clone());
+                Some(tokio::spawn(async move {
+                    lock::hold(&*lock, &key, &cycle).await
+                }))
+            }
+            None => None,
+        };
+        let result = self.cycle(&mut report, &cycle, started).await;
+        self.log_dedup.flush();
+        if let (Some(renewal), Some(lock)) = (renewal, &self.lock) {
+            renewal.abort();
+            if let Err(e) = lock.release(&key).await {
+                tracing::warn!(
+                    "unable to release the lock of {} - {}",
+                    self.root.display(),
+                    e
+                );
+            }
+        }
+        result?;
+        report.duration = started.elapsed();
+        report.apply_duration = report.duration - report.plan_duration;
+        report.cancelled = self.cancel.is_cancelled();
+        report.lock_lost = cycle.is_cancelled() && !report.cancelled;
+        match self.storage.disk_space(&self.root) {
+            Ok(space) => {
+                report.capacity = space.map(|space| {
+                    let now = self.clock.now();
+                    self.capacity
+                        .sample(&self.root, space, report.bytes_freed, now)
+                })
+            }
+            Err(e) => tracing::debug!("no capacity forecast - {}", e),
+        }
+        if let Some(shadow) = report.shadow.as_ref().filter(|s| s.disagreements() > 0) {
+            tracing::info!(
+                "shadow policy disagrees on {} of {} fragments, {} more removed, {} more kept, \
+                 {} with another action",
+                shadow.disagreements(),
+                shadow.evaluated,
+                shadow.would_remove,
+                shadow.would_keep,
+                shadow.other_action
+            );
+        }
+        self.observers.on_cycle_end(&report);
+        if let Some(hook) = &self.cycle_hook {
+            if let Err(e) = hook.after_cycle(&report).await {
+                self.log_dedup
+                    .warn(None, &e, format_args!("{}", error_chain(&e)));
+            }
+        }
+        Ok(report)
+    }
+
+    async fn cycle(
+        &self,
+        report: &mut CleanReport,
+        cancel: &CancellationToken,
+        started: Instant,
+    ) -> Result<()> {
+        let plan = self.plan_into(report, cancel)?;
+        report.plan_duration = started.elapsed();
+        let plan = self.cap(plan, report);
+        let plan = match &self.approval {
+            Some(hook) => self.approve(&**hook, plan, report, cancel).await,
+            None => plan,
+        };
+        self.apply_into(plan, report, cancel);
+        // uploads are retried by the next cycle, not worth delaying a shutdown or racing the
+        // replica that took the lock over
+        if let (Some(remote), false) = (&self.remote, cancel.is_cancelled()) {
+            self.upload(&**remote, report).await;
+        }
+        Ok(())
+    }
+
+    /// compute what a cycle would do without touching any file
+    ///
+    /// kept fragments are reported through [`CleanerObserver::on_skip`] and evaluation errors
+    /// through [`CleanerObserver::on_error`], only the actions are returned.
+    pub fn plan(&self) -> Result<Vec<PlannedAction>> {
+        self.plan_into(
+            &mut CleanReport::new(&self.root, self.clock.now()),
+            &self.cancel,
+        )
+    }
+
+    /// execute a plan, usually one returned by [`Cleaner::plan`] with vetoed actions removed
+    ///
+    /// the returned report only covers the applied actions, it does not count skipped fragments.
+    pub fn apply(&self, plan: Vec<PlannedAction>) -> CleanReport {
+        let started = Instant::now();
+        let mut report = CleanReport::new(&self.root, self.clock.now());
+        let plan = self.cap(plan, &mut report);
+        self.apply_into(plan, &mut report, &self.cancel);
+        report.apply_duration = started.elapsed();
+        report.duration = report.apply_duration;
+        report.cancelled = self.cancel.is_cancelled();
+        report
+    }
+
+    fn plan_into(
+        &self,
+        report: &mut CleanReport,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PlannedAction>> {
+        let current_time = self.clock.now();
+        let mut plan = Vec::new();
+        // whether the streams seen so far are left alone this cycle, see `backoff`
+        let mut skipped = HashMap::<String, bool>::new();
+        let root_device = self.root_device();
+        let mut other_device = 0;
+        if self.shadow.is_some() {
+            report.shadow = Some(ShadowReport::default());
+        }
+        let snapshot = segment::snapshot(&*self.storage, &self.root)?;
+        let stuck = self.stuck_streams(&snapshot, current_time);
+        report.stuck = stuck.values().cloned().collect();
+        let frozen = self.frozen_streams(&snapshot);
+        for (ts_path, listed) in snapshot {
+            if cancel.is_cancelled() {
+                tracing::debug!("cancelled, stopping plan early");
+                break;
+            }
+            let stream = parse_segment_os(&ts_path)
+                .ok()
+                .map(|(s, _)| s.to_string_lossy());
+            let stream = stream.as_deref();
+            if !self.policy.owns(stream) {
+                continue;
+            }
+            if let Some(stream) = stream {
+                let skip = match skipped.get(stream) {
+                    Some(&skip) => skip,
+                    None => {
+                        let skip = self.backoff.skips(stream);
+                        skipped.insert(stream.to_owned(), skip);
+                        skip
+                    }
+                };
+                if skip {
+                    continue;
+                }
+            }
+            tracing::debug!("processing {}", ts_path.display());
+            report.scanned += 1;
+            let evaluated =
+                self.evaluate(&ts_path, listed, root_device, &stuck, &frozen, current_time);
+            if let (Ok((segment, active, Some(shadow))), Some(candidate)) =
+                (&evaluated, &self.shadow)
+            {
+                report.shadow.get_or_insert_default().record(
+                    segment,
+                    active.verdict(self.policy.action),
+                    shadow.verdict(candidate.action),
+                );
+            }
+            match evaluated.map(|(segment, decision, _)| (segment, decision)) {
+                Ok((segment, Decision::Delete(reason))) => plan.push(PlannedAction {
+                    segment,
+                    action: self.policy.action,
+                    reason,
+                }),
+                Ok((segment, Decision::Skip(reason))) => {
+                    if reason == SkipReason::OtherDevice {
+                        other_device += 1;
+                    }
+                    report.record_skip(&segment);
+                    self.observers.on_skip(&segment, reason);
+                }
+                Err(e) => {
+                    // the other fragments of the stream would most likely fail the same way
+                    match stream {
+                        Some(stream) => {
+                            skipped.insert(stream.to_owned(), true);
+                            match self.backoff.failed(stream) {
+                                0 => self.log_dedup.warn(
+                                    Some(stream),
+                                    &e,
+                                    format_args!("{}", error_chain(&e)),
+                                ),
+                                cycles => self.log_dedup.warn(
+                                    Some(stream),
+                                    &e,
+                                    format_args!(
+                                        "{}, skipping {} for the next {} cycles",
+                                        error_chain(&e),
+                                        stream,
+                                        cycles
+                                    ),
+                                ),
+                            }
+                        }
+                        // renaming it is the only fix, one warning is enough
+                        None if ts_path.file_name().and_then(|n| n.to_str()).is_none() => {
+                            if self.misnamed.first(&ts_path) {
+                                tracing::warn!("{}, ignoring it", error_chain(&e));
+                            }
+                            continue;
+                        }
+                        None => self
+                            .log_dedup
+                            .warn(None, &e, format_args!("{}", error_chain(&e))),
+                    }
+                    report.record_error(stream, &e);
+                    self.observers.on_error(stream, &e);
+                }
+            }
+        }
+        for (stream, _) in skipped.iter().filter(|(_, skip)| !**skip) {
+            self.backoff.recovered(stream);
+        }
+        if other_device > 0 {
+            tracing::warn!(
+                "left {} fragments on other filesystems than {} alone",
+                other_device,
+                self.root.display()
+            );
+        }
+        let stale = current_time
+            .checked_sub(lifecycle::COMPACT_AFTER)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        match self.lifecycle.compact(stale) {
+            Ok(0) => {}
+            Ok(n) => tracing::debug!(
+                "compacted {} vanished fragments from the lifecycle store",
+                n
+            ),
+            Err(e) => tracing::warn!("{}", error_chain(&e)),
+        }
+        self.flush_lifecycle();
+        Ok(plan)
+    }
+
+    fn apply_into(
+        &self,
+        plan: Vec<PlannedAction>,
+        report: &mut CleanReport,
+        cancel: &CancellationToken,
+    ) {
+        let current_time = self.clock.now();
+        let root_device = self.root_device();
+        let mut archived = BTreeSet::new();
+        let mut plan = match &self.policy.stitch_template {
+            Some(template) => self.stitch_finished(plan, template, current_time, report),
+            None => plan,
+        };
+        // stream by stream so a cancelled cycle never leaves a stream half removed
+        plan.sort_by(|a, b| {
+            (&a.segment.stream, a.segment.sequence).cmp(&(&b.segment.stream, b.segment.sequence))
+        });
+        let mut in_flight = None;
+        for planned in plan {
+            if in_flight.as_ref() != Some(&planned.segment.stream) {
+                if cancel.is_cancelled() {
+                    tracing::debug!("cancelled, stopping apply early");
+                    break;
+                }
+                in_flight = Some(planned.segment.stream.clone());
+            }
+            tracing::trace!(
+                "{:?} {} - {:?}",
+                planned.action,
+                planned.segment.path.display(),
+                planned.reason
+            );
+            // plans may come from elsewhere, the fragment is checked again right before acting
+            let outcome = self.check_target(&planned.segment.path, root_device);
+            let outcome = outcome.and_then(|()| match planned.action {
+                Action::Delete => self.storage.remove(&planned.segment.path),
+                Action::Trash => trash::trash_path(&self.root, &planned.segment, current_time)
+                    .and_then(|to| self.storage.rename(&planned.segment.path, &to)),
+                Action::Archive => archive::archive_path(
+                    &self.policy.archive_template,
+                    &self.root,
+                    &planned.segment,
+                    current_time,
+                )
+                .and_then(|to| {
+                    let to = archive::archive(
+                        &*self.storage,
+                        &planned.segment,
+                        &to,
+                        self.policy.archive_compression,
+                        self.policy.archive_key.as_ref(),
+                        current_time,
+                        &self.observers,
+                    )?;
+                    let vod = self.policy.vod_playlists
+                        && self.policy.archive_compression.is_none()
+                        && self.policy.archive_key.is_none();
+                    if let (true, Some(dir)) = (vod, to.parent()) {
+                        archived.insert((dir.to_owned(), planned.segment.stream.clone()));
+                    }
+                    Ok(())
+                }),
+            });
+            match outcome {
+                Ok(()) => {
+                    self.boundaries
+                        .forget(&planned.segment.stream, planned.segment.sequence);
+                    if let Err(e) = self.lifecycle.forget(&planned.segment.path) {
+                        tracing::warn!("{}", error_chain(&e));
+                    }
+                    self.skewed.clear(&planned.segment.path);
+                    report.record_delete(&planned.segment);
+                    self.observers.on_delete(&planned.segment, planned.reason);
+                }
+                Err(e) => {
+                    let stream = Some(planned.segment.stream.as_str());
+                    self.log_dedup
+                        .warn(stream, &e, format_args!("{}", error_chain(&e)));
+                    report.record_error(stream, &e);
+                    self.observers.on_error(stream, &e);
+                }
+            }
+        }
+        for (dir, stream) in archived {
+            let live_path = self.root.join(segment::playlist_name(
+                &stream,
+                &self.policy.playlist_template,
+            ));
+            let live = Playlist::read(&*self.storage, &live_path).ok();
+            if let Err(e) = vod::write_playlist(&*self.storage, &dir, &stream, live.as_ref()) {
+                self.log_dedup
+                    .warn(Some(&stream), &e, format_args!("{}", error_chain(&e)));
+                report.record_error(Some(&stream), &e);
+                self.observers.on_error(Some(&stream), &e);
+            }
+        }
+        self.flush_lifecycle();
+    }
+
+    /// the part of `plan` the approval hook lets go ahead
+    ///
+    /// vetoed fragments are reported as skipped. once the hook fails the rest of the plan is
+    /// held back without asking, the next cycle tries again.
+    async fn approve(
+        &self,
+        hook: &dyn ApprovalHook,
+        plan: Vec<PlannedAction>,
+        report: &mut CleanReport,
+        cancel: &CancellationToken,
+    ) -> Vec<PlannedAction> {
+        let mut approved = Vec::with_capacity(plan.len());
+        let mut failed = false;
+        for planned in plan {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let verdict = match failed {
+                true => false,
+                false => match hook
+                    .approve(&ApprovalRequest::new(&self.root, &planned))
+                    .await
+                {
+                    Ok(verdict) => verdict,
+                    Err(e) => {
+                        let stream = Some(planned.segment.stream.as_str());
+                        self.log_dedup
+                            .warn(stream, &e, format_args!("{}", error_chain(&e)));
+                        report.record_error(stream, &e);
+                        self.observers.on_error(stream, &e);
+                        failed = true;
+                        false
+                    }
+                },
+            };
+            match verdict {
+                true => approved.push(planned),
+                false => {
+                    tracing::debug!(
+                        "{} vetoed by the approval hook",
+                        planned.segment.path.display()
+                    );
+                    report.record_skip(&planned.segment);
+                    self.observers.on_skip(&planned.segment, SkipReason::Vetoed);
+                }
+            }
+        }
+        approved
+    }
+
+    /// the part of `plan` the deletion cap allows this cycle, the oldest fragments first
+    ///
+    /// the rest stays on disk and is planned again on the next cycles. streams that are
+    /// stitched are never cut, the cap is exceeded by the rest of the last one instead.
+    fn cap(&self, mut plan: Vec<PlannedAction>, report: &mut CleanReport) -> Vec<PlannedAction> {
+        let Some(max) = self.max_deletions_per_cycle.filter(|&max| plan.len() > max) else {
+            return plan;
+        };
+        plan.sort_by_key(|p| p.segment.modified);
+        let mut deferred = plan.split_off(max);
+        if self.policy.stitch_template.is_some() {
+            let cut = plan
+                .iter()
+                .map(|p| p.segment.stream.clone())
+                .collect::<HashSet<_>>();
+            let (rest, later): (Vec<_>, Vec<_>) = deferred
+                .into_iter()
+                .partition(|p| cut.contains(&p.segment.stream));
+            plan.extend(rest);
+            deferred = later;
+        }
+        if !deferred.is_empty() {
+            tracing::info!(
+                "deferring {} removals to the next cycles, at most {} per cycle",
+                deferred.len(),
+                max
+            );
+        }
+        report.deferred = deferred.len();
+        plan
+    }
+
+    fn flush_lifecycle(&self) {
+        if let Err(e) = self.lifecycle.flush() {
+            tracing::warn!("{}", error_chain(&e));
+        }
+    }
+
+    /// push the archive and stitch directories to the remote store
+    async fn upload(&self, remote: &dyn RemoteStore, report: &mut CleanReport) {
+        let now = self.clock.now();
+        let templates = std::iter::once(&self.policy.archive_template)
+            .chain(self.policy.stitch_template.as_ref());
+        for template in templates {
+            // a template without a fixed prefix would upload and remove the live fragments
+            let base = match archive::archive_root(template, &self.root) {
+                Ok(base) => base,
+                Err(e) => {
+                    tracing::warn!("{}, not uploading it", error_chain(&e));
+                    report.record_error(None, &e);
+                    continue;
+                }
+            };
+            remote::upload_dir(
+                &*self.storage,
+                remote,
+                &base,
+                &self.policy,
+                now,
+                &self.observers,
+                report,
+            )
+            .await;
+        }
+    }
+
+    /// stitch every stream whose remaining fragments are all planned as orphans
+    ///
+    /// orphans of a stream that still has younger fragments are held back until the whole
+    /// stream expires, and the fragments of a stream that failed to stitch are dropped from
+    /// the plan so they are retried on the next cycle.
+    fn stitch_finished(
+        &self,
+        plan: Vec<PlannedAction>,
+        template: &str,
+        current_time: SystemTime,
+        report: &mut CleanReport,
+    ) -> Vec<PlannedAction> {
+        let mut streams = BTreeMap::<&str, Vec<&SegmentInfo>>::new();
+        for planned in &plan {
+            if let Reason::Orphaned { .. } = planned.reason {
+                streams
+                    .entry(&planned.segment.stream)
+                    .or_default()
+                    .push(&planned.segment);
+            }
+        }
+        let on_disk = match list_segments(&*self.storage, &self.root) {
+            Ok(paths) => paths,
+            Err(e) => {
+                tracing::warn!("{}", error_chain(&e));
+                report.record_error(None, &e);
+                self.observers.on_error(None, &e);
+                return Vec::new();
+            }
+        };
+        let mut held = BTreeSet::new();
+        for (stream, mut segments) in streams {
+            let remaining = on_disk
+                .iter()
+                .filter(
+                    |p| matches!(parse_segment_os(p), Ok((s, _)) if s.to_string_lossy() == stream),
+                )
+                .count();
+            if remaining != segments.len() {
+                held.insert(stream.to_owned());
+                continue;
+            }
+            segments.sort_by_key(|s| s.sequence);
+            match vod::stitch(
+                &*self.storage,
+                &self.root,
+                template,
+                &segments,
+                current_time,
+                &self.observers,
+            ) {
+                Ok(path) => tracing::info!("stitched {} into {}", stream, path.display()),
+                Err(e) => {
+                    self.log_dedup
+                        .warn(Some(stream), &e, format_args!("{}", error_chain(&e)));
+                    report.record_error(Some(stream), &e);
+                    self.observers.on_error(Some(stream), &e);
+                    held.insert(stream.to_owned());
+                }
+            }
+        }
+        plan.into_iter()
+            .filter(|p| !held.contains(&p.segment.stream))
+            .collect()
+    }
+
+    /// streams of the snapshot whose playlist stopped updating, warned about once until they
+    /// recover
+    fn stuck_streams(
+        &self,
+        snapshot: &[(PathBuf, Option<SystemTime>)],
+        now: SystemTime,
+    ) -> BTreeMap<String, StuckStream> {
+        if self.policy.stuck_after == 0 {
+            return BTreeMap::new();
+        }
+        let stuck = stuck::detect(
+            &*self.storage,
+            snapshot
+                .iter()
+                .map(|(path, listed)| (path.as_path(), *listed)),
+            self.policy.stuck_after,
+            &self.policy.playlist_template,
+            |stream| self.policy.owns(Some(stream)),
+            now,
+        );
+        let mut reported = self.stuck.lock().unwrap();
+        for s in stuck.values().filter(|s| !reported.contains(&s.stream)) {
+            let since = now.duration_since(s.playlist_updated).unwrap_or_default();
+            tracing::warn!(
+                "{} looks stuck, {} was last updated {}s ago while fragments keep arriving, \
+                 keeping the ones written since",
+                s.stream,
+                s.playlist.display(),
+                since.as_secs()
+            );
+        }
+        for stream in reported.iter().filter(|s| !stuck.contains_key(*s)) {
+            tracing::info!("{} is no longer stuck", stream);
+        }
+        *reported = stuck.keys().cloned().collect();
+        stuck
+    }
+
+    /// playlists of the snapshot whose stream a [`NODELETE_MARKER`] freezes, each marker is
+    /// logged once until it is removed
+    fn frozen_streams(&self, snapshot: &[(PathBuf, Option<SystemTime>)]) -> HashSet<PathBuf> {
+        let mut markers = BTreeSet::new();
+        let mut frozen = HashSet::new();
+        let mut checked = HashSet::new();
+        for (path, _) in snapshot {
+            let Ok((stream, _)) = parse_segment_os(path) else {
+                continue;
+            };
+            let (Ok(playlist), Some(dir)) = (
+                playlist_path_for(path, stream, &self.policy.playlist_template),
+                path.parent(),
+            ) else {
+                continue;
+            };
+            if !checked.insert(playlist.clone()) {
+                continue;
+            }
+            let mut name = stream.to_owned();
+            name.push(NODELETE_MARKER);
+            let marker = [dir.join(name), dir.join(NODELETE_MARKER)]
+                .into_iter()
+                .find(|marker| self.storage.exists(marker));
+            if let Some(marker) = marker {
+                markers.insert((marker, stream.to_string_lossy().into_owned()));
+                frozen.insert(playlist);
+            }
+        }
+        let mut reported = self.frozen.lock().unwrap();
+        for (marker, stream) in markers.difference(&reported) {
+            tracing::info!(
+                "{} exists, keeping every fragment of {}",
+                marker.display(),
+                stream
+            );
+        }
+        for (marker, stream) in reported.difference(&markers) {
+            tracing::info!(
+                "{} was removed, cleaning {} again",
+                marker.display(),
+                stream
+            );
+        }
+        *reported = markers;
+        frozen
+    }
+
+    /// resolves once the control triggers a cycle of the root, never without one
+    async fn triggered(&self) {
+        match &self.control {
+            Some(control) => control.triggered(&self.root).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// filesystem of the root fragments have to be on, `None` when the policy crosses devices
+    /// or the storage cannot tell, a missing root fails the listing soon enough
+    fn root_device(&self) -> Option<u64> {
+        match self.policy.cross_devices {
+            true => None,
+            false => self.storage.metadata(&self.root).ok()?.device,
+        }
+    }
+
+    /// fail when `path` is protected, foreign or on another filesystem than `root_device`
+    fn check_target(&self, path: &Path, root_device: Option<u64>) -> Result<()> {
+        let metadata = self.storage.metadata(path)?;
+        let path = path.to_owned();
+        match metadata {
+            m if m.is_on_other_device(root_device) => Err(CleanerError::OtherDevice { path }),
+            m if m.protected => Err(CleanerError::Protected { path }),
+            m if !self.policy.ownership.allows(&m) => Err(CleanerError::Foreign { path }),
+            _ => Ok(()),
+        }
+    }
+
+    /// whether the fragment at `path` is `protected`, logging it the first time
+    fn note_protected(&self, path: &Path, protected: bool) -> bool {
+        match protected {
+            true if self.protected.first(path) => tracing::warn!(
+                "{} is immutable or append-only, leaving it in place",
+                path.display()
+            ),
+            true => {}
+            false => self.protected.clear(path),
+        }
+        protected
+    }
+
+    /// the time the orphan at `path` ages from, the time it was first seen when its own
+    /// timestamp is further in the future than the policy clock skew tolerance
+    fn orphan_aged_from(
+        &self,
+        path: &Path,
+        metadata: &FileMeta,
+        lifecycle: &Lifecycle,
+        current_time: SystemTime,
+    ) -> Result<(SystemTime, TimeSource)> {
+        let (aged_from, source) = metadata.aged_from.ok_or_else(|| {
+            CleanerError::io(
+                path,
+                io::Error::new(io::ErrorKind::Unsupported, "file times unavailable"),
+            )
+        })?;
+        match aged_from.duration_since(current_time) {
+            Ok(ahead) if ahead > self.policy.clock_skew_tolerance => {
+                if self.skewed.first(path) {
+                    tracing::warn!(
+                        "{} has its {} {}s in the future, aging it from when it was first seen",
+                        path.display(),
+                        source,
+                        ahead.as_secs()
+                    );
+                }
+                Ok((lifecycle.first_seen, TimeSource::FirstSeen))
+            }
+            _ => {
+                self.skewed.clear(path);
+                Ok((aged_from, source))
+            }
+        }
+    }
+
+    /// decide the fate of the fragment at `path` without touching it, under the active policy
+    /// and the shadow policy, `listed` is its modification time in the cycle snapshot
+    fn evaluate(
+        &self,
+        path: &Path,
+        listed: Option<SystemTime>,
+        root_device: Option<u64>,
+        stuck: &BTreeMap<String, StuckStream>,
+        frozen: &HashSet<PathBuf>,
+        current_time: SystemTime,
+    ) -> Result<(SegmentInfo, Decision, Option<Decision>)> {
+        let (stream_os, sequence_num) = parse_segment_os(path)?;
+        let playlist_path = playlist_path_for(path, stream_os, &self.policy.playlist_template)?;
+        let stream_base_name = &*stream_os.to_string_lossy();
+        let metadata = self.storage.metadata(path)?;
+        let skip = if frozen.contains(&playlist_path) {
+            Some(SkipReason::Frozen)
+        } else if metadata.modified != listed {
+            tracing::debug!("{} changed since the cycle started", path.display());
+            Some(SkipReason::Changed)
+        } else if metadata.is_on_other_device(root_device) {
+            tracing::debug!("{} is on another filesystem", path.display());
+            Some(SkipReason::OtherDevice)
+        } else if self.note_protected(path, metadata.protected) {
+            Some(SkipReason::Protected)
+        } else if !self.policy.ownership.allows(&metadata) {
+            tracing::debug!(
+                "{} has an owner or permissions the policy does not allow",
+                path.display()
+            );
+            Some(SkipReason::Foreign)
+        } else {
+            None
+        };
+        if let Some(skip) = skip {
+            let segment = SegmentInfo {
+                path: path.to_owned(),
+                stream: stream_base_name.to_owned(),
+                sequence: sequence_num,
+                size: metadata.size,
+                modified: metadata.modified,
+                date_range: None,
+            };
+            let skip = Decision::Skip(skip);
+            return Ok((segment, skip, self.shadow.as_ref().map(|_| skip)));
+        }
+        let playlist = match self.storage.exists(&playlist_path) {
+            true => {
+                tracing::trace!("playlist {} exist", playlist_path.display());
+                let playlist = Playlist::read(&*self.storage, &playlist_path)?;
+                self.boundaries
+                    .observe(stream_base_name, &playlist, current_time);
+                Some(playlist)
+            }
+            false => None,
+        };
+        let segment = SegmentInfo {
+            path: path.to_owned(),
+            stream: stream_base_name.to_owned(),
+            sequence: sequence_num,
+            size: metadata.size,
+            modified: metadata.modified,
+            date_range: self.boundaries.tag(stream_base_name, sequence_num),
+        };
+        let min_sequence = playlist.as_ref().map(Playlist::min_sequence).transpose()?;
+        let lifecycle = self.lifecycle.observe(
+            &segment,
+            min_sequence.is_some_and(|min| sequence_num < min),
+            current_time,
+        )?;
+        let orphan_aged_from = match min_sequence {
+            Some(_) => None,
+            None => {
+                tracing::trace!("playlist {} does not exist", playlist_path.display());
+                Some(self.orphan_aged_from(path, &metadata, &lifecycle, current_time)?)
+            }
+        };
+        let kept_as_stuck = stuck
+            .get(stream_base_name)
+            .is_some_and(|s| s.keeps(segment.modified));
+        let decide = |policy: &Policy| -> Decision {
+            if kept_as_stuck {
+                return Decision::Skip(SkipReason::Stuck);
+            }
+            match min_sequence {
+                Some(min_sequence) => {
+                    let held = || {
+                        self.boundaries.holds(
+                            stream_base_name,
+                            sequence_num,
+                            min_sequence,
+                            current_time,
+                            policy.max_break_hold,
+                        )
+                    };
+                    let unreferenced_for = lifecycle
+                        .unreferenced_since
+                        .and_then(|since| current_time.duration_since(since).ok())
+                        .unwrap_or_default();
+                    let remaining = policy.unreferenced_grace.saturating_sub(unreferenced_for);
+                    match sequence_num < min_sequence {
+                        true if held() => Decision::Skip(SkipReason::InBreak { min_sequence }),
+                        true if !remaining.is_zero() => {
+                            Decision::Skip(SkipReason::Grace { remaining })
+                        }
+                        true => Decision::Delete(Reason::Unreferenced { min_sequence }),
+                        false => Decision::Skip(SkipReason::Referenced { min_sequence }),
+                    }
+                }
+                None => {
+                    let lock = path
+                        .parent()
+                        .and_then(|dir| policy.publisher_lock_path(dir, stream_os));
+                    if lock.is_some_and(|lock| self.storage.exists(&lock)) {
+                        return Decision::Skip(SkipReason::Publishing);
+                    }
+                    let Some((aged_from, source)) = orphan_aged_from else {
+                        return Decision::Skip(SkipReason::AgeUnknown);
+                    };
+                    match current_time.duration_since(aged_from) {
+                        Ok(age) if age > policy.orphan_max_age => {
+                            Decision::Delete(Reason::Orphaned { age, source })
+                        }
+                        Ok(age) => Decision::Skip(SkipReason::TooYoung { age, source }),
+                        // within the tolerance, the fragment was just written
+                        Err(_) if source != TimeSource::FirstSeen => {
+                            Decision::Skip(SkipReason::TooYoung {
+                                age: Duration::ZERO,
+                                source,
+                            })
+                        }
+                        // the clock went back since the fragment was first seen
+                        Err(_) => Decision::Skip(SkipReason::AgeUnknown),
+                    }
+                }
+            }
+        };
+        let decision = decide(&self.policy);
+        let shadow = self.shadow.as_ref().map(decide);
+        Ok((segment, decision, shadow))
+    }
+}
+
+/// [`LOCK_FILE`], with the shard before the extension for a sharded cleaner
+fn lock_name(shard: Option<Shard>) -> String {
+    match (shard, LOCK_FILE.rsplit_once('.')) {
+        (Some(shard), Some((stem, extension))) => format!(
+            "{}.{}-of-{}.{}",
+            stem,
+            shard.index(),
+            shard.count(),
+            extension
+        ),
+        _ => LOCK_FILE.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStore, ManualClock};
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// skip reasons reported during a plan
+    #[derive(Default)]
+    struct Skips(Mutex<Vec<(PathBuf, SkipReason)>>);
+
+    impl CleanerObserver for Skips {
+        fn on_skip(&self, segment: &SegmentInfo, reason: SkipReason) {
+            self.0.lock().unwrap().push((segment.path.clone(), reason));
+        }
+    }
+
+    /// a cleaner of `/hls` in memory with a clock that only moves when told to
+    struct Fixture {
+        store: Arc<MemoryStore>,
+        clock: Arc<ManualClock>,
+        skips: Arc<Skips>,
+        cleaner: Cleaner,
+    }
+
+    impl Fixture {
+        fn new(policy: Policy) -> Self {
+            Self::with(policy.stuck_after(0), |builder| builder)
+        }
+
+        fn with(policy: Policy, build: impl FnOnce(CleanerBuilder) -> CleanerBuilder) -> Self {
+            let store = Arc::new(MemoryStore::new());
+            let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            let clock = Arc::new(ManualClock::new(start));
+            let skips = Arc::new(Skips::default());
+            let builder = Cleaner::builder()
+                .root("/hls")
+                .policy(policy)
+                .storage(store.clone())
+                .clock(clock.clone())
+                .observer(skips.clone())
+                .log_dedup_window(Duration::ZERO);
+            Self {
+                cleaner: build(builder).build(),
+                store,
+                clock,
+                skips,
+            }
+        }
+
+        fn path(name: &str) -> PathBuf {
+            Path::new("/hls").join(name)
+        }
+
+        /// write `name` timestamped `offset` from now, in the past when negative
+        fn file_at(&self, name: &str, contents: &str, offset: i64) {
+            let now = self.clock.now();
+            let at = match offset < 0 {
+                true => now - Duration::from_secs(offset.unsigned_abs()),
+                false => now + Duration::from_secs(offset as u64),
+            };
+            self.store.insert(Self::path(name), contents, at);
+        }
+
+        fn fragment(&self, name: &str) {
+            self.file_at(name, "ts", 0);
+        }
+
+        /// live playlist of `stream` listing `sequences`
+        fn playlist(&self, stream: &str, sequences: &[u32]) {
+            let tags = vec![""; sequences.len()];
+            self.tagged_playlist(stream, sequences, &tags);
+        }
+
+        /// live playlist of `stream` listing `sequences`, each preceded by its line of `tags`
+        fn tagged_playlist(&self, stream: &str, sequences: &[u32], tags: &[&str]) {
+            let mut playlist = format!(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+                sequences[0]
+            );
+            for (sequence, tag) in sequences.iter().zip(tags) {
+                if !tag.is_empty() {
+                    playlist.push_str(&format!("{}\n", tag));
+                }
+                playlist.push_str(&format!("#EXTINF:2,\n{}-{}.ts\n", stream, sequence));
+            }
+            self.file_at(&format!("{}.m3u8", stream), &playlist, 0);
+        }
+
+        /// the decision on every fragment under the root, by file name
+        fn decide(&self) -> BTreeMap<String, Decision> {
+            let plan = self.cleaner.plan().unwrap();
+            let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+            let mut decisions = self
+                .skips
+                .0
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|(path, reason)| (name(&path), Decision::Skip(reason)))
+                .collect::<BTreeMap<_, _>>();
+            for planned in plan {
+                decisions.insert(
+                    name(&planned.segment.path),
+                    Decision::Delete(planned.reason),
+                );
+            }
+            decisions
+        }
+
+        /// the reason name of the decision on `name`
+        fn verdict(&self, name: &str) -> &'static str {
+            let decisions = self.decide();
+            let decision = decisions
+                .get(name)
+                .unwrap_or_else(|| panic!("{} not seen", name));
+            decision.verdict(Action::Delete).1
+        }
+    }
+
+    #[test]
+    fn orphans_are_removed_after_thirty_minutes() {
+        let f = Fixture::new(Policy::default());
+        f.fragment("gone-1.ts");
+        assert_eq!(f.verdict("gone-1.ts"), "too_young");
+        f.clock.advance(29 * MINUTE);
+        assert_eq!(f.verdict("gone-1.ts"), "too_young");
+        f.clock.advance(2 * MINUTE);
+        assert!(matches!(
+            f.decide()["gone-1.ts"],
+            Decision::Delete(Reason::Orphaned {
+                source: TimeSource::Accessed,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn unreferenced_fragments_wait_for_the_grace() {
+        let f = Fixture::new(Policy::default().unreferenced_grace(MINUTE));
+        for sequence in 3..=6 {
+            f.fragment(&format!("live-{}.ts", sequence));
+        }
+        f.playlist("live", &[5, 6]);
+        assert!(matches!(
+            f.decide()["live-3.ts"],
+            Decision::Skip(SkipReason::Grace { remaining }) if remaining == MINUTE
+        ));
+        f.clock.advance(MINUTE / 2);
+        assert_eq!(f.verdict("live-4.ts"), "grace");
+        f.clock.advance(MINUTE);
+        let decisions = f.decide();
+        for name in ["live-3.ts", "live-4.ts"] {
+            assert_eq!(
+                decisions[name],
+                Decision::Delete(Reason::Unreferenced { min_sequence: 5 })
+            );
+        }
+    }
+
+    #[test]
+    fn fragments_from_the_future_age_from_when_they_were_first_seen() {
+        let f = Fixture::new(Policy::default());
+        // two hours ahead, beyond the one minute tolerance
+        f.file_at("skewed-1.ts", "ts", 7200);
+        // within the tolerance, just written by a clock slightly ahead
+        f.file_at("close-1.ts", "ts", 30);
+        let decisions = f.decide();
+        assert!(matches!(
+            decisions["skewed-1.ts"],
+            Decision::Skip(SkipReason::TooYoung {
+                source: TimeSource::FirstSeen,
+                ..
+            })
+        ));
+        assert_eq!(
+            decisions["close-1.ts"],
+            Decision::Skip(SkipReason::TooYoung {
+                age: Duration::ZERO,
+                source: TimeSource::Accessed,
+            })
+        );
+        // 31 minutes after it was first seen, still ahead of the clock
+        f.clock.advance(31 * MINUTE);
+        assert!(matches!(
+            f.decide()["skewed-1.ts"],
+            Decision::Delete(Reason::Orphaned {
+                source: TimeSource::FirstSeen,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn fragments_age_from_their_own_time_once_the_clock_catches_up() {
+        let f = Fixture::new(Policy::default());
+        f.file_at("skewed-1.ts", "ts", 600);
+        assert!(matches!(
+            f.decide()["skewed-1.ts"],
+            Decision::Skip(SkipReason::TooYoung {
+                source: TimeSource::FirstSeen,
+                ..
+            })
+        ));
+        // first seen 31 minutes ago, written 21 minutes ago
+        f.clock.advance(31 * MINUTE);
+        assert_eq!(
+            f.decide()["skewed-1.ts"],
+            Decision::Skip(SkipReason::TooYoung {
+                age: 21 * MINUTE,
+                source: TimeSource::Accessed,
+            })
+        );
+    }
+
+    #[test]
+    fn fragments_going_back_in_time_have_an_unknown_age() {
+        let f = Fixture::new(Policy::default());
+        f.file_at("skewed-1.ts", "ts", 600);
+        f.decide();
+        f.clock.set(f.clock.now() - MINUTE);
+        assert_eq!(f.verdict("skewed-1.ts"), "age_unknown");
+    }
+
+    #[test]
+    fn referenced_fragments_are_never_removed() {
+        let f = Fixture::new(Policy::default());
+        // written long ago, and one the playlist does not list yet
+        f.file_at("live-5.ts", "ts", -86400);
+        f.file_at("live-9.ts", "ts", -86400);
+        f.playlist("live", &[5, 6]);
+        for _ in 0..3 {
+            f.clock.advance(24 * 60 * MINUTE);
+            let decisions = f.decide();
+            for name in ["live-5.ts", "live-9.ts"] {
+                assert_eq!(
+                    decisions[name],
+                    Decision::Skip(SkipReason::Referenced { min_sequence: 5 })
+                );
+            }
+        }
+    }
+
+    fn names(paths: impl IntoIterator<Item = PathBuf>) -> Vec<String> {
+        paths
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn apply_removes_the_planned_fragments_only() {
+        let f = Fixture::new(Policy::default());
+        f.file_at("gone-1.ts", "old", -3600);
+        f.file_at("live-4.ts", "old", -3600);
+        f.file_at("live-5.ts", "new", -3600);
+        f.playlist("live", &[5]);
+        let plan = f.cleaner.plan().unwrap();
+        assert_eq!(
+            names(plan.iter().map(|p| p.segment.path.clone())),
+            ["gone-1.ts", "live-4.ts"]
+        );
+        // planning alone removes nothing
+        assert_eq!(f.store.paths().len(), 4);
+        let report = f.cleaner.apply(plan);
+        assert_eq!((report.deleted, report.bytes_freed), (2, 6));
+        assert_eq!(names(f.store.paths()), ["live-5.ts", "live.m3u8"]);
+        assert!(f.cleaner.plan().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_streams_are_backed_off() {
+        let f = Fixture::new(Policy::default());
+        f.file_at("bad-1.ts", "ts", -3600);
+        f.file_at("bad.m3u8", "not a playlist", 0);
+        f.file_at("good-1.ts", "ts", 0);
+        let mut failed = Vec::new();
+        for _ in 0..7 {
+            let report = f.cleaner.clean_once().await.unwrap();
+            assert_eq!(report.streams["good"].skipped, 1);
+            failed.push(!report.errors.is_empty());
+        }
+        // skipped for 1 cycle after the second failure, 3 after the third
+        assert_eq!(failed, [true, true, false, true, false, false, false]);
+        f.playlist("bad", &[1]);
+        let report = f.cleaner.clean_once().await.unwrap();
+        assert!(report.errors.is_empty());
+        assert_eq!(report.streams["bad"].skipped, 1);
+        // recovered, a new failure starts over without skipping
+        f.file_at("bad.m3u8", "not a playlist", 0);
+        for _ in 0..2 {
+            assert_eq!(f.cleaner.clean_once().await.unwrap().errors.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_deletion_cap_removes_the_oldest_first() {
+        let f = Fixture::with(Policy::default().stuck_after(0), |builder| {
+            builder.max_deletions_per_cycle(2)
+        });
+        for sequence in 1..=5 {
+            f.file_at(&format!("gone-{}.ts", sequence), "ts", -3600 - sequence);
+        }
+        let report = f.cleaner.clean_once().await.unwrap();
+        assert_eq!((report.deleted, report.deferred), (2, 3));
+        assert_eq!(
+            names(f.store.paths()),
+            ["gone-1.ts", "gone-2.ts", "gone-3.ts"]
+        );
+        let report = f.cleaner.clean_once().await.unwrap();
+        assert_eq!((report.deleted, report.deferred), (2, 1));
+        let report = f.cleaner.clean_once().await.unwrap();
+        assert_eq!((report.deleted, report.deferred), (1, 0));
+        assert!(f.store.paths().is_empty());
+    }
+
+    #[test]
+    fn stuck_streams_keep_what_was_written_since_the_last_playlist_update() {
+        let f = Fixture::with(Policy::default(), |builder| builder);
+        f.file_at("live-3.ts", "ts", -60);
+        f.fragment("live-5.ts");
+        f.playlist("live", &[5]);
+        // the packager stops, the encoder restarts its sequence numbers
+        f.clock.advance(MINUTE);
+        f.fragment("live-1.ts");
+        let decisions = f.decide();
+        assert_eq!(decisions["live-1.ts"], Decision::Skip(SkipReason::Stuck));
+        assert_eq!(
+            decisions["live-3.ts"],
+            Decision::Delete(Reason::Unreferenced { min_sequence: 5 })
+        );
+        assert_eq!(
+            decisions["live-5.ts"],
+            Decision::Skip(SkipReason::Referenced { min_sequence: 5 })
+        );
+        // the packager is back
+        f.playlist("live", &[6]);
+        f.clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            f.decide()["live-1.ts"],
+            Decision::Delete(Reason::Unreferenced { min_sequence: 6 })
+        );
+    }
+
+    #[test]
+    fn nodelete_markers_freeze_streams() {
+        let f = Fixture::new(Policy::default());
+        f.file_at("a-1.ts", "ts", -3600);
+        f.file_at("b-1.ts", "ts", -3600);
+        f.fragment(&format!("a{}", NODELETE_MARKER));
+        let decisions = f.decide();
+        assert_eq!(decisions["a-1.ts"], Decision::Skip(SkipReason::Frozen));
+        assert_eq!(decisions["b-1.ts"].verdict(Action::Delete).1, "orphaned");
+        f.fragment(NODELETE_MARKER);
+        assert_eq!(f.verdict("b-1.ts"), "frozen");
+        f.store.remove(&Fixture::path(NODELETE_MARKER)).unwrap();
+        f.store
+            .remove(&Fixture::path(&format!("a{}", NODELETE_MARKER)))
+            .unwrap();
+        assert_eq!(f.verdict("a-1.ts"), "orphaned");
+    }
+
+    #[test]
+    fn orphans_of_publishing_streams_are_kept() {
+        let f = Fixture::new(Policy::default().publisher_lock(Some("{stream}.lock".into())));
+        f.file_at("live-1.ts", "ts", -3600);
+        f.file_at("live-hd-1.ts", "ts", -3600);
+        f.fragment("live.lock");
+        let decisions = f.decide();
+        assert_eq!(
+            decisions["live-1.ts"],
+            Decision::Skip(SkipReason::Publishing)
+        );
+        assert_eq!(
+            decisions["live-hd-1.ts"].verdict(Action::Delete).1,
+            "orphaned"
+        );
+        f.store.remove(&Fixture::path("live.lock")).unwrap();
+        assert_eq!(f.verdict("live-1.ts"), "orphaned");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn publisher_locks_of_streams_with_names_that_are_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let f = Fixture::new(Policy::default().publisher_lock(Some("{stream}.lock".into())));
+        let stream = OsStr::from_bytes(b"caf\xe9");
+        let fragment = Path::new("/hls").join(segment::playlist_name(stream, "{stream}-1.ts"));
+        let lock = Path::new("/hls").join(segment::playlist_name(stream, "{stream}.lock"));
+        let old = f.clock.now() - Duration::from_secs(3600);
+        f.store.insert(&fragment, "ts", old);
+        f.store.insert(&lock, "", old);
+        // a lossy name would look for `caf\u{fffd}.lock`
+        assert_eq!(f.verdict("caf\u{fffd}-1.ts"), "publishing");
+        f.store.remove(&lock).unwrap();
+        assert_eq!(f.verdict("caf\u{fffd}-1.ts"), "orphaned");
+    }
+
+    #[test]
+    fn ad_breaks_are_removed_once_they_left_the_playlist() {
+        let f = Fixture::new(Policy::default());
+        const OUT: &str =
+            r#"#EXT-X-DATERANGE:ID="ad",START-DATE="2023-11-14T22:13:20Z",SCTE35-OUT=0xFC"#;
+        const IN: &str =
+            r#"#EXT-X-DATERANGE:ID="ad",START-DATE="2023-11-14T22:13:20Z",SCTE35-IN=0xFC"#;
+        for sequence in 4..=9 {
+            f.fragment(&format!("live-{}.ts", sequence));
+        }
+        f.tagged_playlist("live", &[5, 6, 7, 8, 9], &[OUT, "", "", IN, ""]);
+        f.decide();
+        // the break ends with live-8, which is still listed
+        f.playlist("live", &[7, 8, 9]);
+        let decisions = f.decide();
+        assert_eq!(
+            decisions["live-4.ts"],
+            Decision::Delete(Reason::Unreferenced { min_sequence: 7 })
+        );
+        for name in ["live-5.ts", "live-6.ts"] {
+            assert_eq!(
+                decisions[name],
+                Decision::Skip(SkipReason::InBreak { min_sequence: 7 })
+            );
+        }
+        f.playlist("live", &[8, 9]);
+        assert_eq!(
+            names(
+                f.cleaner
+                    .plan()
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.segment.path)
+            ),
+            ["live-4.ts", "live-5.ts", "live-6.ts", "live-7.ts"]
+        );
+    }
+
+    #[test]
+    fn ad_breaks_without_an_end_are_held_for_the_maximum_hold() {
+        let f = Fixture::new(Policy::default().max_break_hold(10 * MINUTE));
+        const OUT: &str =
+            r#"#EXT-X-DATERANGE:ID="ad",START-DATE="2023-11-14T22:13:20Z",SCTE35-OUT=0xFC"#;
+        for sequence in 5..=6 {
+            f.fragment(&format!("live-{}.ts", sequence));
+        }
+        f.tagged_playlist("live", &[5, 6], &[OUT, ""]);
+        f.decide();
+        f.playlist("live", &[7]);
+        assert_eq!(f.verdict("live-5.ts"), "in_break");
+        f.clock.advance(11 * MINUTE);
+        assert_eq!(f.verdict("live-5.ts"), "unreferenced");
+    }
+
+    #[test]
+    fn sharded_cleaners_lock_a_file_of_their_own() {
+        assert_eq!(lock_name(None), LOCK_FILE);
+        let shard = Shard::new(2, 8).unwrap();
+        assert_eq!(lock_name(Some(shard)), ".hls-cleaner.2-of-8.lock");
+    }
+}
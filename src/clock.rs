@@ -0,0 +1,54 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// source of the current time used for every age computation
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// wall clock, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// clock that only moves when told to, for simulating time passing
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += by;
+    }
+
+    pub fn set(&self, to: SystemTime) {
+        *self.now.lock().unwrap() = to;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,62 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use anyhow::Context;
+use hls_fragment_cleaner::{analyze::Analysis, Cleaner, Policy};
+
+use super::{fmt_age, fmt_bytes, print_table, Format};
+
+pub fn run(dirs: &[PathBuf], policy: &Policy, format: Format) -> anyhow::Result<()> {
+    let analyses = dirs
+        .iter()
+        .map(|dir| {
+            Cleaner::builder()
+                .root(dir)
+                .policy(policy.clone())
+                .build()
+                .analyze()
+                .with_context(|| format!("analyzing {}", dir.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&analyses)?),
+        Format::Table => analyses.iter().for_each(print_analysis),
+    }
+    Ok(())
+}
+
+fn print_analysis(analysis: &Analysis) {
+    let now = SystemTime::now();
+    println!("{}", analysis.root.display());
+    let mut rows = vec![[
+        "STREAM",
+        "SEGMENTS",
+        "SIZE",
+        "REFERENCED",
+        "ORPHANED",
+        "OLDEST",
+        "NEWEST",
+        "PLAYLIST",
+    ]
+    .map(str::to_owned)
+    .to_vec()];
+    for stream in &analysis.streams {
+        rows.push(vec![
+            stream.stream.clone(),
+            stream.segment_count.to_string(),
+            fmt_bytes(stream.total_bytes),
+            fmt_bytes(stream.referenced_bytes),
+            fmt_bytes(stream.orphaned_bytes),
+            fmt_age(stream.oldest.as_ref().and_then(|s| s.modified), now),
+            fmt_age(stream.newest.as_ref().and_then(|s| s.modified), now),
+            stream.playlist.to_string(),
+        ]);
+    }
+    print_table(&rows);
+    if !analysis.invalid_segments.is_empty() {
+        println!(
+            "{} fragments with an invalid name",
+            analysis.invalid_segments.len()
+        );
+    }
+    println!();
+}
@@ -0,0 +1,186 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use hls_fragment_cleaner::{CleanReport, CleanerBuilder};
+
+use super::fmt_bytes;
+
+/// file format of `--report-file`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// `csv` for `*.csv`, json otherwise
+    fn for_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+        {
+            true => Self::Csv,
+            false => Self::Json,
+        }
+    }
+}
+
+pub async fn run(
+    dirs: &[PathBuf],
+    template: CleanerBuilder,
+    report_file: Option<&Path>,
+    report_format: Option<ReportFormat>,
+) -> anyhow::Result<()> {
+    let mut reports = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        let cleaner = template.clone().root(dir).build();
+        let started_at = SystemTime::now();
+        // refuse to race a running daemon or a previous run that is still going, a directory
+        // that cannot be cleaned still gets its row in the report file
+        let report = match cleaner.lock() {
+            Ok(_lock) => cleaner.clean_once().await,
+            Err(e) => Err(e),
+        };
+        let report = report.unwrap_or_else(|e| CleanReport::failed(dir, started_at, &e));
+        if report.lock_contended {
+            println!("{}: skipped, another replica holds the lock", dir.display());
+        }
+        for error in &report.errors {
+            println!("error {}", error.message);
+        }
+        println!(
+            "{}: {} scanned, {} deleted, {} freed, {} errors",
+            dir.display(),
+            report.scanned,
+            report.deleted,
+            fmt_bytes(report.bytes_freed),
+            report.errors.len()
+        );
+        if report.deferred > 0 {
+            println!(
+                "{}: {} more deferred by the deletion cap",
+                dir.display(),
+                report.deferred
+            );
+        }
+        if let Some(shadow) = &report.shadow {
+            println!(
+                "{}: shadow policy disagrees on {} of {} segments, {} more removed ({}), {} more \
+                 kept ({}), {} with another action",
+                dir.display(),
+                shadow.disagreements(),
+                shadow.evaluated,
+                shadow.would_remove,
+                fmt_bytes(shadow.would_remove_bytes),
+                shadow.would_keep,
+                fmt_bytes(shadow.would_keep_bytes),
+                shadow.other_action
+            );
+            for example in &shadow.examples {
+                println!(
+                    "  {}: {} instead of {}",
+                    example.path.display(),
+                    example.shadow,
+                    example.active
+                );
+            }
+        }
+        let cancelled = report.cancelled;
+        reports.push(report);
+        if cancelled {
+            println!("interrupted, remaining directories not cleaned");
+            break;
+        }
+    }
+    if let Some(path) = report_file {
+        let content = match report_format.unwrap_or_else(|| ReportFormat::for_path(path)) {
+            ReportFormat::Json => serde_json::to_string_pretty(&reports)? + "\n",
+            ReportFormat::Csv => to_csv(&reports),
+        };
+        std::fs::write(path, content).with_context(|| format!("writing {}", path.display()))?;
+    }
+    if reports.iter().any(|r| !r.errors.is_empty()) {
+        anyhow::bail!("cleanup incomplete");
+    }
+    Ok(())
+}
+
+/// one row per stream and directory plus a `*` row with the directory totals
+fn to_csv(reports: &[CleanReport]) -> String {
+    let mut out = String::from(
+        "root,started_at,stream,scanned,skipped,deleted,bytes_freed,errors,duration_secs\n",
+    );
+    for report in reports {
+        let root = csv_field(&report.root.to_string_lossy());
+        let started_at = report
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for (stream, s) in &report.streams {
+            let _ = writeln!(
+                out,
+                "{},{},{},,{},{},{},{},",
+                root,
+                started_at,
+                csv_field(stream),
+                s.skipped,
+                s.deleted,
+                s.bytes_freed,
+                s.errors
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{},{},*,{},{},{},{},{},{:.3}",
+            root,
+            started_at,
+            report.scanned,
+            report.skipped,
+            report.deleted,
+            report.bytes_freed,
+            report.errors.len(),
+            report.duration.as_secs_f64()
+        );
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hls_fragment_cleaner::Cleaner;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn directories_that_fail_still_get_a_report_row() {
+        let dir = std::env::temp_dir().join(format!("hls-cleaner-clean-{}", std::process::id()));
+        let missing = dir.join("missing");
+        let report = dir.join("report.json");
+        std::fs::create_dir_all(&dir).unwrap();
+        let result = run(
+            std::slice::from_ref(&missing),
+            Cleaner::builder(),
+            Some(&report),
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+        let reports: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!(reports[0]["root"], missing.to_str().unwrap());
+        assert_eq!(reports[0]["errors"].as_array().unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
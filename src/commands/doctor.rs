@@ -0,0 +1,361 @@
+//! checks for the misconfigurations behind most support issues
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use hls_fragment_cleaner::{
+    parse_segment_name,
+    storage::{FsStore, Storage, TimeSource},
+    validate::Issue,
+    Cleaner, Policy,
+};
+
+use super::{fmt_duration, Format};
+
+/// how many segment names are sampled for the naming check
+const NAME_SAMPLE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Finding {
+    check: &'static str,
+    level: Level,
+    message: String,
+}
+
+impl Finding {
+    fn new(check: &'static str, level: Level, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+pub fn run(dirs: &[PathBuf], policy: &Policy, format: Format) -> anyhow::Result<()> {
+    let mut worst = Level::Ok;
+    for dir in dirs {
+        let findings = diagnose(dir, policy);
+        worst = worst.max(findings.iter().map(|f| f.level).max().unwrap_or(Level::Ok));
+        match format {
+            Format::Json => println!(
+                "{}",
+                serde_json::to_string(&serde_json::json!({ "dir": dir, "findings": findings }))?
+            ),
+            Format::Table => {
+                println!("{}", dir.display());
+                for finding in &findings {
+                    let level = match finding.level {
+                        Level::Ok => "ok",
+                        Level::Warning => "warn",
+                        Level::Error => "error",
+                    };
+                    println!("  [{:<5}] {:<12} {}", level, finding.check, finding.message);
+                }
+            }
+        }
+    }
+    if worst == Level::Error {
+        anyhow::bail!("doctor found problems");
+    }
+    Ok(())
+}
+
+fn diagnose(dir: &Path, policy: &Policy) -> Vec<Finding> {
+    if !dir.is_dir() {
+        return vec![Finding::new(
+            "directory",
+            Level::Error,
+            format!("{} does not exist or is not a directory", dir.display()),
+        )];
+    }
+    let storage = FsStore;
+    let (timestamps, source) = check_timestamps(&storage, dir);
+    let mut findings = vec![timestamps];
+    if source == Some(TimeSource::Accessed) {
+        findings.push(check_atime(dir));
+    }
+    findings.push(check_writable(&storage, dir));
+    let cleaner = Cleaner::builder().root(dir).policy(policy.clone()).build();
+    findings.extend(check_names(&storage, dir, &policy.playlist_template));
+    findings.push(check_playlists(&cleaner));
+    findings.push(check_clock(&storage, dir, policy));
+    findings
+}
+
+/// mount options of the filesystem holding `dir`, from /proc/mounts
+fn mount_options(dir: &Path) -> Option<(PathBuf, String)> {
+    let dir = dir.canonicalize().ok()?;
+    let mounts = std::fs::read_to_string("/proc/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, mount_point, _, options) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            Some((PathBuf::from(mount_point), options.to_owned()))
+        })
+        .filter(|(mount_point, _)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+}
+
+/// which timestamp orphans in `dir` age from on this platform
+fn check_timestamps(storage: &dyn Storage, dir: &Path) -> (Finding, Option<TimeSource>) {
+    match storage.metadata(dir).map(|meta| meta.aged_from) {
+        Ok(Some((_, source))) => (
+            Finding::new(
+                "timestamps",
+                Level::Ok,
+                format!("orphans age from their {}", source),
+            ),
+            Some(source),
+        ),
+        Ok(None) => (
+            Finding::new(
+                "timestamps",
+                Level::Error,
+                "the filesystem records no usable file times, orphans are never removed",
+            ),
+            None,
+        ),
+        Err(e) => (
+            Finding::new(
+                "timestamps",
+                Level::Warning,
+                format!("cannot read file times - {}", e),
+            ),
+            None,
+        ),
+    }
+}
+
+fn check_atime(dir: &Path) -> Finding {
+    let Some((mount_point, options)) = mount_options(dir) else {
+        return Finding::new(
+            "atime",
+            Level::Warning,
+            "mount options unavailable, cannot tell how access times are updated",
+        );
+    };
+    let options = options.split(',').collect::<Vec<_>>();
+    let (level, message) = if options.contains(&"noatime") {
+        (
+            Level::Warning,
+            "noatime: access times never move, orphans age from their creation time",
+        )
+    } else if options.contains(&"strictatime") {
+        (
+            Level::Warning,
+            "strictatime: every read refreshes access times, orphans still being served never expire",
+        )
+    } else {
+        (
+            Level::Ok,
+            "relatime: access times move on the first read and then at most daily",
+        )
+    };
+    Finding::new(
+        "atime",
+        level,
+        format!("{} on {}", message, mount_point.display()),
+    )
+}
+
+fn check_writable(storage: &dyn Storage, dir: &Path) -> Finding {
+    let probe = dir.join(format!(".hls-cleaner-doctor-{}", std::process::id()));
+    match storage
+        .append(&probe, b"")
+        .and_then(|()| storage.remove(&probe))
+    {
+        Ok(()) => Finding::new("permissions", Level::Ok, "directory is writable"),
+        Err(e) => Finding::new(
+            "permissions",
+            Level::Error,
+            format!(
+                "cannot create and delete files in {} - {}",
+                dir.display(),
+                e
+            ),
+        ),
+    }
+}
+
+/// segment names parse and their streams have a playlist named after `playlist_template`
+fn check_names(storage: &dyn Storage, dir: &Path, playlist_template: &str) -> Option<Finding> {
+    let entries = storage.list(dir).ok()?;
+    let names = entries
+        .iter()
+        .filter(|e| !e.is_dir)
+        .filter_map(|e| e.path.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .filter(|n| n.ends_with(".ts"))
+        .take(NAME_SAMPLE)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return Some(Finding::new(
+            "naming",
+            Level::Warning,
+            "no .ts segments found",
+        ));
+    }
+    let bad = names
+        .iter()
+        .filter(|n| parse_segment_name(Path::new(n)).is_err())
+        .collect::<Vec<_>>();
+    let streams = names
+        .iter()
+        .filter_map(|n| Some(parse_segment_name(Path::new(n)).ok()?.0))
+        .collect::<BTreeSet<_>>();
+    let orphaned = streams
+        .iter()
+        .map(|stream| playlist_template.replace("{stream}", stream))
+        .filter(|playlist| !storage.exists(&dir.join(playlist)))
+        .collect::<Vec<_>>();
+    Some(match (bad.first(), orphaned.first()) {
+        (None, None) => Finding::new(
+            "naming",
+            Level::Ok,
+            format!(
+                "{} sampled segments follow <stream>-<sequence>.ts next to {} playlists",
+                names.len(),
+                playlist_template
+            ),
+        ),
+        (None, Some(example)) => Finding::new(
+            "naming",
+            Level::Warning,
+            format!(
+                "{} of {} sampled streams have no playlist named after {}, e.g. no {}",
+                orphaned.len(),
+                streams.len(),
+                playlist_template,
+                example
+            ),
+        ),
+        (Some(example), _) => Finding::new(
+            "naming",
+            Level::Error,
+            format!(
+                "{} of {} sampled segments do not follow <stream>-<sequence>.ts, e.g. {}",
+                bad.len(),
+                names.len(),
+                example
+            ),
+        ),
+    })
+}
+
+fn check_playlists(cleaner: &Cleaner) -> Finding {
+    let validation = match cleaner.validate() {
+        Ok(validation) => validation,
+        Err(e) => return Finding::new("playlists", Level::Error, e.to_string()),
+    };
+    let unparsable = validation
+        .issues
+        .iter()
+        .filter(|i| matches!(i, Issue::UnparsablePlaylist { .. }))
+        .collect::<Vec<_>>();
+    match unparsable.first() {
+        None => Finding::new(
+            "playlists",
+            Level::Ok,
+            format!("{} playlists parse", validation.playlists),
+        ),
+        Some(issue) => Finding::new(
+            "playlists",
+            Level::Error,
+            format!(
+                "{} of {} playlists do not parse, e.g. {}",
+                unparsable.len(),
+                validation.playlists,
+                issue
+            ),
+        ),
+    }
+}
+
+fn check_clock(storage: &dyn Storage, dir: &Path, policy: &Policy) -> Finding {
+    let now = SystemTime::now();
+    if now < SystemTime::UNIX_EPOCH + Duration::from_secs(1_577_836_800) {
+        return Finding::new(
+            "clock",
+            Level::Error,
+            "system clock is before 2020, ages cannot be trusted",
+        );
+    }
+    let newest = storage
+        .list(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| storage.metadata(&e.path).ok()?.modified)
+        .max();
+    match newest.map(|t| t.duration_since(now)) {
+        Some(Ok(ahead)) if ahead > policy.clock_skew_tolerance => Finding::new(
+            "clock",
+            Level::Error,
+            format!(
+                "files are modified {} in the future, the writer or this host has clock skew",
+                fmt_duration(ahead)
+            ),
+        ),
+        _ => Finding::new(
+            "clock",
+            Level::Ok,
+            "file timestamps agree with the system clock",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hls_fragment_cleaner::storage::MemoryStore;
+
+    use super::*;
+
+    #[test]
+    fn streams_without_a_playlist_under_the_template_are_reported() {
+        let storage = MemoryStore::new();
+        let dir = Path::new("/hls");
+        let now = SystemTime::now();
+        storage.insert(dir.join("live-1.ts"), "", now);
+        storage.insert(dir.join("live.m3u8"), "", now);
+        let naming = |template| check_names(&storage, dir, template).unwrap();
+        assert_eq!(naming("{stream}.m3u8").level, Level::Ok);
+        let finding = naming("{stream}.m3u");
+        assert_eq!(finding.level, Level::Warning);
+        assert!(
+            finding.message.ends_with("no live.m3u"),
+            "{}",
+            finding.message
+        );
+    }
+
+    #[test]
+    fn the_clock_check_uses_the_policy_tolerance() {
+        let storage = MemoryStore::new();
+        let dir = Path::new("/hls");
+        let ahead = SystemTime::now() + Duration::from_secs(300);
+        storage.insert(dir.join("live-1.ts"), "", ahead);
+        let clock = |tolerance| {
+            let policy = Policy::default().clock_skew_tolerance(tolerance);
+            check_clock(&storage, dir, &policy).level
+        };
+        assert_eq!(clock(Duration::from_secs(60)), Level::Error);
+        assert_eq!(clock(Duration::from_secs(600)), Level::Ok);
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use super::Format;
+
+#[cfg(feature = "sqlite")]
+pub fn run(
+    index: &Path,
+    stream: &str,
+    from: &str,
+    to: Option<&str>,
+    format: Format,
+) -> anyhow::Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::Context;
+    use hls_fragment_cleaner::index::ArchiveIndex;
+
+    use super::{fmt_bytes, print_table, restore::parse_since};
+
+    let from = parse_since(from)?;
+    let to = match to {
+        Some(to) => parse_since(to)?,
+        None => SystemTime::now(),
+    };
+    let entries = ArchiveIndex::open(index)
+        .and_then(|index| index.find(stream, from, to))
+        .with_context(|| format!("searching {}", index.display()))?;
+    match format {
+        Format::Json => println!("{}", serde_json::to_string(&entries)?),
+        Format::Table if entries.is_empty() => {
+            println!("nothing of {} archived in that span", stream)
+        }
+        Format::Table => {
+            let time = |secs: Option<u64>| match secs {
+                Some(secs) => humantime::format_rfc3339_seconds(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                )
+                .to_string(),
+                None => "-".to_owned(),
+            };
+            let mut rows = vec![["start", "end", "sequences", "size", "location"]
+                .map(String::from)
+                .to_vec()];
+            for entry in &entries {
+                let location = match (&entry.remote, entry.expired_at) {
+                    (_, Some(expired_at)) => format!("expired {}", time(Some(expired_at))),
+                    (Some(remote), None) => remote.clone(),
+                    (None, None) => entry.path.display().to_string(),
+                };
+                rows.push(vec![
+                    time(entry.start_time),
+                    time(entry.end_time),
+                    format!("{}-{}", entry.first_sequence, entry.last_sequence),
+                    fmt_bytes(entry.size),
+                    location,
+                ]);
+            }
+            print_table(&rows);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn run(
+    _index: &Path,
+    _stream: &str,
+    _from: &str,
+    _to: Option<&str>,
+    _format: Format,
+) -> anyhow::Result<()> {
+    anyhow::bail!("find needs a build with the sqlite feature")
+}
@@ -0,0 +1,52 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use hls_fragment_cleaner::{Cleaner, Policy};
+
+use super::{fmt_bytes, Format};
+
+pub fn run(
+    dirs: &[PathBuf],
+    policy: &Policy,
+    retention: Duration,
+    verify_archive: bool,
+    format: Format,
+) -> anyhow::Result<()> {
+    let mut failed = false;
+    for dir in dirs {
+        let cleaner = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone().trash_retention(retention))
+            .build();
+        let report = cleaner
+            .gc()
+            .with_context(|| format!("collecting trash in {}", dir.display()))?;
+        failed |= !report.errors.is_empty();
+        match format {
+            Format::Json => println!("{}", serde_json::to_string(&report)?),
+            Format::Table => {
+                for error in &report.errors {
+                    println!("error {}", error);
+                }
+                println!(
+                    "{}: {} trashed segments purged, {} freed, {} empty directories removed",
+                    dir.display(),
+                    report.purged.len(),
+                    fmt_bytes(report.bytes_freed),
+                    report.removed_dirs.len()
+                );
+            }
+        }
+        if verify_archive {
+            let verification = cleaner
+                .verify_archive()
+                .with_context(|| format!("verifying the archive of {}", dir.display()))?;
+            failed |= !verification.issues.is_empty();
+            super::verify::print(&verification, format)?;
+        }
+    }
+    if failed {
+        anyhow::bail!("garbage collection incomplete");
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! subcommands of the binary, the daemon itself lives in `main.rs`
+
+use std::time::{Duration, SystemTime};
+
+pub mod analyze;
+pub mod clean;
+pub mod doctor;
+pub mod find;
+pub mod gc;
+pub mod purge;
+pub mod restore;
+pub mod simulate;
+pub mod stats;
+pub mod tail;
+pub mod tui;
+pub mod validate;
+pub mod verify;
+
+/// output format of the reporting subcommands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+}
+
+/// `1.5 MiB` style sizes
+pub fn fmt_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// how long ago `time` was, `3m12s`, `-` when unknown
+pub fn fmt_age(time: Option<SystemTime>, now: SystemTime) -> String {
+    match time.map(|t| now.duration_since(t)) {
+        Some(Ok(age)) => fmt_duration(age),
+        Some(Err(_)) => "future".to_owned(),
+        None => "-".to_owned(),
+    }
+}
+
+pub fn fmt_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+/// print rows as left aligned columns, the first row is the header
+pub fn print_table(rows: &[Vec<String>]) {
+    let Some(columns) = rows.first().map(Vec::len) else {
+        return;
+    };
+    let widths = (0..columns)
+        .map(|i| rows.iter().map(|r| r[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use hls_fragment_cleaner::{Cleaner, Policy};
+
+use super::Format;
+
+pub fn run(
+    dirs: &[PathBuf],
+    policy: &Policy,
+    stream: &str,
+    dry_run: bool,
+    format: Format,
+) -> anyhow::Result<()> {
+    let mut failed = false;
+    for dir in dirs {
+        let report = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone())
+            .build()
+            .purge_stream(stream, dry_run)
+            .with_context(|| format!("purging {} in {}", stream, dir.display()))?;
+        failed |= !report.errors.is_empty();
+        match format {
+            Format::Json => println!("{}", serde_json::to_string(&report)?),
+            Format::Table => {
+                let verb = match dry_run {
+                    true => "would remove",
+                    false => "removed",
+                };
+                for path in &report.removed {
+                    println!("{} {}", verb, path.display());
+                }
+                for error in &report.errors {
+                    println!("error {}", error);
+                }
+                if report.removed.is_empty() {
+                    println!("nothing to purge for {} in {}", stream, dir.display());
+                }
+            }
+        }
+    }
+    if failed {
+        anyhow::bail!("some artifacts of {} could not be removed", stream);
+    }
+    Ok(())
+}
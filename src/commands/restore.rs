@@ -0,0 +1,71 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use hls_fragment_cleaner::{Cleaner, Policy};
+
+use super::Format;
+
+pub fn run(
+    dirs: &[PathBuf],
+    policy: &Policy,
+    stream: &str,
+    since: Option<&str>,
+    format: Format,
+) -> anyhow::Result<()> {
+    let since = since.map(parse_since).transpose()?;
+    let mut failed = false;
+    for dir in dirs {
+        let report = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone())
+            .build()
+            .restore(stream, since)
+            .with_context(|| format!("restoring {} in {}", stream, dir.display()))?;
+        failed |= !report.errors.is_empty();
+        match format {
+            Format::Json => println!("{}", serde_json::to_string(&report)?),
+            Format::Table => {
+                for path in &report.restored {
+                    println!("restored {}", path.display());
+                }
+                for path in &report.conflicts {
+                    println!("conflict {}, original path exists", path.display());
+                }
+                for error in &report.errors {
+                    println!("error {}", error);
+                }
+                println!(
+                    "{}: {} restored, {} conflicts, {} errors",
+                    dir.display(),
+                    report.restored.len(),
+                    report.conflicts.len(),
+                    report.errors.len()
+                );
+            }
+        }
+    }
+    if failed {
+        anyhow::bail!("some segments of {} could not be restored", stream);
+    }
+    Ok(())
+}
+
+/// unix seconds, an rfc 3339 timestamp or a duration ago such as `2h`
+pub fn parse_since(s: &str) -> anyhow::Result<SystemTime> {
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    }
+    if let Ok(time) = humantime::parse_rfc3339_weak(s) {
+        return Ok(time);
+    }
+    let ago = humantime::parse_duration(s).with_context(|| {
+        format!(
+            "invalid time {}, expected unix seconds, rfc 3339 or a duration",
+            s
+        )
+    })?;
+    Ok(SystemTime::now() - ago)
+}
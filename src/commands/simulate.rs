@@ -0,0 +1,28 @@
+use hls_fragment_cleaner::simulate::{simulate, SimulationConfig};
+
+use super::Format;
+
+pub async fn run(config: SimulationConfig, format: Format) -> anyhow::Result<()> {
+    let report = simulate(&config).await?;
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        Format::Table => {
+            println!(
+                "{} cycles over {} streams, {} segments written, {} deleted",
+                report.cycles, config.streams, report.segments_written, report.deleted
+            );
+            println!(
+                "{} restarts, {} corrupt playlists, {} errors reported by cycles",
+                report.restarts, report.corrupt_playlists, report.cycle_errors
+            );
+            println!("{} unreferenced segments left at the end", report.lingering);
+            for violation in &report.violations {
+                println!("violation: {}", violation);
+            }
+        }
+    }
+    if !report.violations.is_empty() {
+        anyhow::bail!("{} live segments were deleted", report.violations.len());
+    }
+    Ok(())
+}
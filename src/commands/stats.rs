@@ -0,0 +1,62 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use anyhow::Context;
+use hls_fragment_cleaner::{Cleaner, Policy};
+
+use super::{fmt_age, fmt_duration, print_table, Format};
+
+pub fn run(dirs: &[PathBuf], policy: &Policy, format: Format) -> anyhow::Result<()> {
+    let now = SystemTime::now();
+    for dir in dirs {
+        let stats = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone())
+            .build()
+            .stream_stats()
+            .with_context(|| format!("reading {}", dir.display()))?;
+        match format {
+            Format::Json => println!("{}", serde_json::to_string(&stats)?),
+            Format::Table => {
+                println!("{}", dir.display());
+                let mut rows = vec![[
+                    "STREAM",
+                    "SEGMENTS",
+                    "WINDOW",
+                    "WINDOW SECS",
+                    "AVG SEGMENT",
+                    "TARGET",
+                    "MEDIA SEQ",
+                    "PLAYLIST UPDATED",
+                    "NEWEST SEGMENT",
+                ]
+                .map(str::to_owned)
+                .to_vec()];
+                for s in &stats {
+                    rows.push(vec![
+                        s.stream.clone(),
+                        s.segments.to_string(),
+                        match (&s.playlist_error, s.window_segments) {
+                            (Some(_), _) => "unparsable".to_owned(),
+                            (None, Some(n)) => n.to_string(),
+                            (None, None) => "-".to_owned(),
+                        },
+                        s.window.map_or("-".to_owned(), fmt_duration),
+                        s.average_segment_duration
+                            .map_or("-".to_owned(), |d| format!("{:.2}s", d.as_secs_f64())),
+                        s.target_duration.map_or("-".to_owned(), fmt_duration),
+                        match (s.media_sequence, s.ended) {
+                            (Some(seq), Some(true)) => format!("{} ended", seq),
+                            (Some(seq), _) => seq.to_string(),
+                            (None, _) => "-".to_owned(),
+                        },
+                        fmt_age(s.playlist_updated, now),
+                        fmt_age(s.newest_segment, now),
+                    ]);
+                }
+                print_table(&rows);
+                println!();
+            }
+        }
+    }
+    Ok(())
+}
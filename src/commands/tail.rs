@@ -0,0 +1,126 @@
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+
+#[cfg(unix)]
+use anyhow::Context;
+use hls_fragment_cleaner::events::EventStream;
+#[cfg(unix)]
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::broadcast::error::RecvError,
+};
+
+/// serve the daemon events on a unix socket, one json line per event
+#[cfg(unix)]
+pub fn serve(path: &Path, events: EventStream) -> anyhow::Result<()> {
+    // a socket left behind by a previous daemon would make bind fail, anything else at the
+    // path is not ours to remove
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).with_context(|| format!("removing {}", path.display()))?
+        }
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("checking {}", path.display())),
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("binding {}", path.display()))?;
+    tracing::info!("serving events on {}", path.display());
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("unable to accept event subscriber - {}", e);
+                    continue;
+                }
+            };
+            let mut rx = events.subscribe();
+            tokio::spawn(async move {
+                let mut stream = stream;
+                loop {
+                    let line = match rx.recv().await {
+                        Ok(line) => line,
+                        Err(RecvError::Lagged(n)) => {
+                            tracing::debug!("event subscriber missed {} events", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return,
+                    };
+                    if stream.write_all(line.as_bytes()).await.is_err()
+                        || stream.write_all(b"\n").await.is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// print the events of a running daemon until it goes away
+#[cfg(unix)]
+pub async fn run(socket: &Path, stream: Option<&str>, skips: bool) -> anyhow::Result<()> {
+    let conn = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("connecting to {}", socket.display()))?;
+    let mut lines = BufReader::new(conn).lines();
+    while let Some(line) = lines.next_line().await? {
+        let event: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("ignoring malformed event - {}", e);
+                continue;
+            }
+        };
+        if shown(&event, stream, skips) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// errors of no particular stream and cycle summaries pass the stream filter
+#[cfg(unix)]
+fn shown(event: &serde_json::Value, stream: Option<&str>, skips: bool) -> bool {
+    if !skips && event["event"] == "skip" {
+        return false;
+    }
+    match (stream, event["stream"].as_str()) {
+        (Some(stream), Some(of)) => of == stream,
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _events: EventStream) -> anyhow::Result<()> {
+    anyhow::bail!("--events-socket needs unix domain sockets")
+}
+
+#[cfg(not(unix))]
+pub async fn run(_socket: &Path, _stream: Option<&str>, _skips: bool) -> anyhow::Result<()> {
+    anyhow::bail!("tail needs unix domain sockets")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn the_stream_filter_lets_events_without_a_stream_through() {
+        let error = |stream| json!({"event": "error", "stream": stream, "message": "x"});
+        assert!(shown(&error(Some("live")), Some("live"), false));
+        assert!(!shown(&error(Some("other")), Some("live"), false));
+        assert!(shown(&error(None), Some("live"), false));
+        assert!(shown(&json!({"event": "cycle_end"}), Some("live"), false));
+        let skip = json!({"event": "skip", "stream": "live"});
+        assert!(!shown(&skip, Some("live"), false));
+        assert!(shown(&skip, Some("live"), true));
+    }
+}
@@ -0,0 +1,373 @@
+//! live terminal dashboard of the streams of every directory, until `q` or esc is pressed
+//!
+//! segment counts, playlist windows and disk usage are re-read from the directories like
+//! `stats` and `analyze` do, deletions and errors come from the events socket of a running
+//! daemon when one is given. nothing is modified.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "tui")]
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
+};
+
+#[cfg(feature = "tui")]
+use hls_fragment_cleaner::Cleaner;
+use hls_fragment_cleaner::Policy;
+#[cfg(feature = "tui")]
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+#[cfg(feature = "tui")]
+use super::{fmt_age, fmt_bytes, fmt_duration};
+
+/// deletions and errors kept for display
+#[cfg(all(feature = "tui", unix))]
+const RECENT: usize = 200;
+
+/// what the directories hold, refreshed every interval
+#[cfg(feature = "tui")]
+struct Root {
+    dir: PathBuf,
+    streams: Vec<StreamRow>,
+    invalid_segments: usize,
+    /// the directory could not be read
+    error: Option<String>,
+}
+
+#[cfg(feature = "tui")]
+struct StreamRow {
+    stream: String,
+    segments: usize,
+    window_segments: Option<usize>,
+    window: Option<Duration>,
+    bytes: u64,
+    orphaned_bytes: u64,
+    newest: Option<SystemTime>,
+    playlist: String,
+    playlist_ok: bool,
+}
+
+/// what the daemon reported since the dashboard started
+#[cfg(feature = "tui")]
+#[derive(Default)]
+struct Feed {
+    status: String,
+    deletions: VecDeque<String>,
+    errors: VecDeque<String>,
+    /// deleted fragments and errors per root and stream, from the cycle reports
+    totals: BTreeMap<(PathBuf, String), (usize, usize)>,
+    cycles: usize,
+}
+
+#[cfg(all(feature = "tui", unix))]
+impl Feed {
+    fn push(list: &mut VecDeque<String>, line: String) {
+        if list.len() == RECENT {
+            list.pop_back();
+        }
+        list.push_front(line);
+    }
+
+    fn record(&mut self, event: &serde_json::Value) {
+        let at = event["at"]
+            .as_u64()
+            .map(|secs| {
+                let at = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+                humantime::format_rfc3339_seconds(at).to_string()
+            })
+            .unwrap_or_default();
+        match event["event"].as_str() {
+            Some("delete") => Self::push(
+                &mut self.deletions,
+                format!(
+                    "{} {} {} {} ({})",
+                    at,
+                    event["stream"].as_str().unwrap_or("-"),
+                    event["path"].as_str().unwrap_or("-"),
+                    event["reason"].as_str().unwrap_or("-"),
+                    fmt_bytes(event["size"].as_u64().unwrap_or(0)),
+                ),
+            ),
+            Some("error") => Self::push(
+                &mut self.errors,
+                format!("{} {}", at, event["message"].as_str().unwrap_or("-")),
+            ),
+            Some("cycle_end") => {
+                let report = &event["report"];
+                let root = PathBuf::from(report["root"].as_str().unwrap_or_default());
+                if let Some(streams) = report["streams"].as_object() {
+                    for (stream, counters) in streams {
+                        let totals = self
+                            .totals
+                            .entry((root.clone(), stream.clone()))
+                            .or_default();
+                        totals.0 += counters["deleted"].as_u64().unwrap_or(0) as usize;
+                        totals.1 += counters["errors"].as_u64().unwrap_or(0) as usize;
+                    }
+                }
+                self.cycles += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+pub async fn run(
+    dirs: &[PathBuf],
+    policy: &Policy,
+    socket: Option<&Path>,
+    refresh: Duration,
+) -> anyhow::Result<()> {
+    let feed = Arc::new(Mutex::new(Feed {
+        status: "no --events-socket, deletions and errors of the daemon are not shown".to_owned(),
+        ..Default::default()
+    }));
+    if let Some(socket) = socket {
+        follow(socket, feed.clone());
+    }
+    let (dirs, policy) = (dirs.to_vec(), policy.clone());
+    tokio::task::spawn_blocking(move || {
+        let mut terminal = ratatui::try_init()?;
+        let result = dashboard(&mut terminal, &dirs, &policy, &feed, refresh);
+        ratatui::try_restore()?;
+        result
+    })
+    .await?
+}
+
+/// record the events of the daemon listening on `socket` into `feed` until it goes away
+#[cfg(all(feature = "tui", unix))]
+fn follow(socket: &Path, feed: Arc<Mutex<Feed>>) {
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::UnixStream,
+    };
+
+    let socket = socket.to_owned();
+    feed.lock().unwrap().status = format!("connecting to {}", socket.display());
+    tokio::spawn(async move {
+        let status = match UnixStream::connect(&socket).await {
+            Ok(conn) => {
+                feed.lock().unwrap().status = format!("following {}", socket.display());
+                let mut lines = BufReader::new(conn).lines();
+                loop {
+                    match lines.next_line().await {
+                        Ok(Some(line)) => {
+                            // malformed lines are skipped, the log would garble the screen
+                            if let Ok(event) = serde_json::from_str(&line) {
+                                feed.lock().unwrap().record(&event);
+                            }
+                        }
+                        Ok(None) => break format!("{} closed", socket.display()),
+                        Err(e) => break format!("reading {} - {}", socket.display(), e),
+                    }
+                }
+            }
+            Err(e) => format!("unable to connect to {} - {}", socket.display(), e),
+        };
+        feed.lock().unwrap().status = status;
+    });
+}
+
+#[cfg(all(feature = "tui", not(unix)))]
+fn follow(_socket: &Path, feed: Arc<Mutex<Feed>>) {
+    feed.lock().unwrap().status = "following the daemon needs unix domain sockets".to_owned();
+}
+
+#[cfg(feature = "tui")]
+fn dashboard(
+    terminal: &mut DefaultTerminal,
+    dirs: &[PathBuf],
+    policy: &Policy,
+    feed: &Mutex<Feed>,
+    refresh: Duration,
+) -> anyhow::Result<()> {
+    let mut roots = Vec::new();
+    let mut refreshed: Option<Instant> = None;
+    loop {
+        if refreshed.is_none_or(|at| at.elapsed() >= refresh) {
+            roots = dirs.iter().map(|dir| read(dir, policy)).collect();
+            refreshed = Some(Instant::now());
+        }
+        terminal.draw(|frame| draw(frame, &roots, &feed.lock().unwrap(), refresh))?;
+        // short polls so daemon events show up between refreshes
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c)
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn read(dir: &Path, policy: &Policy) -> Root {
+    let cleaner = Cleaner::builder().root(dir).policy(policy.clone()).build();
+    let (stats, analysis) = match cleaner
+        .stream_stats()
+        .and_then(|s| Ok((s, cleaner.analyze()?)))
+    {
+        Ok(read) => read,
+        Err(e) => {
+            return Root {
+                dir: dir.to_owned(),
+                streams: Vec::new(),
+                invalid_segments: 0,
+                error: Some(e.to_string()),
+            }
+        }
+    };
+    let mut windows = stats
+        .into_iter()
+        .map(|s| (s.stream.clone(), s))
+        .collect::<BTreeMap<_, _>>();
+    let streams = analysis
+        .streams
+        .into_iter()
+        .map(|s| {
+            let stats = windows.remove(&s.stream);
+            StreamRow {
+                segments: s.segment_count,
+                window_segments: stats.as_ref().and_then(|s| s.window_segments),
+                window: stats.as_ref().and_then(|s| s.window),
+                bytes: s.total_bytes,
+                orphaned_bytes: s.orphaned_bytes,
+                newest: s.newest.and_then(|n| n.modified),
+                playlist: s.playlist.to_string(),
+                playlist_ok: s.playlist.is_ok(),
+                stream: s.stream,
+            }
+        })
+        .collect();
+    Root {
+        dir: dir.to_owned(),
+        streams,
+        invalid_segments: analysis.invalid_segments.len(),
+        error: None,
+    }
+}
+
+#[cfg(feature = "tui")]
+fn draw(frame: &mut Frame, roots: &[Root], feed: &Feed, refresh: Duration) {
+    let now = SystemTime::now();
+    let [tables, recent, footer] = Layout::vertical([
+        Constraint::Min(4),
+        Constraint::Length(12),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let areas = Layout::vertical(
+        roots
+            .iter()
+            .map(|r| Constraint::Min(r.streams.len().max(1) as u16 + 3)),
+    )
+    .split(tables);
+    for (root, area) in roots.iter().zip(areas.iter()) {
+        let total = root.streams.iter().map(|s| s.bytes).sum();
+        let mut title = format!(" {} - {} ", root.dir.display(), fmt_bytes(total));
+        if root.invalid_segments > 0 {
+            title.push_str(&format!("- {} misnamed fragments ", root.invalid_segments));
+        }
+        let block = Block::bordered().title(title);
+        if let Some(error) = &root.error {
+            let error = List::new([ListItem::new(error.as_str()).fg(Color::Red)]);
+            frame.render_widget(error.block(block), *area);
+            continue;
+        }
+        let rows = root.streams.iter().map(|s| {
+            let (deleted, errors) = feed
+                .totals
+                .get(&(root.dir.clone(), s.stream.clone()))
+                .copied()
+                .unwrap_or_default();
+            let row = Row::new([
+                s.stream.clone(),
+                s.segments.to_string(),
+                s.window_segments.map_or("-".to_owned(), |n| n.to_string()),
+                s.window.map_or("-".to_owned(), fmt_duration),
+                fmt_bytes(s.bytes),
+                fmt_bytes(s.orphaned_bytes),
+                fmt_age(s.newest, now),
+                s.playlist.clone(),
+                deleted.to_string(),
+                errors.to_string(),
+            ]);
+            match s.playlist_ok && errors == 0 {
+                true => row,
+                false => row.style(Style::new().fg(Color::Red)),
+            }
+        });
+        let header = Row::new([
+            "STREAM",
+            "SEGMENTS",
+            "WINDOW",
+            "WINDOW SECS",
+            "SIZE",
+            "ORPHANED",
+            "NEWEST",
+            "PLAYLIST",
+            "DELETED",
+            "ERRORS",
+        ])
+        .bold();
+        let widths = [
+            Constraint::Fill(2),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(11),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(7),
+            Constraint::Length(6),
+        ];
+        frame.render_widget(Table::new(rows, widths).header(header).block(block), *area);
+    }
+    let [deletions, errors] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(recent);
+    frame.render_widget(recent_list(&feed.deletions, "recent deletions"), deletions);
+    frame.render_widget(recent_list(&feed.errors, "errors").fg(Color::Red), errors);
+    let status = format!(
+        " {} - {} cycles - refreshed every {} - q to quit",
+        feed.status,
+        feed.cycles,
+        fmt_duration(refresh)
+    );
+    frame.render_widget(Line::from(status).dim(), footer);
+}
+
+#[cfg(feature = "tui")]
+fn recent_list<'a>(lines: &'a VecDeque<String>, title: &str) -> List<'a> {
+    List::new(lines.iter().map(|l| ListItem::new(l.as_str())))
+        .block(Block::bordered().title(format!(" {} ", title)))
+}
+
+#[cfg(not(feature = "tui"))]
+pub async fn run(
+    _dirs: &[PathBuf],
+    _policy: &Policy,
+    _socket: Option<&Path>,
+    _refresh: Duration,
+) -> anyhow::Result<()> {
+    anyhow::bail!("tui needs a build with the tui feature")
+}
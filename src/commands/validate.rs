@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use hls_fragment_cleaner::{validate::Severity, Cleaner, Policy};
+
+use super::Format;
+
+/// exits non-zero on errors, or on warnings too with `strict`
+pub fn run(dirs: &[PathBuf], policy: &Policy, strict: bool, format: Format) -> anyhow::Result<()> {
+    let threshold = match strict {
+        true => Severity::Warning,
+        false => Severity::Error,
+    };
+    let mut failed = false;
+    for dir in dirs {
+        let validation = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone())
+            .build()
+            .validate()
+            .with_context(|| format!("validating {}", dir.display()))?;
+        failed |= validation.has_issues(threshold);
+        match format {
+            Format::Json => println!("{}", serde_json::to_string(&validation)?),
+            Format::Table => {
+                for issue in &validation.issues {
+                    let level = match issue.severity() {
+                        Severity::Warning => "warning",
+                        Severity::Error => "error",
+                    };
+                    println!("{}: {}", level, issue);
+                }
+                println!(
+                    "{}: {} playlists, {} segments, {} issues",
+                    dir.display(),
+                    validation.playlists,
+                    validation.segments,
+                    validation.issues.len()
+                );
+            }
+        }
+    }
+    if failed {
+        anyhow::bail!("inconsistencies found");
+    }
+    Ok(())
+}
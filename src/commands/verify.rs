@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use hls_fragment_cleaner::{archive::ArchiveVerification, Cleaner, Policy};
+
+use super::Format;
+
+pub fn run(dirs: &[PathBuf], policy: &Policy, format: Format) -> anyhow::Result<()> {
+    let mut failed = false;
+    for dir in dirs {
+        let verification = Cleaner::builder()
+            .root(dir)
+            .policy(policy.clone())
+            .build()
+            .verify_archive()
+            .with_context(|| format!("verifying the archive of {}", dir.display()))?;
+        failed |= !verification.issues.is_empty();
+        print(&verification, format)?;
+    }
+    if failed {
+        anyhow::bail!("archive verification failed");
+    }
+    Ok(())
+}
+
+pub fn print(verification: &ArchiveVerification, format: Format) -> anyhow::Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string(verification)?),
+        Format::Table => {
+            for issue in &verification.issues {
+                println!("error {}", issue);
+            }
+            println!(
+                "{}: {} manifests, {} segments verified, {} without checksum, {} decrypted, {} \
+                 issues",
+                verification.dir.display(),
+                verification.manifests,
+                verification.verified,
+                verification.unchecked,
+                verification.decrypted,
+                verification.issues.len()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! runtime control of the cleaners of a daemon
+//!
+//! a [`Control`] handed to the cleaners with
+//! [`CleanerBuilder::control`](crate::CleanerBuilder::control) lets an operator pause the
+//! scheduled cycles of a root, run one right away and read back how the last one went. a
+//! triggered cycle runs even while the root is paused, a trigger arriving during a cycle runs
+//! another one right after it. the `grpc` feature exposes it to remote control planes.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::{CleanReport, CleanerObserver};
+
+/// what is known about a controlled root, see [`Control::status`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RootStatus {
+    pub root: PathBuf,
+    pub paused: bool,
+    /// completed cycles since the daemon started
+    pub cycles: u64,
+    pub last_cycle: Option<CleanReport>,
+}
+
+#[derive(Debug, Default)]
+struct RootControl {
+    paused: AtomicBool,
+    trigger: Notify,
+    cycles: Mutex<(u64, Option<CleanReport>)>,
+}
+
+/// pause, resume and trigger switches of a fixed set of roots, also an observer recording
+/// the outcome of their cycles
+#[derive(Debug)]
+pub struct Control {
+    roots: BTreeMap<PathBuf, RootControl>,
+}
+
+impl Control {
+    pub fn new(roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            roots: roots
+                .into_iter()
+                .map(|root| (root, RootControl::default()))
+                .collect(),
+        }
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.roots.keys().map(PathBuf::as_path)
+    }
+
+    /// skip the scheduled cycles of `root` until resumed, false when it is not controlled
+    pub fn pause(&self, root: &Path) -> bool {
+        self.set_paused(root, true)
+    }
+
+    pub fn resume(&self, root: &Path) -> bool {
+        self.set_paused(root, false)
+    }
+
+    fn set_paused(&self, root: &Path, paused: bool) -> bool {
+        let Some(control) = self.roots.get(root) else {
+            return false;
+        };
+        if control.paused.swap(paused, Ordering::Relaxed) != paused {
+            tracing::info!(
+                "{} {}",
+                if paused { "paused" } else { "resumed" },
+                root.display()
+            );
+        }
+        true
+    }
+
+    pub fn is_paused(&self, root: &Path) -> bool {
+        self.roots
+            .get(root)
+            .is_some_and(|c| c.paused.load(Ordering::Relaxed))
+    }
+
+    /// run a cycle of `root` as soon as the running one is done, false when it is not
+    /// controlled
+    pub fn trigger(&self, root: &Path) -> bool {
+        let Some(control) = self.roots.get(root) else {
+            return false;
+        };
+        control.trigger.notify_one();
+        true
+    }
+
+    /// resolves once a cycle of `root` is triggered, never for roots not controlled
+    pub(crate) async fn triggered(&self, root: &Path) {
+        match self.roots.get(root) {
+            Some(control) => control.trigger.notified().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    pub fn status(&self, root: &Path) -> Option<RootStatus> {
+        let control = self.roots.get(root)?;
+        let (cycles, last_cycle) = control.cycles.lock().unwrap().clone();
+        Some(RootStatus {
+            root: root.to_owned(),
+            paused: control.paused.load(Ordering::Relaxed),
+            cycles,
+            last_cycle,
+        })
+    }
+}
+
+impl CleanerObserver for Control {
+    fn on_cycle_end(&self, report: &CleanReport) {
+        if let Some(control) = self.roots.get(&report.root) {
+            let mut cycles = control.cycles.lock().unwrap();
+            cycles.0 += 1;
+            cycles.1 = Some(report.clone());
+        }
+    }
+}
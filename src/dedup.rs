@@ -0,0 +1,200 @@
+//! deduplication of the warnings a misbehaving stream repeats for every fragment
+//!
+//! the first error of a kind on a stream is logged as it happens, later ones of the same kind
+//! on the same stream are only counted until the window ends, then summarized with the last
+//! message as `repeated n times`. a zero window logs everything.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::CleanerError;
+
+type Key = (Option<String>, &'static str);
+
+#[derive(Debug)]
+struct Repeats {
+    since: Instant,
+    /// suppressed since the logged one
+    count: u64,
+    last: String,
+}
+
+/// per stream and error kind log deduplication, shared by the clones of a cleaner
+#[derive(Debug)]
+pub(crate) struct LogDedup {
+    window: Duration,
+    seen: Mutex<HashMap<Key, Repeats>>,
+}
+
+impl LogDedup {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// log `message` about `error` unless the same kind of error on `stream` was logged
+    /// within the window
+    pub(crate) fn warn(
+        &self,
+        stream: Option<&str>,
+        error: &CleanerError,
+        message: fmt::Arguments<'_>,
+    ) {
+        if self.window.is_zero() {
+            tracing::warn!("{}", message);
+            return;
+        }
+        let now = Instant::now();
+        let key = (stream.map(str::to_owned), error.kind());
+        let mut seen = self.seen.lock().unwrap();
+        if let Some(repeats) = seen.get_mut(&key) {
+            if now.duration_since(repeats.since) < self.window {
+                repeats.count += 1;
+                repeats.last = message.to_string();
+                return;
+            }
+        }
+        if let Some(repeats) = seen.remove(&key) {
+            summarize(&key, &repeats);
+        }
+        tracing::warn!("{}", message);
+        seen.insert(
+            key,
+            Repeats {
+                since: now,
+                count: 0,
+                last: String::new(),
+            },
+        );
+    }
+
+    /// summarize the errors whose window ended, called after every cycle
+    pub(crate) fn flush(&self) {
+        let now = Instant::now();
+        self.seen.lock().unwrap().retain(|key, repeats| {
+            let open = now.duration_since(repeats.since) < self.window;
+            if !open {
+                summarize(key, repeats);
+            }
+            open
+        });
+    }
+}
+
+impl Drop for LogDedup {
+    /// the repeats of the last window are summarized when the cleaner goes away
+    fn drop(&mut self) {
+        let seen = self.seen.get_mut().unwrap_or_else(|e| e.into_inner());
+        for (key, repeats) in seen.drain() {
+            summarize(&key, &repeats);
+        }
+    }
+}
+
+fn summarize((stream, kind): &Key, repeats: &Repeats) {
+    if repeats.count == 0 {
+        return;
+    }
+    let stream = stream
+        .as_ref()
+        .map(|s| format!(" on {}", s))
+        .unwrap_or_default();
+    tracing::warn!(
+        "{} errors{} repeated {} times in the last {}s, last - {}",
+        kind,
+        stream,
+        repeats.count,
+        repeats.since.elapsed().as_secs(),
+        repeats.last
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::PathBuf, sync::Arc};
+
+    use super::*;
+
+    /// the lines logged while running `f`
+    fn logged(f: impl FnOnce()) -> Vec<String> {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || Capture(writer.clone()))
+            .with_ansi(false)
+            .without_time()
+            .with_level(false)
+            .with_target(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        let buffer = buffer.lock().unwrap();
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .collect()
+    }
+
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn not_found(name: &str) -> CleanerError {
+        CleanerError::io(PathBuf::from(name), io::ErrorKind::NotFound.into())
+    }
+
+    #[test]
+    fn repeats_are_summarized() {
+        let lines = logged(|| {
+            let dedup = LogDedup::new(Duration::from_secs(3600));
+            for name in ["a-1.ts", "a-2.ts", "a-3.ts"] {
+                dedup.warn(Some("a"), &not_found(name), format_args!("lost {}", name));
+            }
+            // the window is still open, the summary waits for the drop
+            dedup.flush();
+            assert_eq!(dedup.seen.lock().unwrap().len(), 1);
+        });
+        assert_eq!(lines[0], "lost a-1.ts");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("io errors on a repeated 2 times"));
+        assert!(lines[1].ends_with("last - lost a-3.ts"));
+    }
+
+    #[test]
+    fn streams_and_kinds_are_counted_apart() {
+        let lines = logged(|| {
+            let dedup = LogDedup::new(Duration::from_secs(3600));
+            let config = CleanerError::Config("bad".into());
+            dedup.warn(Some("a"), &not_found("a-1.ts"), format_args!("a io"));
+            dedup.warn(Some("b"), &not_found("b-1.ts"), format_args!("b io"));
+            dedup.warn(Some("a"), &config, format_args!("a config"));
+            dedup.warn(None, &config, format_args!("config"));
+        });
+        assert_eq!(lines, ["a io", "b io", "a config", "config"]);
+    }
+
+    #[test]
+    fn a_zero_window_logs_everything() {
+        let lines = logged(|| {
+            let dedup = LogDedup::new(Duration::ZERO);
+            for _ in 0..3 {
+                dedup.warn(Some("a"), &not_found("a-1.ts"), format_args!("lost"));
+            }
+        });
+        assert_eq!(lines, ["lost", "lost", "lost"]);
+    }
+}
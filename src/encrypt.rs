@@ -0,0 +1,176 @@
+//! at rest encryption of archived fragments
+//!
+//! fragments are sealed with aes-256-gcm, the stored file is a random 12 byte nonce followed
+//! by the ciphertext and its tag, named `<name>.enc`. sealing and opening need the
+//! `encryption` feature, keys can be loaded either way.
+//!
+//! keys come from a file or from aws kms, as a data key generated with
+//! `aws kms generate-data-key --key-spec AES_256` whose encrypted blob is stored in a file and
+//! decrypted by kms at startup, which needs the `kms` feature. only the encrypted blob ever
+//! touches the disk.
+
+use std::{fmt, io, path::Path};
+
+use crate::{CleanerError, Result};
+
+/// a 256 bit key, never printed
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// read a key file holding either 32 raw bytes or 64 hex digits
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path).map_err(|e| CleanerError::io(path, e))?;
+        let invalid = || {
+            CleanerError::io(
+                path,
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected 32 raw bytes or 64 hex digits",
+                ),
+            )
+        };
+        if let Ok(key) = <[u8; 32]>::try_from(contents.as_slice()) {
+            return Ok(Self(key));
+        }
+        let hex = std::str::from_utf8(&contents)
+            .map_err(|_| invalid())?
+            .trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut key = [0; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(key))
+    }
+
+    /// decrypt the kms data key blob in `path` with the credentials of the environment
+    #[cfg(feature = "kms")]
+    pub async fn from_kms(path: &Path) -> Result<Self> {
+        let blob = std::fs::read(path).map_err(|e| CleanerError::io(path, e))?;
+        let config = aws_config::load_from_env().await;
+        let output = aws_sdk_kms::Client::new(&config)
+            .decrypt()
+            .ciphertext_blob(aws_sdk_kms::primitives::Blob::new(blob))
+            .send()
+            .await
+            .map_err(|e| {
+                CleanerError::Storage(format!(
+                    "kms decrypt of {} failed - {}",
+                    path.display(),
+                    aws_sdk_kms::error::DisplayErrorContext(e)
+                ))
+            })?;
+        let key = output
+            .plaintext()
+            .and_then(|key| <[u8; 32]>::try_from(key.as_ref()).ok())
+            .ok_or_else(|| {
+                CleanerError::Config(format!("{} is not an aes-256 data key", path.display()))
+            })?;
+        Ok(Self(key))
+    }
+
+    #[cfg(not(feature = "kms"))]
+    pub async fn from_kms(_path: &Path) -> Result<Self> {
+        Err(CleanerError::Config(
+            "kms data keys need the kms feature".to_owned(),
+        ))
+    }
+}
+
+/// nonce, ciphertext and tag of `plaintext`
+#[cfg(feature = "encryption")]
+pub fn seal(key: &EncryptionKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng},
+        Aes256Gcm,
+    };
+    let cipher = Aes256Gcm::new(&key.0.into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| CleanerError::Storage("aes-gcm encryption failed".to_owned()))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+/// the plaintext of a file produced by [`seal`], fails if it was tampered with
+#[cfg(feature = "encryption")]
+pub fn open(key: &EncryptionKey, sealed: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Nonce,
+    };
+    if sealed.len() < 12 {
+        return Err(CleanerError::Storage("sealed data is truncated".to_owned()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    Aes256Gcm::new(&key.0.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CleanerError::Storage("aes-gcm decryption failed".to_owned()))
+}
+
+#[cfg(not(feature = "encryption"))]
+pub fn seal(_key: &EncryptionKey, _plaintext: &[u8]) -> Result<Vec<u8>> {
+    Err(CleanerError::Config(
+        "archive encryption needs the encryption feature".to_owned(),
+    ))
+}
+
+#[cfg(not(feature = "encryption"))]
+pub fn open(_key: &EncryptionKey, _sealed: &[u8]) -> Result<Vec<u8>> {
+    Err(CleanerError::Config(
+        "archive encryption needs the encryption feature".to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_file(contents: &[u8]) -> Result<EncryptionKey> {
+        let path = std::env::temp_dir().join(format!("hls-cleaner-key-{}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let key = EncryptionKey::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        key
+    }
+
+    #[test]
+    fn key_files_hold_raw_bytes_or_hex() {
+        let raw = std::array::from_fn::<u8, 32, _>(|i| i as u8);
+        let hex = format!("{}\n", crate::archive::hex(&raw));
+        assert_eq!(key_file(&raw).unwrap(), EncryptionKey::new(raw));
+        assert_eq!(key_file(hex.as_bytes()).unwrap(), EncryptionKey::new(raw));
+        assert!(key_file(b"too short").is_err());
+        assert!(key_file(&[b'g'; 64]).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn sealed_data_opens_with_the_same_key_only() {
+        let key = EncryptionKey::new([7; 32]);
+        let sealed = seal(&key, b"fragment").unwrap();
+        assert_eq!(sealed.len(), 12 + 8 + 16);
+        assert_eq!(open(&key, &sealed).unwrap(), b"fragment");
+        // a fresh nonce every time
+        assert_ne!(seal(&key, b"fragment").unwrap(), sealed);
+        assert!(open(&EncryptionKey::new([8; 32]), &sealed).is_err());
+        let mut tampered = sealed.clone();
+        tampered[20] ^= 1;
+        assert!(open(&key, &tampered).is_err());
+        assert!(open(&key, &sealed[..11]).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::{io, path::PathBuf};
+
+use crate::platform;
+
+pub type Result<T, E = CleanerError> = std::result::Result<T, E>;
+
+/// errors surfaced by the library api
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CleanerError {
+    #[error("unable to parse playlist {path}")]
+    PlaylistParse {
+        path: PathBuf,
+        #[source]
+        source: hls_m3u8::Error,
+    },
+
+    #[error("playlist {path} has no segments")]
+    EmptyPlaylist { path: PathBuf },
+
+    #[error("invalid segment name {name} - {reason}")]
+    InvalidSegmentName { name: String, reason: String },
+
+    #[error("io error on {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("storage error - {0}")]
+    Storage(String),
+
+    #[error("invalid configuration - {0}")]
+    Config(String),
+
+    #[error("another cleaner holds {path}{}", holder.map(|pid| format!(", pid {}", pid)).unwrap_or_default())]
+    Locked { path: PathBuf, holder: Option<u32> },
+
+    #[error("{path} is on another filesystem than the root")]
+    OtherDevice { path: PathBuf },
+
+    #[error("{path} is immutable or append-only")]
+    Protected { path: PathBuf },
+
+    #[error("{path} has an owner or permissions the policy does not allow removing")]
+    Foreign { path: PathBuf },
+
+    #[error("hook {hook} failed - {message}")]
+    Hook { hook: String, message: String },
+
+    #[error("{cycles} cleanup cycles failed in a row")]
+    FailedCycles {
+        cycles: u32,
+        #[source]
+        last: Box<CleanerError>,
+    },
+}
+
+impl CleanerError {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn invalid_name(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::InvalidSegmentName {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// snake case name of the variant, `io`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PlaylistParse { .. } => "playlist_parse",
+            Self::EmptyPlaylist { .. } => "empty_playlist",
+            Self::InvalidSegmentName { .. } => "invalid_segment_name",
+            Self::Io { .. } => "io",
+            Self::Storage(_) => "storage",
+            Self::Config(_) => "config",
+            Self::Locked { .. } => "locked",
+            Self::OtherDevice { .. } => "other_device",
+            Self::Protected { .. } => "protected",
+            Self::Foreign { .. } => "foreign",
+            Self::Hook { .. } => "hook",
+            Self::FailedCycles { .. } => "failed_cycles",
+        }
+    }
+
+    /// the file or directory does not exist (any more)
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::Io { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+
+    /// whether the same operation may succeed on a later cycle
+    ///
+    /// io and storage failures are usually transient (a file rotated away mid-read, an nfs
+    /// hiccup) and playlists may be caught mid-write, whereas naming and configuration errors
+    /// will not fix themselves.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::PlaylistParse { .. } | Self::EmptyPlaylist { .. } => true,
+            Self::Io { source, .. } => {
+                platform::is_sharing_violation(source)
+                    || !matches!(
+                        source.kind(),
+                        io::ErrorKind::PermissionDenied | io::ErrorKind::InvalidInput
+                    )
+            }
+            Self::Storage(_) | Self::Locked { .. } | Self::Hook { .. } => true,
+            Self::InvalidSegmentName { .. }
+            | Self::Config(_)
+            | Self::OtherDevice { .. }
+            | Self::Protected { .. }
+            | Self::Foreign { .. }
+            | Self::FailedCycles { .. } => false,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! cleanup events as newline delimited json, for operators following a running daemon
+
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    report, serde_time, CleanReport, CleanerError, CleanerObserver, Reason, SegmentInfo, SkipReason,
+};
+
+/// one line of the event stream, tagged by `event`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Delete {
+        #[serde(serialize_with = "serde_time::unix_secs")]
+        at: SystemTime,
+        path: &'a PathBuf,
+        stream: &'a str,
+        sequence: u32,
+        size: u64,
+        reason: &'static str,
+    },
+    Skip {
+        #[serde(serialize_with = "serde_time::unix_secs")]
+        at: SystemTime,
+        path: &'a PathBuf,
+        stream: &'a str,
+        sequence: u32,
+        reason: &'static str,
+    },
+    Error {
+        #[serde(serialize_with = "serde_time::unix_secs")]
+        at: SystemTime,
+        /// the stream the error belongs to, if it is known
+        stream: Option<&'a str>,
+        message: String,
+        retryable: bool,
+    },
+    CycleEnd {
+        #[serde(serialize_with = "serde_time::unix_secs")]
+        at: SystemTime,
+        report: &'a CleanReport,
+    },
+}
+
+/// observer broadcasting every event as a json line to its subscribers
+///
+/// events are dropped while nobody is subscribed, a subscriber that falls more than
+/// `capacity` lines behind misses the oldest ones.
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    tx: broadcast::Sender<Arc<str>>,
+}
+
+impl EventStream {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            tx: broadcast::channel(capacity).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<str>> {
+        self.tx.subscribe()
+    }
+
+    fn send(&self, event: Event) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        match serde_json::to_string(&event) {
+            Ok(line) => {
+                let _ = self.tx.send(line.into());
+            }
+            Err(e) => tracing::warn!("unable to serialize event - {}", e),
+        }
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl CleanerObserver for EventStream {
+    fn on_delete(&self, segment: &SegmentInfo, reason: Reason) {
+        self.send(Event::Delete {
+            at: SystemTime::now(),
+            path: &segment.path,
+            stream: &segment.stream,
+            sequence: segment.sequence,
+            size: segment.size,
+            reason: reason.name(),
+        });
+    }
+
+    fn on_skip(&self, segment: &SegmentInfo, reason: SkipReason) {
+        self.send(Event::Skip {
+            at: SystemTime::now(),
+            path: &segment.path,
+            stream: &segment.stream,
+            sequence: segment.sequence,
+            reason: reason.name(),
+        });
+    }
+
+    fn on_error(&self, stream: Option<&str>, error: &CleanerError) {
+        self.send(Event::Error {
+            at: SystemTime::now(),
+            stream,
+            message: report::error_chain(error),
+            retryable: error.is_retryable(),
+        });
+    }
+
+    fn on_cycle_end(&self, report: &CleanReport) {
+        self.send(Event::CycleEnd {
+            at: SystemTime::now(),
+            report,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn errors_carry_their_stream() {
+        let events = EventStream::new(4);
+        let mut rx = events.subscribe();
+        let error = CleanerError::io("/hls/live-1.ts", io::ErrorKind::NotFound.into());
+        events.on_error(Some("live"), &error);
+        events.on_error(None, &error);
+        let line: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(line["event"], "error");
+        assert_eq!(line["stream"], "live");
+        let line: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert!(line["stream"].is_null());
+    }
+}
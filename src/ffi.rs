@@ -0,0 +1,252 @@
+//! c abi over the library api, enabled by the `ffi` feature
+//!
+//! a handle is created with `hls_cleaner_new`, tuned with `hls_cleaner_configure`, driven with
+//! `hls_cleaner_clean_once` and released with `hls_cleaner_free`. functions returning `int`
+//! return `HLS_CLEANER_OK` on success and a negative code otherwise, the message of the last
+//! failure is available from `hls_cleaner_last_error`. see `include/hls_cleaner.h`.
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    ptr,
+    time::Duration,
+};
+
+use crate::{storage::LockGuard, Cleaner, CleanerError, Policy};
+
+pub const HLS_CLEANER_OK: c_int = 0;
+pub const HLS_CLEANER_ERR_NULL: c_int = -1;
+pub const HLS_CLEANER_ERR_INVALID_ARG: c_int = -2;
+pub const HLS_CLEANER_ERR_CLEAN: c_int = -3;
+pub const HLS_CLEANER_ERR_PANIC: c_int = -4;
+
+/// opaque handle
+///
+/// the cleaner is built once and kept across cycles so its lifecycle, dedup and backoff state
+/// carries over, it is only rebuilt when a key changes. the root lock is taken by the first
+/// cycle and held until the handle is freed or reconfigured.
+pub struct HlsCleaner {
+    root: PathBuf,
+    policy: Policy,
+    lock: Option<LockGuard>,
+    cleaner: Cleaner,
+    runtime: tokio::runtime::Runtime,
+    last_error: Option<CString>,
+}
+
+/// counters of one cycle, a flattened [`CleanReport`](crate::CleanReport)
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HlsCleanReport {
+    pub scanned: u64,
+    pub skipped: u64,
+    pub deleted: u64,
+    pub errors: u64,
+    pub bytes_freed: u64,
+}
+
+impl HlsCleaner {
+    fn set_error(&mut self, message: impl Into<String>) {
+        self.last_error = CString::new(message.into().replace('\0', " ")).ok();
+    }
+
+    fn configure(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "root" => self.root = PathBuf::from(value),
+            "orphan_max_age_secs" => {
+                let secs = value
+                    .parse::<u64>()
+                    .map_err(|e| format!("invalid orphan_max_age_secs {} - {}", value, e))?;
+                self.policy.orphan_max_age = Duration::from_secs(secs);
+            }
+            "action" => {
+                self.policy.action = value.parse().map_err(|e: CleanerError| e.to_string())?
+            }
+            "archive_template" => {
+                crate::archive::check_template(value).map_err(|e| e.to_string())?;
+                self.policy.archive_template = value.to_owned();
+            }
+            _ => return Err(format!("unknown key {}", key)),
+        }
+        self.lock = None;
+        self.cleaner = build(&self.root, &self.policy);
+        Ok(())
+    }
+
+    fn clean_once(&mut self) -> Result<HlsCleanReport, String> {
+        if self.lock.is_none() {
+            self.lock = Some(
+                self.cleaner
+                    .lock()
+                    .map_err(|e| crate::report::error_chain(&e))?,
+            );
+        }
+        let report = self
+            .runtime
+            .block_on(self.cleaner.clean_once())
+            .map_err(|e| crate::report::error_chain(&e))?;
+        Ok(HlsCleanReport {
+            scanned: report.scanned as u64,
+            skipped: report.skipped as u64,
+            deleted: report.deleted as u64,
+            errors: report.errors.len() as u64,
+            bytes_freed: report.bytes_freed,
+        })
+    }
+}
+
+fn build(root: &Path, policy: &Policy) -> Cleaner {
+    Cleaner::builder().root(root).policy(policy.clone()).build()
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    match s.is_null() {
+        true => None,
+        false => CStr::from_ptr(s).to_str().ok(),
+    }
+}
+
+/// create a cleaner for `root` with the default policy, returns null on failure
+///
+/// # Safety
+/// `root` must be null or a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hls_cleaner_new(root: *const c_char) -> *mut HlsCleaner {
+    let Some(root) = str_arg(root) else {
+        return ptr::null_mut();
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(_) => return ptr::null_mut(),
+    };
+    let root = PathBuf::from(root);
+    let policy = Policy::default();
+    Box::into_raw(Box::new(HlsCleaner {
+        cleaner: build(&root, &policy),
+        lock: None,
+        root,
+        policy,
+        runtime,
+        last_error: None,
+    }))
+}
+
+/// set a configuration key, one of `root`, `orphan_max_age_secs`, `action` or
+/// `archive_template`
+///
+/// the cleaner is rebuilt with the new setting and the root lock released, state of
+/// previous cycles starts over.
+///
+/// # Safety
+/// `cleaner` must come from `hls_cleaner_new`, `key` and `value` must be valid nul terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn hls_cleaner_configure(
+    cleaner: *mut HlsCleaner,
+    key: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let Some(cleaner) = cleaner.as_mut() else {
+        return HLS_CLEANER_ERR_NULL;
+    };
+    let (Some(key), Some(value)) = (str_arg(key), str_arg(value)) else {
+        cleaner.set_error("key and value must be valid utf-8 strings");
+        return HLS_CLEANER_ERR_INVALID_ARG;
+    };
+    match cleaner.configure(key, value) {
+        Ok(()) => HLS_CLEANER_OK,
+        Err(e) => {
+            cleaner.set_error(e);
+            HLS_CLEANER_ERR_INVALID_ARG
+        }
+    }
+}
+
+/// run a single cleanup cycle, blocking the calling thread
+///
+/// `report` may be null, otherwise it is filled on success.
+///
+/// # Safety
+/// `cleaner` must come from `hls_cleaner_new` and `report` must be null or point to writable
+/// memory.
+#[no_mangle]
+pub unsafe extern "C" fn hls_cleaner_clean_once(
+    cleaner: *mut HlsCleaner,
+    report: *mut HlsCleanReport,
+) -> c_int {
+    let Some(cleaner) = cleaner.as_mut() else {
+        return HLS_CLEANER_ERR_NULL;
+    };
+    match catch_unwind(AssertUnwindSafe(|| cleaner.clean_once())) {
+        Ok(Ok(r)) => {
+            if !report.is_null() {
+                *report = r;
+            }
+            HLS_CLEANER_OK
+        }
+        Ok(Err(e)) => {
+            cleaner.set_error(e);
+            HLS_CLEANER_ERR_CLEAN
+        }
+        Err(_) => {
+            cleaner.set_error("panic during cleanup");
+            HLS_CLEANER_ERR_PANIC
+        }
+    }
+}
+
+/// message of the last failure on this handle, null if none, valid until the next call
+///
+/// # Safety
+/// `cleaner` must be null or come from `hls_cleaner_new`.
+#[no_mangle]
+pub unsafe extern "C" fn hls_cleaner_last_error(cleaner: *const HlsCleaner) -> *const c_char {
+    cleaner
+        .as_ref()
+        .and_then(|c| c.last_error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// release a handle, null is ignored
+///
+/// # Safety
+/// `cleaner` must be null or come from `hls_cleaner_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hls_cleaner_free(cleaner: *mut HlsCleaner) {
+    if !cleaner.is_null() {
+        drop(Box::from_raw(cleaner));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_handle_keeps_its_cleaner_and_lock_across_cycles() {
+        let root = std::env::temp_dir().join(format!("hls-cleaner-ffi-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = CString::new(root.to_str().unwrap()).unwrap();
+        unsafe {
+            let first = hls_cleaner_new(path.as_ptr());
+            let second = hls_cleaner_new(path.as_ptr());
+            let mut report = HlsCleanReport::default();
+            assert_eq!(hls_cleaner_clean_once(first, &mut report), HLS_CLEANER_OK);
+            assert_eq!(hls_cleaner_clean_once(first, &mut report), HLS_CLEANER_OK);
+            // the first handle still holds the root
+            assert_eq!(
+                hls_cleaner_clean_once(second, &mut report),
+                HLS_CLEANER_ERR_CLEAN
+            );
+            assert!(!hls_cleaner_last_error(second).is_null());
+            hls_cleaner_free(first);
+            assert_eq!(hls_cleaner_clean_once(second, &mut report), HLS_CLEANER_OK);
+            hls_cleaner_free(second);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+//! grpc control interface of a daemon, the service of `proto/cleaner.proto`
+//!
+//! [`ControlService`] drives the cleaners of a daemon through their [`Control`] and answers the
+//! stats and purge requests with cleaners of its own for the same roots. the interface is not
+//! authenticated, bind it to a private address.
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use tonic::{transport::server::TcpIncoming, Request, Response, Status};
+
+use crate::{
+    control::{Control, RootStatus},
+    report, serde_time, CancellationToken, CleanReport, Cleaner, CleanerError,
+};
+
+/// messages and server generated from `proto/cleaner.proto`
+pub mod proto {
+    tonic::include_proto!("hls_cleaner.v1");
+}
+
+use proto::cleaner_server::CleanerServer;
+
+/// the `hls_cleaner.v1.Cleaner` service over the roots of `cleaners`
+#[derive(Debug, Clone)]
+pub struct ControlService {
+    cleaners: Vec<Cleaner>,
+    control: Arc<Control>,
+}
+
+impl ControlService {
+    /// `control` is the one the running cleaners were built with
+    pub fn new(cleaners: Vec<Cleaner>, control: Arc<Control>) -> Self {
+        Self { cleaners, control }
+    }
+
+    pub fn into_server(self) -> CleanerServer<Self> {
+        CleanerServer::new(self)
+    }
+
+    /// the cleaners of `root`, every one when empty
+    fn select(&self, root: &str) -> Result<Vec<Cleaner>, Status> {
+        if root.is_empty() {
+            return Ok(self.cleaners.clone());
+        }
+        match self.cleaners.iter().find(|c| c.root() == Path::new(root)) {
+            Some(cleaner) => Ok(vec![cleaner.clone()]),
+            None => Err(Status::not_found(format!("{} is not cleaned here", root))),
+        }
+    }
+
+    /// apply `op` to the control of the selected roots and reply with their status
+    fn control(
+        &self,
+        request: Request<proto::RootRequest>,
+        op: impl Fn(&Control, &Path) -> bool,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        let roots = self
+            .select(&request.into_inner().root)?
+            .iter()
+            .filter(|cleaner| op(&self.control, cleaner.root()))
+            .filter_map(|cleaner| self.control.status(cleaner.root()))
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(proto::StatusReply { roots }))
+    }
+}
+
+/// serve `service` on `addr` until `shutdown` fires, fails right away when `addr` is taken
+pub fn serve(
+    addr: SocketAddr,
+    service: ControlService,
+    shutdown: CancellationToken,
+) -> std::io::Result<()> {
+    let incoming = TcpIncoming::bind(addr)?;
+    tracing::info!("serving grpc on {}", addr);
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(service.into_server())
+            .serve_with_incoming_shutdown(incoming, shutdown.cancelled_owned())
+            .await;
+        if let Err(e) = result {
+            tracing::error!("grpc server failed - {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// run `op` on a blocking thread for each cleaner, the filesystem is walked synchronously
+async fn blocking<T: Send + 'static>(
+    cleaners: Vec<Cleaner>,
+    op: impl Fn(&Cleaner) -> crate::Result<T> + Clone + Send + 'static,
+) -> Result<Vec<(Cleaner, T)>, Status> {
+    let mut results = Vec::with_capacity(cleaners.len());
+    for cleaner in cleaners {
+        let op = op.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = op(&cleaner);
+            result.map(|r| (cleaner, r))
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        results.push(result.map_err(status)?);
+    }
+    Ok(results)
+}
+
+fn status(error: CleanerError) -> Status {
+    Status::internal(report::error_chain(&error))
+}
+
+#[tonic::async_trait]
+impl proto::cleaner_server::Cleaner for ControlService {
+    async fn trigger_cycle(
+        &self,
+        request: Request<proto::RootRequest>,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        self.control(request, Control::trigger)
+    }
+
+    async fn pause(
+        &self,
+        request: Request<proto::RootRequest>,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        self.control(request, Control::pause)
+    }
+
+    async fn resume(
+        &self,
+        request: Request<proto::RootRequest>,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        self.control(request, Control::resume)
+    }
+
+    async fn get_status(
+        &self,
+        request: Request<proto::RootRequest>,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        self.control(request, |_, _| true)
+    }
+
+    async fn get_stream_stats(
+        &self,
+        request: Request<proto::RootRequest>,
+    ) -> Result<Response<proto::StreamStatsReply>, Status> {
+        let cleaners = self.select(&request.into_inner().root)?;
+        let roots = blocking(cleaners, Cleaner::stream_stats)
+            .await?
+            .into_iter()
+            .map(|(cleaner, stats)| proto::RootStreamStats {
+                root: cleaner.root().display().to_string(),
+                streams: stats.into_iter().map(Into::into).collect(),
+            })
+            .collect();
+        Ok(Response::new(proto::StreamStatsReply { roots }))
+    }
+
+    async fn purge_stream(
+        &self,
+        request: Request<proto::PurgeStreamRequest>,
+    ) -> Result<Response<proto::PurgeStreamReply>, Status> {
+        let request = request.into_inner();
+        if request.stream.is_empty() {
+            return Err(Status::invalid_argument("stream is required"));
+        }
+        let cleaners = self.select(&request.root)?;
+        let (stream, dry_run) = (request.stream, request.dry_run);
+        let roots = blocking(cleaners, move |c| c.purge_stream(&stream, dry_run))
+            .await?
+            .into_iter()
+            .map(|(cleaner, report)| proto::RootPurge {
+                root: cleaner.root().display().to_string(),
+                removed: report
+                    .removed
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect(),
+                errors: report.errors,
+            })
+            .collect();
+        Ok(Response::new(proto::PurgeStreamReply { roots }))
+    }
+}
+
+impl From<RootStatus> for proto::RootStatus {
+    fn from(status: RootStatus) -> Self {
+        Self {
+            root: status.root.display().to_string(),
+            paused: status.paused,
+            cycles: status.cycles,
+            last_cycle: status.last_cycle.map(Into::into),
+        }
+    }
+}
+
+impl From<CleanReport> for proto::CycleSummary {
+    fn from(report: CleanReport) -> Self {
+        Self {
+            started_at: serde_time::to_unix_secs(report.started_at),
+            duration: report.duration.as_secs_f64(),
+            scanned: report.scanned as u64,
+            skipped: report.skipped as u64,
+            deleted: report.deleted as u64,
+            bytes_freed: report.bytes_freed,
+            deferred: report.deferred as u64,
+            errors: report
+                .errors
+                .into_iter()
+                .map(|e| proto::CycleError {
+                    stream: e.stream,
+                    message: e.message,
+                    retryable: e.retryable,
+                })
+                .collect(),
+            cancelled: report.cancelled,
+            lock_contended: report.lock_contended,
+            lock_lost: report.lock_lost,
+            stuck: report
+                .stuck
+                .into_iter()
+                .map(|s| proto::StuckStream {
+                    stream: s.stream,
+                    playlist: s.playlist.display().to_string(),
+                    playlist_updated: serde_time::to_unix_secs(s.playlist_updated),
+                    newest_segment: serde_time::to_unix_secs(s.newest_segment),
+                    target_duration: s.target_duration.as_secs_f64(),
+                })
+                .collect(),
+            capacity: report.capacity.map(|c| proto::Capacity {
+                total_bytes: c.total_bytes,
+                available_bytes: c.available_bytes,
+                growth_rate: c.growth_rate,
+                reclaim_rate: c.reclaim_rate,
+                time_to_full: c.time_to_full.map(|t| t.as_secs_f64()),
+            }),
+        }
+    }
+}
+
+impl From<crate::stats::StreamStats> for proto::StreamStats {
+    fn from(stats: crate::stats::StreamStats) -> Self {
+        Self {
+            stream: stats.stream,
+            segments: stats.segments as u64,
+            playlist: stats.playlist.map(|p| p.display().to_string()),
+            window_segments: stats.window_segments.map(|n| n as u64),
+            window: stats.window.map(|w| w.as_secs_f64()),
+            average_segment_duration: stats.average_segment_duration.map(|d| d.as_secs_f64()),
+            target_duration: stats.target_duration.map(|d| d.as_secs_f64()),
+            media_sequence: stats.media_sequence,
+            ended: stats.ended,
+            playlist_updated: stats.playlist_updated.map(serde_time::to_unix_secs),
+            newest_segment: stats.newest_segment.map(serde_time::to_unix_secs),
+            playlist_error: stats.playlist_error,
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! external programs and services consulted by the cleaner
+//!
+//! a [`CycleHook`] set with [`CleanerBuilder::cycle_hook`](crate::CleanerBuilder::cycle_hook)
+//! is told about every completed cycle with its [`CleanReport`] as json, for bookkeeping the
+//! cleaner does not know about. [`CommandHook`] runs a program with the report on its stdin.
+//!
+//! an [`ApprovalHook`] set with
+//! [`CleanerBuilder::approval_hook`](crate::CleanerBuilder::approval_hook) is asked about every
+//! planned action of a cycle before it is applied, with the action as [`ApprovalRequest`] json.
+//! [`CommandHook`] runs a program with the json on its stdin, an exit status other than zero
+//! vetoes the action. [`HttpHook`] posts the json to a url, a status other than 2xx vetoes it,
+//! it needs the `webhooks` feature.
+//!
+//! vetoed fragments are kept and reported with
+//! [`SkipReason::Vetoed`](crate::SkipReason::Vetoed), the next cycle asks again. a hook that
+//! fails or does not answer in time vetoes the action as well and the rest of the cycle is held
+//! back without asking, so a dead hook costs one timeout per cycle rather than one per fragment.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{remote::BoxFuture, serde_time, CleanReport, CleanerError, PlannedAction, Result};
+
+/// what a hook is told about a planned action
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalRequest<'a> {
+    pub root: &'a Path,
+    pub path: &'a Path,
+    pub stream: &'a str,
+    pub sequence: u32,
+    pub size: u64,
+    #[serde(serialize_with = "serde_time::opt_unix_secs")]
+    pub modified: Option<SystemTime>,
+    /// `delete`, `trash` or `archive`
+    pub action: String,
+    pub reason: &'static str,
+}
+
+impl<'a> ApprovalRequest<'a> {
+    pub fn new(root: &'a Path, planned: &'a PlannedAction) -> Self {
+        Self {
+            root,
+            path: &planned.segment.path,
+            stream: &planned.segment.stream,
+            sequence: planned.segment.sequence,
+            size: planned.segment.size,
+            modified: planned.segment.modified,
+            action: planned.action.to_string(),
+            reason: planned.reason.name(),
+        }
+    }
+}
+
+pub trait ApprovalHook: Send + Sync {
+    /// whether the action of `request` may go ahead, an error vetoes it too
+    fn approve<'a>(&'a self, request: &'a ApprovalRequest<'a>) -> BoxFuture<'a, Result<bool>>;
+}
+
+pub trait CycleHook: Send + Sync {
+    /// `report` is the outcome of a cycle that just ended, errors are logged
+    fn after_cycle<'a>(&'a self, report: &'a CleanReport) -> BoxFuture<'a, Result<()>>;
+}
+
+/// a program run once per request or report with the json on its stdin
+#[derive(Debug, Clone)]
+pub struct CommandHook {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandHook {
+    /// the program is killed once it runs longer than `timeout`
+    pub fn new(program: impl Into<PathBuf>, timeout: Duration) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout,
+        }
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// the exit status of the program after reading `input`
+    async fn run(&self, input: &[u8]) -> Result<ExitStatus> {
+        let error = |e: std::io::Error| self.error(e.to_string());
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(error)?;
+        let run = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                // a program deciding without reading its input closes the pipe early
+                match stdin.write_all(input).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e),
+                    _ => {}
+                }
+            }
+            child.wait().await
+        };
+        match tokio::time::timeout(self.timeout, run).await {
+            Ok(status) => status.map_err(error),
+            Err(_) => Err(self.error(format!("no answer within {:?}", self.timeout))),
+        }
+    }
+
+    fn error(&self, message: String) -> CleanerError {
+        CleanerError::Hook {
+            hook: self.program.display().to_string(),
+            message,
+        }
+    }
+}
+
+impl ApprovalHook for CommandHook {
+    fn approve<'a>(&'a self, request: &'a ApprovalRequest<'a>) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move { Ok(self.run(&json(request)?).await?.success()) })
+    }
+}
+
+impl CycleHook for CommandHook {
+    fn after_cycle<'a>(&'a self, report: &'a CleanReport) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            match self.run(&json(report)?).await? {
+                status if status.success() => Ok(()),
+                status => Err(self.error(status.to_string())),
+            }
+        })
+    }
+}
+
+/// a url the json is posted to
+#[cfg(feature = "webhooks")]
+#[derive(Debug, Clone)]
+pub struct HttpHook {
+    http: reqwest::Client,
+    url: String,
+}
+
+#[cfg(feature = "webhooks")]
+impl HttpHook {
+    /// requests not answered within `timeout` fail
+    pub fn new(url: &str, timeout: Duration) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| CleanerError::Config(format!("http client - {}", e)))?;
+        Ok(Self {
+            http,
+            url: url.to_owned(),
+        })
+    }
+
+    /// whether the url answered `body` with a 2xx status
+    async fn post(&self, body: Vec<u8>) -> Result<bool> {
+        let response = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| CleanerError::Hook {
+                hook: self.url.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(response.status().is_success())
+    }
+}
+
+#[cfg(feature = "webhooks")]
+impl ApprovalHook for HttpHook {
+    fn approve<'a>(&'a self, request: &'a ApprovalRequest<'a>) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move { self.post(json(request)?).await })
+    }
+}
+
+fn json(value: &impl Serialize) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|e| CleanerError::Config(format!("hook payload - {}", e)))
+}
@@ -0,0 +1,200 @@
+//! sqlite index of archived content, answering where a time span of a stream ended up
+//!
+//! [`ArchiveIndex`] is an observer, every archived or stitched file becomes one row with the
+//! sequence range and the time span of the fragments it holds, later updated with its remote
+//! location once uploaded and its expiry once the remote copy is gone. the manifests stay the
+//! source of truth, the index only spans the cycles it was registered for.
+//!
+//! the time span is taken from the modification times of the fragments, which the packager
+//! sets when it finishes writing them.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::{
+    archive::{ExpiryRecord, ManifestEntry, UploadRecord},
+    serde_time, CleanerError, CleanerObserver, Result, SegmentInfo,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS archive (
+        path TEXT PRIMARY KEY,
+        stream TEXT NOT NULL,
+        first_sequence INTEGER NOT NULL,
+        last_sequence INTEGER NOT NULL,
+        start_time INTEGER,
+        end_time INTEGER,
+        size INTEGER NOT NULL,
+        stored_size INTEGER NOT NULL,
+        sha256 TEXT,
+        archived_at INTEGER NOT NULL,
+        remote TEXT,
+        uploaded_at INTEGER,
+        expired_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS archive_stream_time ON archive (stream, start_time);
+";
+
+/// one archived file, as found by [`ArchiveIndex::find`]
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexEntry {
+    /// where the file was archived to, removed locally once uploaded
+    pub path: PathBuf,
+    pub stream: String,
+    pub first_sequence: u32,
+    pub last_sequence: u32,
+    /// unix seconds, earliest modification time of its fragments
+    pub start_time: Option<u64>,
+    /// unix seconds, latest modification time of its fragments
+    pub end_time: Option<u64>,
+    pub size: u64,
+    pub stored_size: u64,
+    pub sha256: Option<String>,
+    /// unix seconds
+    pub archived_at: u64,
+    /// url of the uploaded copy
+    pub remote: Option<String>,
+    /// unix seconds
+    pub uploaded_at: Option<u64>,
+    /// unix seconds, the remote copy was deleted
+    pub expired_at: Option<u64>,
+}
+
+/// observer recording archived content in a sqlite database
+#[derive(Debug)]
+pub struct ArchiveIndex {
+    conn: Mutex<Connection>,
+}
+
+impl ArchiveIndex {
+    /// open or create the index at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).map_err(|e| sqlite_error(path, e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| sqlite_error(path, e))?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// the archived files of `stream` overlapping `from..=to`, in time order
+    ///
+    /// files without a time span are never returned.
+    pub fn find(&self, stream: &str, from: SystemTime, to: SystemTime) -> Result<Vec<IndexEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(
+                "SELECT path, stream, first_sequence, last_sequence, start_time, end_time, size,
+                 stored_size, sha256, archived_at, remote, uploaded_at, expired_at FROM archive
+                 WHERE stream = ?1 AND end_time >= ?2 AND start_time <= ?3
+                 ORDER BY start_time, first_sequence",
+            )
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map(
+                params![
+                    stream,
+                    serde_time::to_unix_secs(from),
+                    serde_time::to_unix_secs(to)
+                ],
+                |row| {
+                    Ok(IndexEntry {
+                        path: PathBuf::from(row.get::<_, String>(0)?),
+                        stream: row.get(1)?,
+                        first_sequence: row.get(2)?,
+                        last_sequence: row.get(3)?,
+                        start_time: row.get(4)?,
+                        end_time: row.get(5)?,
+                        size: row.get(6)?,
+                        stored_size: row.get(7)?,
+                        sha256: row.get(8)?,
+                        archived_at: row.get(9)?,
+                        remote: row.get(10)?,
+                        uploaded_at: row.get(11)?,
+                        expired_at: row.get(12)?,
+                    })
+                },
+            )
+            .map_err(query_error)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(query_error)
+    }
+
+    fn record_archive(
+        &self,
+        segments: &[&SegmentInfo],
+        path: &Path,
+        entry: &ManifestEntry,
+    ) -> rusqlite::Result<()> {
+        let (Some(first), Some(last)) = (segments.first(), segments.last()) else {
+            return Ok(());
+        };
+        let times = segments.iter().filter_map(|s| s.modified);
+        let start = times.clone().min().map(serde_time::to_unix_secs);
+        let end = times.max().map(serde_time::to_unix_secs);
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO archive (path, stream, first_sequence, last_sequence,
+             start_time, end_time, size, stored_size, sha256, archived_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                path.to_string_lossy(),
+                first.stream,
+                first.sequence,
+                last.sequence,
+                start,
+                end,
+                entry.size,
+                entry.stored_size,
+                entry.sha256,
+                entry.archived_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// files archived before the index was registered are not updated
+    fn update(&self, path: &Path, sql: &str, params: impl rusqlite::Params) {
+        match self.conn.lock().unwrap().execute(sql, params) {
+            Ok(0) => tracing::debug!("{} is not in the archive index", path.display()),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("unable to update the archive index - {}", e),
+        }
+    }
+}
+
+impl CleanerObserver for ArchiveIndex {
+    fn on_archive(&self, segments: &[&SegmentInfo], path: &Path, entry: &ManifestEntry) {
+        if let Err(e) = self.record_archive(segments, path, entry) {
+            tracing::warn!("unable to index {} - {}", path.display(), e);
+        }
+    }
+
+    fn on_upload(&self, path: &Path, record: &UploadRecord) {
+        self.update(
+            path,
+            "UPDATE archive SET remote = ?1, uploaded_at = ?2 WHERE path = ?3",
+            params![record.uploaded, record.uploaded_at, path.to_string_lossy()],
+        );
+    }
+
+    fn on_remote_expire(&self, path: &Path, record: &ExpiryRecord) {
+        self.update(
+            path,
+            "UPDATE archive SET expired_at = ?1 WHERE path = ?2",
+            params![record.expired_at, path.to_string_lossy()],
+        );
+    }
+}
+
+fn sqlite_error(path: &Path, e: rusqlite::Error) -> CleanerError {
+    CleanerError::Storage(format!("archive index {} - {}", path.display(), e))
+}
+
+fn query_error(e: rusqlite::Error) -> CleanerError {
+    CleanerError::Storage(format!("archive index query - {}", e))
+}
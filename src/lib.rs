@@ -0,0 +1,76 @@
+//! delete all unreferenced ts fragment
+//!
+//! criterias for ts deletion,
+//!
+//! scenario 1:
+//! * ts corresponding playlist file must exist
+//! * ts is not referenced in that playlist
+//! * ts sequence number must be smaller than any other referenced sequence number in that playlist
+//!
+//! scenario 2:
+//! * ts does not have corresponding playlist file
+//! * ts file is older than the policy orphan age (30 minutes by default)
+//!
+//! the cleanup logic is exposed through [`Cleaner`] so it can be embedded in other services,
+//! the `hls-fragment-cleaner` binary is a thin wrapper around it.
+
+pub mod analyze;
+pub mod archive;
+mod backoff;
+mod boundary;
+mod capacity;
+mod cleaner;
+mod clock;
+pub mod control;
+mod dedup;
+pub mod encrypt;
+mod error;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hook;
+#[cfg(feature = "sqlite")]
+pub mod index;
+pub mod lifecycle;
+pub mod lock;
+mod observer;
+mod plan;
+mod platform;
+mod playlist;
+mod policy;
+mod purge;
+#[cfg(feature = "python")]
+mod python;
+pub mod remote;
+mod report;
+mod segment;
+mod serde_time;
+pub mod simulate;
+pub mod stats;
+pub mod storage;
+mod stuck;
+pub mod systemd;
+pub mod trash;
+pub mod validate;
+pub mod vod;
+
+pub use capacity::CapacityForecast;
+pub use cleaner::{Cleaner, CleanerBuilder, LOCK_FILE, NODELETE_MARKER};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use error::{CleanerError, Result};
+pub use observer::{CleanerObserver, Reason, SegmentInfo, SkipReason};
+pub use plan::PlannedAction;
+pub use playlist::{DateRange, Playlist, PlaylistSegment};
+pub use policy::{Action, Ownership, Policy, Shard};
+pub use purge::PurgeReport;
+pub use report::{
+    CleanReport, ReportError, ShadowExample, ShadowReport, StreamReport, SHADOW_EXAMPLES,
+};
+pub use segment::{check_playlist_template, parse_segment_name, DEFAULT_PLAYLIST_TEMPLATE};
+pub use stuck::StuckStream;
+pub use tokio_util::sync::CancellationToken;
+
+/// default directory scanned for fragments
+pub const DEFAULT_ROOT: &str = "/tmp/hls";
@@ -0,0 +1,105 @@
+//! per fragment lifecycle timestamps remembered across cycles
+//!
+//! every evaluated fragment is recorded in the cleaner's [`LifecycleStore`] with the time it
+//! was first seen and the time the playlist first moved past it, which the
+//! [unreferenced grace](crate::Policy::unreferenced_grace) is measured from. the default store
+//! lives in memory and starts over with the process, [`SqliteLifecycle`] keeps the timestamps
+//! in a database so they survive restarts.
+//!
+//! removed fragments are dropped from the store right away, fragments that vanished without
+//! the cleaner are compacted once they were not seen for [`COMPACT_AFTER`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{Result, SegmentInfo};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteLifecycle;
+
+/// fragments not seen for this long are dropped from the store
+pub const COMPACT_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+/// what the store remembers about one fragment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifecycle {
+    pub first_seen: SystemTime,
+    /// first time the playlist no longer referenced the fragment
+    pub unreferenced_since: Option<SystemTime>,
+}
+
+pub trait LifecycleStore: Send + Sync {
+    /// record that the fragment was seen at `now`, `unreferenced` once the playlist moved past
+    /// it, and return what is known about it
+    fn observe(
+        &self,
+        segment: &SegmentInfo,
+        unreferenced: bool,
+        now: SystemTime,
+    ) -> Result<Lifecycle>;
+
+    /// the fragment at `path` was removed
+    fn forget(&self, path: &Path) -> Result<()>;
+
+    /// drop the fragments last seen before `before`, returns how many
+    fn compact(&self, before: SystemTime) -> Result<usize>;
+
+    /// persist the changes of the cycle, stores may batch them until then
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    lifecycle: Lifecycle,
+    last_seen: SystemTime,
+}
+
+/// store kept in memory, the default
+#[derive(Debug, Default)]
+pub struct MemoryLifecycle {
+    segments: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl LifecycleStore for MemoryLifecycle {
+    fn observe(
+        &self,
+        segment: &SegmentInfo,
+        unreferenced: bool,
+        now: SystemTime,
+    ) -> Result<Lifecycle> {
+        let mut segments = self.segments.lock().unwrap();
+        let entry = segments.entry(segment.path.clone()).or_insert(Entry {
+            lifecycle: Lifecycle {
+                first_seen: now,
+                unreferenced_since: None,
+            },
+            last_seen: now,
+        });
+        entry.last_seen = now;
+        if unreferenced && entry.lifecycle.unreferenced_since.is_none() {
+            entry.lifecycle.unreferenced_since = Some(now);
+        }
+        Ok(entry.lifecycle)
+    }
+
+    fn forget(&self, path: &Path) -> Result<()> {
+        self.segments.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn compact(&self, before: SystemTime) -> Result<usize> {
+        let mut segments = self.segments.lock().unwrap();
+        let len = segments.len();
+        segments.retain(|_, e| e.last_seen >= before);
+        Ok(len - segments.len())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use rusqlite::{params, Connection};
+
+use crate::{serde_time, CleanerError, Result, SegmentInfo};
+
+use super::{Lifecycle, LifecycleStore};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS lifecycle (
+        path TEXT PRIMARY KEY,
+        stream TEXT NOT NULL,
+        sequence INTEGER NOT NULL,
+        first_seen INTEGER NOT NULL,
+        unreferenced_since INTEGER,
+        last_seen INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS lifecycle_last_seen ON lifecycle (last_seen);
+";
+
+/// lifecycle store in a sqlite database, the writes of a cycle share one transaction
+#[derive(Debug)]
+pub struct SqliteLifecycle {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteLifecycle {
+    /// open or create the store at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).map_err(|e| sqlite_error(path, e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| sqlite_error(path, e))?;
+        Ok(Self {
+            path: path.to_owned(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// run `f` inside the transaction of the cycle, opening it if needed
+    fn write<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T> {
+        let conn = self.conn.lock().unwrap();
+        let result = match conn.is_autocommit() {
+            true => conn.execute_batch("BEGIN").and_then(|()| f(&conn)),
+            false => f(&conn),
+        };
+        result.map_err(|e| sqlite_error(&self.path, e))
+    }
+}
+
+impl LifecycleStore for SqliteLifecycle {
+    fn observe(
+        &self,
+        segment: &SegmentInfo,
+        unreferenced: bool,
+        now: SystemTime,
+    ) -> Result<Lifecycle> {
+        let now = serde_time::to_unix_secs(now);
+        let path = segment.path.to_string_lossy();
+        self.write(|conn| {
+            conn.query_row(
+                "INSERT INTO lifecycle (path, stream, sequence, first_seen, unreferenced_since,
+                 last_seen) VALUES (?1, ?2, ?3, ?4, ?5, ?4)
+                 ON CONFLICT (path) DO UPDATE SET last_seen = excluded.last_seen,
+                 unreferenced_since = coalesce(unreferenced_since, excluded.unreferenced_since)
+                 RETURNING first_seen, unreferenced_since",
+                params![
+                    path,
+                    segment.stream,
+                    segment.sequence,
+                    now,
+                    unreferenced.then_some(now)
+                ],
+                |row| {
+                    Ok(Lifecycle {
+                        first_seen: serde_time::from_unix_secs(row.get(0)?),
+                        unreferenced_since: row
+                            .get::<_, Option<u64>>(1)?
+                            .map(serde_time::from_unix_secs),
+                    })
+                },
+            )
+        })
+    }
+
+    fn forget(&self, path: &Path) -> Result<()> {
+        self.write(|conn| {
+            conn.execute(
+                "DELETE FROM lifecycle WHERE path = ?1",
+                params![path.to_string_lossy()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn compact(&self, before: SystemTime) -> Result<usize> {
+        self.write(|conn| {
+            conn.execute(
+                "DELETE FROM lifecycle WHERE last_seen < ?1",
+                params![serde_time::to_unix_secs(before)],
+            )
+        })
+    }
+
+    fn flush(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match conn.is_autocommit() {
+            true => Ok(()),
+            false => conn
+                .execute_batch("COMMIT")
+                .map_err(|e| sqlite_error(&self.path, e)),
+        }
+    }
+}
+
+fn sqlite_error(path: &Path, e: rusqlite::Error) -> CleanerError {
+    CleanerError::Storage(format!("lifecycle store {} - {}", path.display(), e))
+}
@@ -0,0 +1,144 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+
+use super::{holder, http_client, DistributedLock};
+use crate::{remote::BoxFuture, CleanerError, Result};
+
+/// lock on a consul kv key through a session with a ttl
+///
+/// the session is created with no lock delay and deletes the key when it is invalidated, so a
+/// standby takes over as soon as the session of a dead holder expires. `CONSUL_HTTP_TOKEN` is
+/// sent as the acl token when set.
+#[derive(Debug)]
+pub struct ConsulLock {
+    http: Client,
+    endpoint: String,
+    token: Option<String>,
+    ttl: Duration,
+    sessions: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct Session {
+    #[serde(rename = "ID")]
+    id: String,
+}
+
+impl ConsulLock {
+    /// `http://127.0.0.1:8500` style agent endpoint, consul accepts ttls of 10s to 24h
+    pub fn new(endpoint: &str, ttl: Duration) -> Result<Self> {
+        if !(Duration::from_secs(10)..=Duration::from_secs(86400)).contains(&ttl) {
+            return Err(CleanerError::Config(format!(
+                "consul session ttl {:?} is not between 10s and 24h",
+                ttl
+            )));
+        }
+        Ok(Self {
+            http: http_client()?,
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            token: std::env::var("CONSUL_HTTP_TOKEN").ok(),
+            ttl,
+            sessions: Mutex::default(),
+        })
+    }
+
+    fn put(&self, path: &str) -> RequestBuilder {
+        let request = self.http.put(format!("{}{}", self.endpoint, path));
+        match &self.token {
+            Some(token) => request.header("X-Consul-Token", token),
+            None => request,
+        }
+    }
+
+    async fn destroy(&self, session: &str) -> Result<()> {
+        send(self.put(&format!("/v1/session/destroy/{}", session))).await?;
+        Ok(())
+    }
+}
+
+impl DistributedLock for ConsulLock {
+    fn acquire<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "Name": "hls-fragment-cleaner",
+                "TTL": format!("{}s", self.ttl.as_secs()),
+                "LockDelay": "0s",
+                "Behavior": "delete",
+            });
+            let session = send(self.put("/v1/session/create").json(&body))
+                .await?
+                .json::<Session>()
+                .await
+                .map_err(consul_error)?
+                .id;
+            let acquired = send(
+                self.put(&format!("/v1/kv/{}?acquire={}", key, session))
+                    .body(holder()),
+            )
+            .await?
+            .json::<bool>()
+            .await
+            .map_err(consul_error)?;
+            match acquired {
+                true => {
+                    self.sessions
+                        .lock()
+                        .unwrap()
+                        .insert(key.to_owned(), session);
+                }
+                false => self.destroy(&session).await?,
+            }
+            Ok(acquired)
+        })
+    }
+
+    fn renew<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let Some(session) = self.sessions.lock().unwrap().get(key).cloned() else {
+                return Ok(false);
+            };
+            let response = self
+                .put(&format!("/v1/session/renew/{}", session))
+                .send()
+                .await
+                .map_err(consul_error)?;
+            match response.status() {
+                // the session was invalidated and the key deleted with it
+                StatusCode::NOT_FOUND => {
+                    self.sessions.lock().unwrap().remove(key);
+                    Ok(false)
+                }
+                status if status.is_success() => Ok(true),
+                status => Err(CleanerError::Storage(format!("consul renew - {}", status))),
+            }
+        })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let Some(session) = self.sessions.lock().unwrap().remove(key) else {
+                return Ok(());
+            };
+            send(self.put(&format!("/v1/kv/{}?release={}", key, session))).await?;
+            self.destroy(&session).await
+        })
+    }
+
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+async fn send(request: RequestBuilder) -> Result<reqwest::Response> {
+    request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(consul_error)
+}
+
+fn consul_error(e: reqwest::Error) -> CleanerError {
+    CleanerError::Storage(format!("consul - {}", e))
+}
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use super::{holder, http_client, DistributedLock};
+use crate::{remote::BoxFuture, CleanerError, Result};
+
+/// lock on an etcd key attached to a lease, through the v3 json gateway
+///
+/// the key is only created if it does not exist, and disappears with the lease once the
+/// holder stops renewing it.
+#[derive(Debug)]
+pub struct EtcdLock {
+    http: Client,
+    endpoint: String,
+    ttl: Duration,
+    leases: Mutex<HashMap<String, String>>,
+}
+
+impl EtcdLock {
+    /// `http://127.0.0.1:2379` style endpoint
+    pub fn new(endpoint: &str, ttl: Duration) -> Result<Self> {
+        if ttl < Duration::from_secs(2) {
+            return Err(CleanerError::Config(format!(
+                "etcd lease ttl {:?} is shorter than 2s",
+                ttl
+            )));
+        }
+        Ok(Self {
+            http: http_client()?,
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            ttl,
+            leases: Mutex::default(),
+        })
+    }
+
+    async fn call(&self, path: &str, body: Value) -> Result<Value> {
+        self.http
+            .post(format!("{}{}", self.endpoint, path))
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(etcd_error)?
+            .json()
+            .await
+            .map_err(etcd_error)
+    }
+
+    async fn revoke(&self, lease: &str) -> Result<()> {
+        self.call("/v3/lease/revoke", json!({ "ID": lease }))
+            .await?;
+        Ok(())
+    }
+}
+
+impl DistributedLock for EtcdLock {
+    fn acquire<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let grant = self
+                .call("/v3/lease/grant", json!({ "TTL": self.ttl.as_secs() }))
+                .await?;
+            let Some(lease) = grant["ID"].as_str().map(str::to_owned) else {
+                return Err(CleanerError::Storage(format!(
+                    "etcd lease grant - {}",
+                    grant
+                )));
+            };
+            let encoded = STANDARD.encode(key);
+            let txn = json!({
+                "compare": [{ "key": encoded, "target": "CREATE", "create_revision": "0" }],
+                "success": [{ "requestPut": {
+                    "key": encoded,
+                    "value": STANDARD.encode(holder()),
+                    "lease": lease,
+                } }],
+            });
+            let acquired = self.call("/v3/kv/txn", txn).await?["succeeded"] == true;
+            match acquired {
+                true => {
+                    self.leases.lock().unwrap().insert(key.to_owned(), lease);
+                }
+                false => self.revoke(&lease).await?,
+            }
+            Ok(acquired)
+        })
+    }
+
+    fn renew<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let Some(lease) = self.leases.lock().unwrap().get(key).cloned() else {
+                return Ok(false);
+            };
+            let response = self
+                .call("/v3/lease/keepalive", json!({ "ID": lease }))
+                .await?;
+            // an expired lease comes back without a ttl
+            let alive = response["result"]["TTL"]
+                .as_str()
+                .and_then(|ttl| ttl.parse::<i64>().ok())
+                .is_some_and(|ttl| ttl > 0);
+            if !alive {
+                self.leases.lock().unwrap().remove(key);
+            }
+            Ok(alive)
+        })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let Some(lease) = self.leases.lock().unwrap().remove(key) else {
+                return Ok(());
+            };
+            self.revoke(&lease).await
+        })
+    }
+
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+fn etcd_error(e: reqwest::Error) -> CleanerError {
+    CleanerError::Storage(format!("etcd - {}", e))
+}
@@ -0,0 +1,96 @@
+//! locks shared by cleaner replicas pointing at the same root
+//!
+//! with a [`DistributedLock`] configured every cycle first takes the lock of its root and skips
+//! the cycle while another replica holds it, see
+//! [`CleanReport::lock_contended`](crate::CleanReport::lock_contended). the lock is renewed in
+//! the background at a third of its ttl, a failed renewal stops the cycle between two files.
+//!
+//! the same locks elect a leader among replicas for high availability, the replica holding a
+//! fixed key through [`campaign`] and [`hold`] is the only active one while the others stand by.
+
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{remote::BoxFuture, Result};
+
+#[cfg(feature = "election")]
+mod consul;
+#[cfg(feature = "election")]
+mod etcd;
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisLock;
+#[cfg(feature = "election")]
+pub use consul::ConsulLock;
+#[cfg(feature = "election")]
+pub use etcd::EtcdLock;
+
+pub trait DistributedLock: Send + Sync {
+    /// take the lock on `key` for one ttl, false while someone else holds it
+    fn acquire<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// extend the lock on `key` by one ttl, false once it was lost
+    fn renew<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// give up the lock on `key`, succeeds if it is not held
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// how long the lock is held without renewal
+    fn ttl(&self) -> Duration;
+}
+
+/// wait until `key` is acquired, retrying at a third of the ttl, false if `cancel` fired first
+///
+/// errors of the lock backend are logged and retried, a standby keeps campaigning until the
+/// backend comes back.
+pub async fn campaign(lock: &dyn DistributedLock, key: &str, cancel: &CancellationToken) -> bool {
+    let mut interval = tokio::time::interval(lock.ttl() / 3);
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return false,
+            _ = interval.tick() => {}
+        }
+        match lock.acquire(key).await {
+            Ok(true) => return true,
+            Ok(false) => tracing::trace!("{} is held by another replica", key),
+            Err(e) => tracing::warn!("unable to acquire {} - {}", key, e),
+        }
+    }
+}
+
+/// renew `key` at a third of the ttl until `term` fires, cancels `term` once the lock is lost
+pub async fn hold(lock: &dyn DistributedLock, key: &str, term: &CancellationToken) {
+    let mut interval = tokio::time::interval(lock.ttl() / 3);
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = term.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        match lock.renew(key).await {
+            Ok(true) => continue,
+            Ok(false) => tracing::warn!("lost the lock of {}", key),
+            Err(e) => tracing::warn!("unable to renew the lock of {} - {}", key, e),
+        }
+        term.cancel();
+        return;
+    }
+}
+
+#[cfg(feature = "election")]
+fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| crate::CleanerError::Config(format!("http client - {}", e)))
+}
+
+/// stored with the lock so operators can tell which replica leads
+#[cfg(feature = "election")]
+fn holder() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_owned());
+    format!("{} pid {}", host, std::process::id())
+}
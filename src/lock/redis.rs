@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use redis::{Client, Cmd, Value};
+
+use super::DistributedLock;
+use crate::{remote::BoxFuture, CleanerError, Result};
+
+const RENEW: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then \
+                     return redis.call('pexpire', KEYS[1], ARGV[2]) else return 0 end";
+const RELEASE: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then \
+                       return redis.call('del', KEYS[1]) else return 0 end";
+
+/// how long a single node may take to answer
+const NODE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// redlock over one or more independent redis nodes
+///
+/// the lock is held while a majority of the nodes store the random token of this replica
+/// under `hls-fragment-cleaner:<root>`. a single node gives a plain `SET NX PX` lock.
+pub struct RedisLock {
+    nodes: Vec<Client>,
+    ttl: Duration,
+    tokens: Mutex<HashMap<String, String>>,
+    counter: AtomicU64,
+}
+
+impl std::fmt::Debug for RedisLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisLock")
+            .field("nodes", &self.nodes.len())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl RedisLock {
+    /// `redis://host:port/db` urls of the nodes, connections are made on demand
+    pub fn new(urls: &[String], ttl: Duration) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(CleanerError::Config("no redis node given".to_owned()));
+        }
+        if ttl < Duration::from_secs(1) {
+            return Err(CleanerError::Config(format!(
+                "lock ttl {:?} is shorter than a second",
+                ttl
+            )));
+        }
+        let nodes = urls
+            .iter()
+            .map(|url| {
+                Client::open(url.as_str())
+                    .map_err(|e| CleanerError::Config(format!("redis url {} - {}", url, e)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            nodes,
+            ttl,
+            tokens: Mutex::default(),
+            counter: AtomicU64::new(0),
+        })
+    }
+
+    fn quorum(&self) -> usize {
+        self.nodes.len() / 2 + 1
+    }
+
+    fn key(key: &str) -> String {
+        format!("hls-fragment-cleaner:{}", key)
+    }
+
+    /// unique per replica and acquisition
+    fn token(&self) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}-{}", std::process::id(), nanos, count)
+    }
+
+    /// run `cmd` on every node, returns the number of nodes whose reply satisfied `ok`, an
+    /// error when too many nodes failed for a quorum
+    async fn each(&self, cmd: &Cmd, ok: impl Fn(&Value) -> bool) -> Result<usize> {
+        let mut succeeded = 0;
+        let mut failed = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let result = tokio::time::timeout(NODE_TIMEOUT, async {
+                let mut conn = node.get_multiplexed_async_connection().await?;
+                cmd.query_async::<Value>(&mut conn).await
+            })
+            .await;
+            match result {
+                Ok(Ok(reply)) if ok(&reply) => succeeded += 1,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => failed.push(format!("node {} - {}", index, e)),
+                Err(_) => failed.push(format!("node {} - timed out", index)),
+            }
+        }
+        if self.nodes.len() - failed.len() < self.quorum() {
+            return Err(CleanerError::Storage(format!(
+                "redis lock unavailable, {}",
+                failed.join(", ")
+            )));
+        }
+        for failure in failed {
+            tracing::warn!("redis lock {}", failure);
+        }
+        Ok(succeeded)
+    }
+
+    async fn release_token(&self, key: &str, token: &str) -> Result<usize> {
+        let mut cmd = redis::cmd("EVAL");
+        cmd.arg(RELEASE).arg(1).arg(key).arg(token);
+        self.each(&cmd, |reply| *reply == Value::Int(1)).await
+    }
+}
+
+impl DistributedLock for RedisLock {
+    fn acquire<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let key = Self::key(key);
+            let token = self.token();
+            let ttl = self.ttl.as_millis() as u64;
+            let started = Instant::now();
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(&key).arg(&token).arg("NX").arg("PX").arg(ttl);
+            let acquired = self.each(&cmd, |reply| *reply == Value::Okay).await;
+            // clock drift allowance of the redlock algorithm
+            let drift = self.ttl / 100 + Duration::from_millis(2);
+            match acquired {
+                Ok(n) if n >= self.quorum() && started.elapsed() + drift < self.ttl => {
+                    self.tokens.lock().unwrap().insert(key, token);
+                    Ok(true)
+                }
+                Ok(_) => {
+                    // undo the partial acquisition so the holder is not blocked needlessly
+                    let _ = self.release_token(&key, &token).await;
+                    Ok(false)
+                }
+                Err(e) => {
+                    let _ = self.release_token(&key, &token).await;
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    fn renew<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let key = Self::key(key);
+            let Some(token) = self.tokens.lock().unwrap().get(&key).cloned() else {
+                return Ok(false);
+            };
+            let ttl = self.ttl.as_millis() as u64;
+            let mut cmd = redis::cmd("EVAL");
+            cmd.arg(RENEW).arg(1).arg(&key).arg(&token).arg(ttl);
+            let renewed = self.each(&cmd, |reply| *reply == Value::Int(1)).await?;
+            Ok(renewed >= self.quorum())
+        })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let key = Self::key(key);
+            let Some(token) = self.tokens.lock().unwrap().remove(&key) else {
+                return Ok(());
+            };
+            self.release_token(&key, &token).await.map(|_| ())
+        })
+    }
+
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
@@ -1,23 +1,375 @@
-//! delete all unreferenced ts fragment
-//!
-//! criterias for ts deletion,
-//!
-//! scenario 1:
-//! * ts corresponding playlist file must exist
-//! * ts is not referenced in that playlist
-//! * ts sequence number must be smaller than any other referenced sequence number in that playlist
-//!
-//! scenario 2:
-//! * ts does not have corresponding playlist file
-//! * ts file is older than 30 minutes
-
-use std::{path::PathBuf, str::FromStr, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
-use tracing::{instrument, metadata::LevelFilter};
+use clap::{Parser, Subcommand};
+use hls_fragment_cleaner::{
+    archive,
+    control::Control,
+    encrypt::EncryptionKey,
+    events::EventStream,
+    hook::{ApprovalHook, CommandHook},
+    lifecycle::LifecycleStore,
+    lock::{self, DistributedLock},
+    remote::RemoteStore,
+    simulate::SimulationConfig,
+    systemd::SystemdNotifier,
+    Action, CancellationToken, Cleaner, CleanerBuilder, CleanerObserver, Ownership, Policy, Shard,
+    DEFAULT_PLAYLIST_TEMPLATE, DEFAULT_ROOT,
+};
+use tracing::metadata::LevelFilter;
 use tracing_subscriber::EnvFilter;
 
-const HLS_DIR: &str = "/tmp/hls";
+mod commands;
+
+use commands::{clean::ReportFormat, Format};
+
+/// delete unreferenced hls ts fragments
+///
+/// without a subcommand the cleanup daemon is started, gated by `HLS_CLEANUP=off`.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// hls directory, may be repeated
+    #[arg(long = "dir", global = true, default_value = DEFAULT_ROOT)]
+    dirs: Vec<PathBuf>,
+
+    /// file name of the playlist of a stream next to its segments, `{stream}` is replaced by
+    /// the stream name, e.g. `index.m3u8` for one stream per directory
+    #[arg(long, global = true, value_parser = parse_playlist_template, default_value = DEFAULT_PLAYLIST_TEMPLATE)]
+    playlist_template: String,
+
+    /// what the daemon does with expired segments, `delete`, `trash` or `archive`
+    #[arg(long, global = true, default_value_t = Action::Delete)]
+    action: Action,
+
+    /// destination of archived segments, strftime specifiers and `{stream}` are expanded,
+    /// relative to the directory
+    #[arg(long, global = true, value_parser = parse_archive_template, default_value = archive::DEFAULT_TEMPLATE)]
+    archive_template: String,
+
+    /// stitch finished streams into one ts file below this template before removing them,
+    /// expanded like the archive template
+    #[arg(long, global = true, value_parser = parse_archive_template)]
+    stitch: Option<String>,
+
+    /// compress archived segments with zstd at this level, needs the `zstd` feature
+    #[arg(long, global = true, value_parser = clap::value_parser!(i32).range(1..=22))]
+    archive_zstd_level: Option<i32>,
+
+    /// encrypt archived segments with the aes-256 key in this file, 32 raw bytes or 64 hex
+    /// digits, needs the `encryption` feature
+    #[arg(long, global = true, conflicts_with = "archive_kms_key_file")]
+    archive_key_file: Option<PathBuf>,
+
+    /// encrypt archived segments with the aes-256 data key whose kms encrypted blob is in this
+    /// file, decrypted by aws kms at startup, needs the `kms` feature
+    #[arg(long, global = true)]
+    archive_kms_key_file: Option<PathBuf>,
+
+    /// keep a playable vod playlist next to archived segments
+    #[arg(long, global = true)]
+    vod_playlist: bool,
+
+    /// segments of an ad break are kept until the whole break left the playlist, at most
+    /// this long when the playlist never signals its end
+    #[arg(long, global = true, default_value = "10m", value_parser = humantime::parse_duration)]
+    max_break_hold: Duration,
+
+    /// unreferenced segments are kept this long after the playlist moved past them
+    #[arg(long, global = true, default_value = "0s", value_parser = humantime::parse_duration)]
+    unreferenced_grace: Duration,
+
+    /// follow and delete files on other filesystems than the directory, such as a volume
+    /// mounted below it
+    #[arg(long, global = true)]
+    cross_devices: bool,
+
+    /// only remove files owned by this user, a name or uid, may be repeated
+    #[arg(long, global = true, value_parser = parse_uid)]
+    owner: Vec<u32>,
+
+    /// only remove files of this group, a name or gid, may be repeated
+    #[arg(long, global = true, value_parser = parse_gid)]
+    group: Vec<u32>,
+
+    /// only remove files with all of these permission bits set, octal
+    #[arg(long, global = true, value_parser = parse_mode)]
+    require_mode: Option<u32>,
+
+    /// orphaned segments timestamped further than this in the future age from when they
+    /// were first seen instead
+    #[arg(long, global = true, default_value = "1m", value_parser = humantime::parse_duration)]
+    clock_skew_tolerance: Duration,
+
+    /// report a stream as stuck once its playlist was not updated for this many target
+    /// durations while segments keep arriving, the segments written since are kept, 0 disables
+    #[arg(long, global = true, default_value_t = 3)]
+    stuck_after: u32,
+
+    /// segments without a playlist are kept while this file exists next to them, `{stream}`
+    /// is replaced by the stream name, e.g. `{stream}.lock` held by the packager while it
+    /// publishes
+    #[arg(long, global = true)]
+    publisher_lock: Option<String>,
+
+    /// remember when segments were first seen and unreferenced in this sqlite database so
+    /// grace periods survive restarts, needs the `sqlite` feature
+    #[arg(long, global = true)]
+    state_db: Option<PathBuf>,
+
+    /// evaluate segments against a candidate policy as well and report where it disagrees,
+    /// only the active policy acts, the candidate is the active one with the shadow options
+    #[arg(long, global = true)]
+    shadow_action: Option<Action>,
+
+    /// orphaned segments are removed once older than this under the candidate policy
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    shadow_orphan_max_age: Option<Duration>,
+
+    /// maximum break hold of the candidate policy
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    shadow_max_break_hold: Option<Duration>,
+
+    /// push archived segments and vod files to `s3://bucket/prefix` after every cycle and
+    /// remove them locally once uploaded, needs the `s3` feature
+    #[arg(long, global = true)]
+    upload: Option<String>,
+
+    /// s3 storage class of uploaded objects, e.g. `GLACIER_IR`
+    #[arg(long, global = true, requires = "upload")]
+    storage_class: Option<String>,
+
+    /// keep archived content locally this long before uploading it
+    #[arg(long, global = true, requires = "upload", default_value = "0s", value_parser = humantime::parse_duration)]
+    local_retention: Duration,
+
+    /// delete uploaded objects once they are older than this
+    #[arg(long, global = true, requires = "upload", value_parser = humantime::parse_duration)]
+    remote_retention: Option<Duration>,
+
+    /// remove at most this many segments per directory and cycle, the oldest first, the rest
+    /// waits for the next cycles
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    max_deletions_per_cycle: Option<u64>,
+
+    /// ask this program before acting on each segment, it gets the planned action as json on
+    /// stdin and vetoes it by exiting with a status other than zero
+    #[arg(long, global = true, conflicts_with = "approval_url")]
+    approval_command: Option<PathBuf>,
+
+    /// post each planned action as json to this url before acting on it, a status other than
+    /// 2xx vetoes it, needs the `webhooks` feature
+    #[arg(long, global = true)]
+    approval_url: Option<String>,
+
+    /// an approval taking longer than this vetoes the action, and the rest of the cycle
+    #[arg(long, global = true, default_value = "5s", value_parser = humantime::parse_duration)]
+    approval_timeout: Duration,
+
+    /// run this program after every cycle of a directory with the cycle report as json on
+    /// stdin, e.g. to update an inventory
+    #[arg(long, global = true)]
+    post_cycle_command: Option<PathBuf>,
+
+    /// the post cycle program is killed once it runs longer than this
+    #[arg(long, global = true, requires = "post_cycle_command", default_value = "1m", value_parser = humantime::parse_duration)]
+    post_cycle_timeout: Duration,
+
+    /// log the same kind of error on a stream once per window and summarize how often it
+    /// repeated, 0s logs every error
+    #[arg(long, global = true, default_value = "1m", value_parser = humantime::parse_duration, help_heading = "Logging")]
+    log_dedup_window: Duration,
+
+    /// warn once the filesystem of a directory is estimated to fill up within this, from the
+    /// space written and freed between cycles, 0s never warns
+    #[arg(long, global = true, default_value = "1h", value_parser = humantime::parse_duration, help_heading = "Logging")]
+    time_to_full_warning: Duration,
+
+    /// exit with an error once this many cycles of a directory failed in a row, e.g. because
+    /// it vanished or is no longer readable, instead of retrying forever
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    max_failed_cycles: Option<u32>,
+
+    /// take a lock on every directory in this redis before each cycle so only one replica
+    /// cleans it, repeat for a redlock over independent nodes, needs the `redis` feature
+    #[arg(long, global = true)]
+    redis_lock: Vec<String>,
+
+    /// how long a replica holds the lock without renewing it
+    #[arg(long, global = true, default_value = "30s", value_parser = humantime::parse_duration)]
+    lock_ttl: Duration,
+
+    /// only the elected leader among the daemon replicas cleans, the others stand by,
+    /// `consul+http://127.0.0.1:8500` or `etcd+http://127.0.0.1:2379`, needs the `election`
+    /// feature
+    #[arg(long, global = true)]
+    leader_election: Option<String>,
+
+    /// key the replicas campaign for
+    #[arg(
+        long,
+        global = true,
+        requires = "leader_election",
+        default_value = "hls-fragment-cleaner/leader"
+    )]
+    leader_key: String,
+
+    /// a dead leader is replaced within about this long
+    #[arg(long, global = true, requires = "leader_election", default_value = "10s", value_parser = humantime::parse_duration)]
+    leader_ttl: Duration,
+
+    /// clean only the streams of shard `index/count`, e.g. `2/8`, so a fleet of cleaners
+    /// divides the streams of the directories among themselves
+    #[arg(long, global = true)]
+    shard: Option<Shard>,
+
+    /// record archived content in this sqlite database, searched by `find`, needs the
+    /// `sqlite` feature
+    #[arg(long, global = true)]
+    archive_index: Option<PathBuf>,
+
+    /// unix socket the daemon streams its events on, read by `tail`
+    #[arg(long, global = true)]
+    events_socket: Option<PathBuf>,
+
+    /// serve the grpc control interface of `proto/cleaner.proto` on this address, it is not
+    /// authenticated, needs the `grpc` feature
+    #[arg(long, global = true)]
+    grpc_listen: Option<SocketAddr>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// report disk usage per stream without deleting anything
+    Analyze {
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// run a single cleanup cycle per directory and exit, for cron
+    Clean {
+        /// write the structured reports of the cycle to this file
+        #[arg(long)]
+        report_file: Option<PathBuf>,
+        /// format of the report file, inferred from its extension by default
+        #[arg(long, value_enum)]
+        report_format: Option<ReportFormat>,
+    },
+    /// diagnose common misconfigurations of the hls directories
+    Doctor {
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// list where the archived content of a stream within a time span went
+    Find {
+        #[arg(long)]
+        stream: String,
+        /// unix seconds, rfc 3339 in utc or a duration ago
+        #[arg(long)]
+        from: String,
+        /// like `from`, now by default
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// purge expired trash and remove empty trash directories
+    Gc {
+        /// trashed segments older than this are deleted for good
+        #[arg(long, default_value = "24h", value_parser = humantime::parse_duration)]
+        retention: Duration,
+        /// also re-check the checksums of archived segments
+        #[arg(long)]
+        verify_archive: bool,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// delete every artifact of a stream immediately
+    PurgeStream {
+        name: String,
+        /// only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// move trashed segments of a stream back into place and copy archived ones back,
+    /// decrypted with the archive key and decompressed
+    Restore {
+        #[arg(long)]
+        stream: String,
+        /// only segments trashed or archived at or after this, unix seconds, rfc 3339 or a
+        /// duration ago
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// run the cleaner against a synthetic hls workload in accelerated time
+    Simulate {
+        #[arg(long, default_value_t = 4)]
+        streams: usize,
+        #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+        segment_duration: Duration,
+        /// segments listed in each playlist
+        #[arg(long, default_value_t = 5)]
+        window: usize,
+        /// simulated time to run for
+        #[arg(long, default_value = "4h", value_parser = humantime::parse_duration)]
+        duration: Duration,
+        /// chance per stream and segment of a packager restart
+        #[arg(long, default_value_t = 0.001)]
+        restart_probability: f64,
+        /// chance per stream and segment of a corrupt playlist write
+        #[arg(long, default_value_t = 0.002)]
+        corrupt_probability: f64,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// print current segment counts, playlist windows and update times per stream
+    Stats {
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// re-check archived segments against their checksum manifests
+    VerifyArchive {
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// stream the events of a running daemon as newline delimited json
+    Tail {
+        /// only events of this stream
+        #[arg(long)]
+        stream: Option<String>,
+        /// include skipped segments, one event per kept fragment and cycle
+        #[arg(long)]
+        skips: bool,
+    },
+    /// live dashboard of the streams, with the deletions and errors of a running daemon
+    /// when `--events-socket` is given
+    Tui {
+        /// how often the directories are re-read
+        #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+        refresh: Duration,
+    },
+    /// check playlists parse and their segments exist, exits non-zero on inconsistencies
+    Validate {
+        /// also fail on warnings such as unreferenced segments
+        #[arg(long)]
+        strict: bool,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -27,155 +379,602 @@ async fn main() -> anyhow::Result<()> {
                 .with_default_directive(LevelFilter::INFO.into())
                 .from_env_lossy(),
         )
+        .with_writer(std::io::stderr)
         .init();
-    tracing::info!("ts cleaner initialized");
-    run().await
+    let cli = Cli::parse();
+    let dirs = cli.dirs;
+    if cli.archive_zstd_level.is_some() && !cfg!(feature = "zstd") {
+        anyhow::bail!("--archive-zstd-level needs a build with the zstd feature");
+    }
+    if cli.archive_key_file.is_some() && !cfg!(feature = "encryption") {
+        anyhow::bail!("--archive-key-file needs a build with the encryption feature");
+    }
+    if cli.archive_kms_key_file.is_some() && !cfg!(feature = "kms") {
+        anyhow::bail!("--archive-kms-key-file needs a build with the kms feature");
+    }
+    let archive_key = match (&cli.archive_key_file, &cli.archive_kms_key_file) {
+        (Some(path), _) => Some(EncryptionKey::from_file(path)?),
+        (None, Some(path)) => Some(EncryptionKey::from_kms(path).await?),
+        (None, None) => None,
+    };
+    let policy = Policy::default()
+        .action(cli.action)
+        .archive_template(cli.archive_template)
+        .archive_compression(cli.archive_zstd_level)
+        .archive_key(archive_key)
+        .stitch_template(cli.stitch)
+        .local_retention(cli.local_retention)
+        .remote_retention(cli.remote_retention)
+        .vod_playlists(cli.vod_playlist)
+        .max_break_hold(cli.max_break_hold)
+        .unreferenced_grace(cli.unreferenced_grace)
+        .shard(cli.shard)
+        .cross_devices(cli.cross_devices)
+        .clock_skew_tolerance(cli.clock_skew_tolerance)
+        .stuck_after(cli.stuck_after)
+        .publisher_lock(cli.publisher_lock)
+        .playlist_template(cli.playlist_template)
+        .ownership(Ownership {
+            uids: cli.owner,
+            gids: cli.group,
+            mode: cli.require_mode,
+        });
+    let remote = remote_store(cli.upload.as_deref(), cli.storage_class.as_deref()).await?;
+    let lock = distributed_lock(&cli.redis_lock, cli.lock_ttl)?;
+    let (index, lifecycle) = match &cli.command {
+        None | Some(Command::Clean { .. }) => (
+            archive_index(cli.archive_index.as_deref())?,
+            lifecycle_store(cli.state_db.as_deref())?,
+        ),
+        Some(_) => (None, None),
+    };
+    let shutdown = CancellationToken::new();
+    // shared by the daemon and `clean`, the root is set per directory
+    let mut template = Cleaner::builder()
+        .policy(policy.clone())
+        .log_dedup_window(cli.log_dedup_window)
+        .time_to_full_warning(cli.time_to_full_warning)
+        .cancellation_token(shutdown.clone());
+    if let Some(index) = index {
+        template = template.observer(index);
+    }
+    if let Some(lifecycle) = lifecycle {
+        template = template.lifecycle_store(lifecycle);
+    }
+    if let Some(remote) = remote {
+        template = template.remote(remote);
+    }
+    if let Some(lock) = lock {
+        template = template.distributed_lock(lock);
+    }
+    let approval = approval_hook(
+        cli.approval_command.as_deref(),
+        cli.approval_url.as_deref(),
+        cli.approval_timeout,
+    )?;
+    if let Some(hook) = approval {
+        template = template.approval_hook(hook);
+    }
+    if let Some(program) = &cli.post_cycle_command {
+        let hook = CommandHook::new(program, cli.post_cycle_timeout);
+        template = template.cycle_hook(Arc::new(hook));
+    }
+    if cli.shadow_action.is_some()
+        || cli.shadow_orphan_max_age.is_some()
+        || cli.shadow_max_break_hold.is_some()
+    {
+        let mut shadow = policy.clone();
+        if let Some(action) = cli.shadow_action {
+            shadow = shadow.action(action);
+        }
+        if let Some(age) = cli.shadow_orphan_max_age {
+            shadow = shadow.orphan_max_age(age);
+        }
+        if let Some(hold) = cli.shadow_max_break_hold {
+            shadow = shadow.max_break_hold(hold);
+        }
+        template = template.shadow_policy(shadow);
+    }
+    if let Some(cycles) = cli.max_failed_cycles {
+        template = template.max_failed_cycles(cycles);
+    }
+    if let Some(max) = cli.max_deletions_per_cycle {
+        template = template.max_deletions_per_cycle(max.try_into()?);
+    }
+    match cli.command {
+        None => {
+            handle_signals(shutdown.clone())?;
+            tracing::info!("ts cleaner initialized");
+            let election = leader_election(cli.leader_election.as_deref(), cli.leader_ttl)?
+                .map(|lock| (lock, cli.leader_key));
+            let events_socket = cli.events_socket.as_deref();
+            let servers = Servers {
+                events_socket,
+                grpc_listen: cli.grpc_listen,
+            };
+            run(dirs, template, election, servers, shutdown).await
+        }
+        Some(Command::Analyze { format }) => commands::analyze::run(&dirs, &policy, format),
+        Some(Command::Clean {
+            report_file,
+            report_format,
+        }) => {
+            handle_signals(shutdown)?;
+            let report_file = report_file.as_deref();
+            commands::clean::run(&dirs, template, report_file, report_format).await
+        }
+        Some(Command::Doctor { format }) => commands::doctor::run(&dirs, &policy, format),
+        Some(Command::Find {
+            stream,
+            from,
+            to,
+            format,
+        }) => {
+            let index = cli
+                .archive_index
+                .context("--archive-index is required to search the archive")?;
+            commands::find::run(&index, &stream, &from, to.as_deref(), format)
+        }
+        Some(Command::Gc {
+            retention,
+            verify_archive,
+            format,
+        }) => commands::gc::run(&dirs, &policy, retention, verify_archive, format),
+        Some(Command::PurgeStream {
+            name,
+            dry_run,
+            format,
+        }) => commands::purge::run(&dirs, &policy, &name, dry_run, format),
+        Some(Command::Restore {
+            stream,
+            since,
+            format,
+        }) => commands::restore::run(&dirs, &policy, &stream, since.as_deref(), format),
+        Some(Command::Simulate {
+            streams,
+            segment_duration,
+            window,
+            duration,
+            restart_probability,
+            corrupt_probability,
+            seed,
+            format,
+        }) => {
+            let config = SimulationConfig {
+                streams,
+                segment_duration,
+                window,
+                duration,
+                restart_probability,
+                corrupt_probability,
+                seed,
+                policy,
+                ..Default::default()
+            };
+            commands::simulate::run(config, format).await
+        }
+        Some(Command::Stats { format }) => commands::stats::run(&dirs, &policy, format),
+        Some(Command::Tail { stream, skips }) => {
+            let socket = cli
+                .events_socket
+                .context("--events-socket is required to tail a daemon")?;
+            commands::tail::run(&socket, stream.as_deref(), skips).await
+        }
+        Some(Command::Tui { refresh }) => {
+            let socket = cli.events_socket.as_deref();
+            commands::tui::run(&dirs, &policy, socket, refresh).await
+        }
+        Some(Command::VerifyArchive { format }) => commands::verify::run(&dirs, &policy, format),
+        Some(Command::Validate { strict, format }) => {
+            commands::validate::run(&dirs, &policy, strict, format)
+        }
+    }
+}
+
+/// what the daemon serves besides cleaning
+struct Servers<'a> {
+    events_socket: Option<&'a Path>,
+    grpc_listen: Option<SocketAddr>,
 }
 
-async fn run() -> anyhow::Result<()> {
+async fn run(
+    dirs: Vec<PathBuf>,
+    template: CleanerBuilder,
+    election: Option<(Arc<dyn DistributedLock>, String)>,
+    servers: Servers<'_>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
     let Ok(cleanup) = std::env::var("HLS_CLEANUP") else {
         tracing::info!("HLS_CLEANUP is not set, exiting");
         return Ok(());
-    } ;
+    };
     if cleanup != "off" {
         tracing::info!("cleanup is done by nginx process, exiting");
         return Ok(());
     }
     println!("launching cleanup process");
 
-    let mut interval = tokio::time::interval(Duration::from_secs(15));
-    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut template = template;
+    let notifier = SystemdNotifier::from_env(dirs.iter().cloned()).map(Arc::new);
+    if let Some(notifier) = &notifier {
+        template = template.observer(notifier.clone());
+    }
+    if let Some(socket) = servers.events_socket {
+        let events = EventStream::default();
+        commands::tail::serve(socket, events.clone())?;
+        template = template.observer(Arc::new(events));
+    }
+    if let Some(control) = grpc_control(servers.grpc_listen, &dirs, &template, &shutdown)? {
+        template = template.control(control);
+    }
+
+    let Some((election, key)) = election else {
+        return clean(&dirs, &template, shutdown).await;
+    };
     loop {
-        interval.tick().await;
-        tracing::trace!("launching task");
-        if let Err(e) = tokio::spawn(clean_task()).await? {
-            tracing::error!("{}", e);
+        tracing::info!("standing by for {}", key);
+        let campaign = lock::campaign(&*election, &key, &shutdown);
+        let elected = match &notifier {
+            Some(notifier) => notifier.idle(campaign).await,
+            None => campaign.await,
+        };
+        if !elected {
+            return Ok(());
+        }
+        tracing::info!("elected leader for {}", key);
+        let term = shutdown.child_token();
+        let holder = {
+            let (election, key, term) = (election.clone(), key.clone(), term.clone());
+            tokio::spawn(async move { lock::hold(&*election, &key, &term).await })
+        };
+        let result = clean(&dirs, &template, term.clone()).await;
+        term.cancel();
+        holder.await?;
+        if let Err(e) = election.release(&key).await {
+            tracing::warn!("unable to step down - {}", e);
         }
+        result?;
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+        tracing::warn!("lost the leadership for {}", key);
     }
 }
 
-#[instrument(level = "trace")]
-async fn clean_task() -> anyhow::Result<()> {
-    let ts_matcher = globset::GlobBuilder::new("*.ts").build()?.compile_matcher();
-    let current_time = std::time::SystemTime::now();
-    for ts_entry in walkdir::WalkDir::new(HLS_DIR)
-        .min_depth(1)
-        .max_depth(1)
-        .contents_first(true)
-        .into_iter()
-        .filter_entry(|e| ts_matcher.is_match(e.path()) && e.file_type().is_file())
-        .filter_map(|e| e.ok())
-    {
-        tracing::debug!("processing {}", ts_entry.path().display());
-        let file_stem = ts_entry
-            .path()
-            .file_stem()
-            .with_context(|| format!("{} has not file stem", ts_entry.path().display()))?
-            .to_str()
-            .with_context(|| format!("{} contains invalid character", ts_entry.path().display()))?;
-        let (stream_base_name, sequence_num) = file_stem
-            .rsplit_once('-')
-            .map(|(base, num)| {
-                (
-                    base,
-                    num.parse::<u32>()
-                        .with_context(|| format!("invalid sequence num {}", num)),
-                )
-            })
-            .with_context(|| file_stem.to_owned())?;
-        let sequence_num = sequence_num?;
-        let playlist_path = ts_entry
-            .path()
-            .parent()
-            .with_context(|| format!("{} does not have a parent", ts_entry.path().display()))?
-            .join(format!("{}.m3u8", stream_base_name));
-        match playlist_path.exists() {
-            true => {
-                tracing::trace!("playlist {} exist", playlist_path.display());
-                let playlist_content = std::fs::read_to_string(&playlist_path)
-                    .with_context(|| format!("{}", playlist_path.display()))?;
-                let playlist = hls_m3u8::MediaPlaylist::from_str(&playlist_content)
-                    .with_context(|| playlist_content.to_string())?;
-                let segment_paths = playlist
-                    .segments
-                    .iter()
-                    .map(|(_, seg)| {
-                        PathBuf::from_str(seg.uri())
-                            .with_context(|| format!("invalid path {}", seg.uri()))
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                let file_stems = segment_paths
-                    .iter()
-                    .map(|p| {
-                        p.file_stem()
-                            .with_context(|| format!("{} does not have stem", p.display()))
-                    })
-                    .collect::<Result<Vec<_>, _>>()?
-                    .into_iter()
-                    .map(|stem| {
-                        stem.to_str().with_context(|| {
-                            format!("path {} contains invalid character", stem.to_string_lossy())
-                        })
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                let min_sequence_num = file_stems
-                    .into_iter()
-                    .map(|s| {
-                        s.split_once('-')
-                            .with_context(|| format!("invalid segment name {}", s))
-                    })
-                    .collect::<Result<Vec<_>, _>>()?
-                    .iter()
-                    .map(|split| split.1.parse::<u32>())
-                    .collect::<Result<Vec<_>, _>>()?
-                    .into_iter()
-                    .min()
-                    .with_context(|| format!("{} has no segments", playlist_path.display()))?;
-                if sequence_num < min_sequence_num {
-                    tracing::trace!("{} is not in playlist, deleting", ts_entry.path().display());
-                    if let Err(e) = std::fs::remove_file(ts_entry.path()) {
-                        tracing::warn!("unable to remove {} - {}", ts_entry.path().display(), e);
-                    }
-                }
-            }
-            false => {
-                tracing::trace!("playlist {} does not exist", playlist_path.display());
-
-                match tokio::fs::metadata(ts_entry.path()).await {
-                    Ok(metadata) => match metadata.accessed() {
-                        Ok(time) => {
-                            if let Ok(duration_since_access) = current_time.duration_since(time) {
-                                if duration_since_access > std::time::Duration::from_secs(1800) {
-                                    tracing::trace!(
-                                        "{} older than 30 minutes, deleting",
-                                        ts_entry.path().display()
-                                    );
-                                    if let Err(e) = std::fs::remove_file(ts_entry.path()) {
-                                        tracing::error!(
-                                            "unable to remove {} - {}",
-                                            ts_entry.path().display(),
-                                            e
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "error reading access time for {} - {}",
-                                ts_entry.path().display(),
-                                e
-                            )
-                        }
-                    },
-                    Err(e) => tracing::error!(
-                        "error getting metadata for {} - {}",
-                        ts_entry.path().display(),
-                        e
-                    ),
-                }
-            }
+/// cancel `shutdown` on the first SIGTERM or SIGINT, the cleaners finish the stream they are
+/// working on and return, a second signal exits right away
+#[cfg(unix)]
+fn handle_signals(shutdown: CancellationToken) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).context("installing SIGTERM handler")?;
+    let mut interrupt = signal(SignalKind::interrupt()).context("installing SIGINT handler")?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+        }
+        tracing::info!("shutting down, finishing the streams in flight");
+        if let Err(e) = hls_fragment_cleaner::systemd::notify("STOPPING=1") {
+            tracing::warn!("unable to notify systemd - {}", e);
+        }
+        shutdown.cancel();
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+        }
+        tracing::warn!("second signal, exiting now");
+        std::process::exit(130);
+    });
+    Ok(())
+}
+
+/// cancel `shutdown` on the first ctrl-c, exit right away on the second
+#[cfg(not(unix))]
+fn handle_signals(shutdown: CancellationToken) -> anyhow::Result<()> {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
         }
+        tracing::info!("shutting down, finishing the streams in flight");
+        shutdown.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tracing::warn!("second ctrl-c, exiting now");
+            std::process::exit(130);
+        }
+    });
+    Ok(())
+}
+
+/// run a cleaner per directory until `cancel` fires
+///
+/// a directory whose cleaner fails is logged and the others keep running, the failures are
+/// returned together once every cleaner stopped.
+async fn clean(
+    dirs: &[PathBuf],
+    template: &CleanerBuilder,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for dir in dirs {
+        let cleaner = template
+            .clone()
+            .root(dir)
+            .cancellation_token(cancel.clone())
+            .build();
+        tasks.spawn(async move {
+            cleaner
+                .run()
+                .await
+                .with_context(|| format!("cleaning {}", cleaner.root().display()))
+        });
+    }
+    let mut failed = 0;
+    while let Some(result) = tasks.join_next().await {
+        let result = result
+            .context("cleaner task panicked")
+            .and_then(|result| result);
+        if let Err(e) = result {
+            tracing::error!("{:#}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} directories failed", failed, dirs.len());
     }
     Ok(())
 }
+
+#[cfg(feature = "s3")]
+async fn remote_store(
+    url: Option<&str>,
+    storage_class: Option<&str>,
+) -> anyhow::Result<Option<Arc<dyn RemoteStore>>> {
+    let Some(url) = url else {
+        return Ok(None);
+    };
+    let store = hls_fragment_cleaner::remote::S3Store::new(url, storage_class).await?;
+    Ok(Some(Arc::new(store)))
+}
+
+#[cfg(not(feature = "s3"))]
+async fn remote_store(
+    url: Option<&str>,
+    _storage_class: Option<&str>,
+) -> anyhow::Result<Option<Arc<dyn RemoteStore>>> {
+    if url.is_some() {
+        anyhow::bail!("--upload needs a build with the s3 feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "grpc")]
+fn grpc_control(
+    addr: Option<SocketAddr>,
+    dirs: &[PathBuf],
+    template: &CleanerBuilder,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<Option<Arc<Control>>> {
+    use hls_fragment_cleaner::grpc::{self, ControlService};
+
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+    let control = Arc::new(Control::new(dirs.iter().cloned()));
+    let cleaners = dirs
+        .iter()
+        .map(|dir| template.clone().root(dir).build())
+        .collect();
+    let service = ControlService::new(cleaners, control.clone());
+    grpc::serve(addr, service, shutdown.clone()).with_context(|| format!("binding {}", addr))?;
+    Ok(Some(control))
+}
+
+#[cfg(not(feature = "grpc"))]
+fn grpc_control(
+    addr: Option<SocketAddr>,
+    _dirs: &[PathBuf],
+    _template: &CleanerBuilder,
+    _shutdown: &CancellationToken,
+) -> anyhow::Result<Option<Arc<Control>>> {
+    if addr.is_some() {
+        anyhow::bail!("--grpc-listen needs a build with the grpc feature");
+    }
+    Ok(None)
+}
+
+fn approval_hook(
+    command: Option<&Path>,
+    url: Option<&str>,
+    timeout: Duration,
+) -> anyhow::Result<Option<Arc<dyn ApprovalHook>>> {
+    if let Some(program) = command {
+        return Ok(Some(Arc::new(CommandHook::new(program, timeout))));
+    }
+    url.map(|url| http_hook(url, timeout)).transpose()
+}
+
+#[cfg(feature = "webhooks")]
+fn http_hook(url: &str, timeout: Duration) -> anyhow::Result<Arc<dyn ApprovalHook>> {
+    let hook = hls_fragment_cleaner::hook::HttpHook::new(url, timeout)?;
+    Ok(Arc::new(hook))
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn http_hook(_url: &str, _timeout: Duration) -> anyhow::Result<Arc<dyn ApprovalHook>> {
+    anyhow::bail!("--approval-url needs a build with the webhooks feature")
+}
+
+#[cfg(feature = "redis")]
+fn distributed_lock(
+    urls: &[String],
+    ttl: Duration,
+) -> anyhow::Result<Option<Arc<dyn DistributedLock>>> {
+    if urls.is_empty() {
+        return Ok(None);
+    }
+    let lock = hls_fragment_cleaner::lock::RedisLock::new(urls, ttl)?;
+    Ok(Some(Arc::new(lock)))
+}
+
+#[cfg(not(feature = "redis"))]
+fn distributed_lock(
+    urls: &[String],
+    _ttl: Duration,
+) -> anyhow::Result<Option<Arc<dyn DistributedLock>>> {
+    if !urls.is_empty() {
+        anyhow::bail!("--redis-lock needs a build with the redis feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "election")]
+fn leader_election(
+    url: Option<&str>,
+    ttl: Duration,
+) -> anyhow::Result<Option<Arc<dyn DistributedLock>>> {
+    use hls_fragment_cleaner::lock::{ConsulLock, EtcdLock};
+
+    let Some(url) = url else {
+        return Ok(None);
+    };
+    let lock: Arc<dyn DistributedLock> = match url.split_once('+') {
+        Some(("consul", endpoint)) => Arc::new(ConsulLock::new(endpoint, ttl)?),
+        Some(("etcd", endpoint)) => Arc::new(EtcdLock::new(endpoint, ttl)?),
+        _ => anyhow::bail!(
+            "unsupported leader election {}, expected consul+<url> or etcd+<url>",
+            url
+        ),
+    };
+    Ok(Some(lock))
+}
+
+#[cfg(not(feature = "election"))]
+fn leader_election(
+    url: Option<&str>,
+    _ttl: Duration,
+) -> anyhow::Result<Option<Arc<dyn DistributedLock>>> {
+    if url.is_some() {
+        anyhow::bail!("--leader-election needs a build with the election feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "sqlite")]
+fn archive_index(path: Option<&Path>) -> anyhow::Result<Option<Arc<dyn CleanerObserver>>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let index = hls_fragment_cleaner::index::ArchiveIndex::open(path)?;
+    Ok(Some(Arc::new(index)))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn archive_index(path: Option<&Path>) -> anyhow::Result<Option<Arc<dyn CleanerObserver>>> {
+    if path.is_some() {
+        anyhow::bail!("--archive-index needs a build with the sqlite feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "sqlite")]
+fn lifecycle_store(path: Option<&Path>) -> anyhow::Result<Option<Arc<dyn LifecycleStore>>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let store = hls_fragment_cleaner::lifecycle::SqliteLifecycle::open(path)?;
+    Ok(Some(Arc::new(store)))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn lifecycle_store(path: Option<&Path>) -> anyhow::Result<Option<Arc<dyn LifecycleStore>>> {
+    if path.is_some() {
+        anyhow::bail!("--state-db needs a build with the sqlite feature");
+    }
+    Ok(None)
+}
+
+fn parse_archive_template(template: &str) -> anyhow::Result<String> {
+    archive::check_template(template)?;
+    Ok(template.to_owned())
+}
+
+fn parse_playlist_template(template: &str) -> anyhow::Result<String> {
+    hls_fragment_cleaner::check_playlist_template(template)?;
+    Ok(template.to_owned())
+}
+
+fn parse_uid(user: &str) -> anyhow::Result<u32> {
+    lookup_id("/etc/passwd", user).with_context(|| format!("unknown user {}", user))
+}
+
+fn parse_gid(group: &str) -> anyhow::Result<u32> {
+    lookup_id("/etc/group", group).with_context(|| format!("unknown group {}", group))
+}
+
+/// `name` as a number, or the id of the `name:x:id:...` entry of the passwd style `file`
+fn lookup_id(file: &str, name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    std::fs::read_to_string(file)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(entry), Some(_), Some(id)) if entry == name => id.parse().ok(),
+                _ => None,
+            }
+        })
+}
+
+fn parse_mode(mode: &str) -> anyhow::Result<u32> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => anyhow::bail!(
+            "invalid mode {}, expected octal permission bits like 0640",
+            mode
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn policy_flags_are_accepted_after_the_subcommand() {
+        let cli = Cli::try_parse_from([
+            "hls-fragment-cleaner",
+            "clean",
+            "--max-deletions-per-cycle",
+            "10",
+            "--upload",
+            "s3://bucket/archive",
+            "--local-retention",
+            "1h",
+            "--shadow-action",
+            "trash",
+        ])
+        .unwrap();
+        assert_eq!(cli.max_deletions_per_cycle, Some(10));
+        assert_eq!(cli.upload.as_deref(), Some("s3://bucket/archive"));
+        assert_eq!(cli.local_retention, Duration::from_secs(3600));
+        assert_eq!(cli.shadow_action, Some(Action::Trash));
+        assert!(matches!(cli.command, Some(Command::Clean { .. })));
+    }
+
+    #[test]
+    fn every_top_level_flag_is_global() {
+        let command = Cli::command();
+        let local = command
+            .get_arguments()
+            .filter(|arg| !arg.is_global_set())
+            .map(|arg| arg.get_id().to_string())
+            .collect::<Vec<_>>();
+        assert!(local.is_empty(), "not global: {:?}", local);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    archive::{ExpiryRecord, ManifestEntry, UploadRecord},
+    storage::TimeSource,
+    CleanReport, CleanerError,
+};
+
+/// a ts fragment found under the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    pub path: PathBuf,
+    /// stream base name, `live` for `live-42.ts`
+    pub stream: String,
+    pub sequence: u32,
+    /// size in bytes when the fragment was evaluated
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// `#EXT-X-DATERANGE` tag the live playlist listed before the fragment
+    pub date_range: Option<String>,
+}
+
+/// why a fragment was deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// scenario 1, the playlist exists and has moved past this fragment
+    ///
+    /// fragments inside an ad break signaled by `#EXT-X-DATERANGE` are only removed once the
+    /// whole break left the playlist.
+    Unreferenced { min_sequence: u32 },
+    /// scenario 2, no playlist and the fragment is older than the orphan age, measured from
+    /// its `source` timestamp
+    Orphaned { age: Duration, source: TimeSource },
+}
+
+/// why a fragment was kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// the playlist still references this fragment or a later one is older
+    Referenced { min_sequence: u32 },
+    /// no playlist, but the fragment is not old enough yet
+    TooYoung { age: Duration, source: TimeSource },
+    /// no playlist and the clock is behind the time the fragment was first seen
+    AgeUnknown,
+    /// unreferenced, but part of an ad break the playlist still lists the end of
+    InBreak { min_sequence: u32 },
+    /// unreferenced, but for less than the policy's unreferenced grace
+    Grace { remaining: Duration },
+    /// on another filesystem than the root and the policy does not cross devices
+    OtherDevice,
+    /// written or rewritten after the cycle listed the root, evaluated on the next one
+    Changed,
+    /// flagged immutable or append-only, which is how operators pin a fragment
+    Protected,
+    /// owned by a user or group, or with permissions, the policy ownership does not allow
+    Foreign,
+    /// written after the last update of a playlist that stopped updating, see
+    /// [`Policy::stuck_after`](crate::Policy::stuck_after)
+    Stuck,
+    /// the approval hook did not let the action go ahead, see [`hook`](crate::hook)
+    Vetoed,
+    /// the stream is frozen by a [`NODELETE_MARKER`](crate::NODELETE_MARKER)
+    Frozen,
+    /// no playlist, but the [`Policy::publisher_lock`](crate::Policy::publisher_lock) of the
+    /// stream exists
+    Publishing,
+}
+
+impl Reason {
+    /// snake case name without the details, `unreferenced`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unreferenced { .. } => "unreferenced",
+            Self::Orphaned { .. } => "orphaned",
+        }
+    }
+}
+
+impl SkipReason {
+    /// snake case name without the details, `too_young`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Referenced { .. } => "referenced",
+            Self::TooYoung { .. } => "too_young",
+            Self::AgeUnknown => "age_unknown",
+            Self::InBreak { .. } => "in_break",
+            Self::Grace { .. } => "grace",
+            Self::OtherDevice => "other_device",
+            Self::Changed => "changed",
+            Self::Protected => "protected",
+            Self::Foreign => "foreign",
+            Self::Stuck => "stuck",
+            Self::Vetoed => "vetoed",
+            Self::Frozen => "frozen",
+            Self::Publishing => "publishing",
+        }
+    }
+}
+
+/// hooks invoked by the cleaner while it works through a cycle
+///
+/// every method has an empty default so implementors only override what they need.
+/// hooks are called synchronously from the cleanup loop and should not block.
+pub trait CleanerObserver: Send + Sync {
+    fn on_delete(&self, _segment: &SegmentInfo, _reason: Reason) {}
+
+    fn on_skip(&self, _segment: &SegmentInfo, _reason: SkipReason) {}
+
+    /// `stream` is the stream the error belongs to, if it is known
+    fn on_error(&self, _stream: Option<&str>, _error: &CleanerError) {}
+
+    fn on_cycle_end(&self, _report: &CleanReport) {}
+
+    /// `segments` were archived, or stitched, into `path` and recorded in its manifest
+    fn on_archive(&self, _segments: &[&SegmentInfo], _path: &Path, _entry: &ManifestEntry) {}
+
+    /// the archived file at `path` was uploaded and removed locally
+    fn on_upload(&self, _path: &Path, _record: &UploadRecord) {}
+
+    /// the remote copy of the archived file at `path` was deleted after the remote retention
+    fn on_remote_expire(&self, _path: &Path, _record: &ExpiryRecord) {}
+
+    /// the cleaner stopped, observers buffering events write them out
+    fn flush(&self) {}
+}
+
+/// fans every event out to the registered observers in order
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn CleanerObserver>>);
+
+impl Observers {
+    pub(crate) fn push(&mut self, observer: Arc<dyn CleanerObserver>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl CleanerObserver for Observers {
+    fn on_delete(&self, segment: &SegmentInfo, reason: Reason) {
+        self.0.iter().for_each(|o| o.on_delete(segment, reason));
+    }
+
+    fn on_skip(&self, segment: &SegmentInfo, reason: SkipReason) {
+        self.0.iter().for_each(|o| o.on_skip(segment, reason));
+    }
+
+    fn on_error(&self, stream: Option<&str>, error: &CleanerError) {
+        self.0.iter().for_each(|o| o.on_error(stream, error));
+    }
+
+    fn on_cycle_end(&self, report: &CleanReport) {
+        self.0.iter().for_each(|o| o.on_cycle_end(report));
+    }
+
+    fn on_archive(&self, segments: &[&SegmentInfo], path: &Path, entry: &ManifestEntry) {
+        self.0
+            .iter()
+            .for_each(|o| o.on_archive(segments, path, entry));
+    }
+
+    fn on_upload(&self, path: &Path, record: &UploadRecord) {
+        self.0.iter().for_each(|o| o.on_upload(path, record));
+    }
+
+    fn on_remote_expire(&self, path: &Path, record: &ExpiryRecord) {
+        self.0.iter().for_each(|o| o.on_remote_expire(path, record));
+    }
+
+    fn flush(&self) {
+        self.0.iter().for_each(|o| o.flush());
+    }
+}
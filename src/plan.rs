@@ -0,0 +1,9 @@
+use crate::{Action, Reason, SegmentInfo};
+
+/// a single action computed by [`Cleaner::plan`](crate::Cleaner::plan)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedAction {
+    pub segment: SegmentInfo,
+    pub action: Action,
+    pub reason: Reason,
+}
@@ -0,0 +1,165 @@
+//! differences between the platforms the cleaner runs on
+//!
+//! orphans age from the birth time on macos and the bsds, which record it reliably, and
+//! from the access time elsewhere. windows does not update access times by default and
+//! refuses to remove or rename a file another process has open without sharing it for
+//! deletion, as media servers do with the fragments they are serving.
+//!
+//! files flagged immutable or append-only, `chattr +i` on linux or `chflags uchg` on macos and
+//! the bsds, cannot be removed and are reported as protected.
+
+use std::{fs::Metadata, io, path::Path, time::SystemTime};
+
+use crate::storage::{DiskSpace, TimeSource};
+
+/// pauses between the attempts to remove or rename a file that is still open, windows only
+#[cfg(windows)]
+const SHARING_RETRY_DELAYS: [std::time::Duration; 3] = [
+    std::time::Duration::from_millis(50),
+    std::time::Duration::from_millis(200),
+    std::time::Duration::from_millis(500),
+];
+
+/// the timestamp the orphan rule ages a file from and where it came from, the first
+/// available of birth, modification and access time on macos and the bsds
+///
+/// ntfs only updates access times when enabled system wide, the modification time is used
+/// when it is more recent.
+pub(crate) fn aged_from(metadata: &Metadata) -> Option<(SystemTime, TimeSource)> {
+    let birth = || Some((metadata.created().ok()?, TimeSource::Birth));
+    let modified = || Some((metadata.modified().ok()?, TimeSource::Modified));
+    let accessed = || Some((metadata.accessed().ok()?, TimeSource::Accessed));
+    if cfg!(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly"
+    )) {
+        birth().or_else(modified).or_else(accessed)
+    } else if cfg!(windows) {
+        match (accessed(), modified()) {
+            (Some(a), Some(m)) if m.0 > a.0 => Some(m),
+            (a, m) => a.or(m),
+        }
+    } else {
+        accessed().or_else(modified)
+    }
+}
+
+/// filesystem holding the file
+#[cfg(unix)]
+pub(crate) fn device(metadata: &Metadata) -> Option<u64> {
+    Some(std::os::unix::fs::MetadataExt::dev(metadata))
+}
+
+/// windows volume serial numbers are not exposed on stable, the guard is disabled there
+#[cfg(not(unix))]
+pub(crate) fn device(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// owner, group and permission bits of the file
+#[cfg(unix)]
+pub(crate) fn ownership(metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.uid()),
+        Some(metadata.gid()),
+        Some(metadata.mode() & 0o7777),
+    )
+}
+
+/// windows has no unix owners or permission bits
+#[cfg(not(unix))]
+pub(crate) fn ownership(_metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
+
+/// whether the file at `path` carries the immutable or append-only attribute
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub(crate) fn is_protected(path: &Path, _metadata: &Metadata) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    // STATX_ATTR_IMMUTABLE and STATX_ATTR_APPEND
+    const PROTECTED: u64 = 0x10 | 0x20;
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is nul terminated and statx only writes into the zeroed buffer
+    let attributes = unsafe {
+        let mut buf = std::mem::zeroed::<libc::statx>();
+        match libc::statx(libc::AT_FDCWD, path.as_ptr(), 0, 0, &mut buf) {
+            0 => buf.stx_attributes & buf.stx_attributes_mask,
+            _ => 0,
+        }
+    };
+    attributes & PROTECTED != 0
+}
+
+/// whether the file carries the immutable or append-only flag, user or system
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub(crate) fn is_protected(_path: &Path, metadata: &Metadata) -> bool {
+    #[cfg(target_os = "freebsd")]
+    use std::os::freebsd::fs::MetadataExt;
+    #[cfg(target_os = "macos")]
+    use std::os::macos::fs::MetadataExt;
+    // UF_IMMUTABLE, UF_APPEND, SF_IMMUTABLE and SF_APPEND
+    const PROTECTED: u32 = 0x2 | 0x4 | 0x20000 | 0x40000;
+    metadata.st_flags() & PROTECTED != 0
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", any(target_env = "gnu", target_env = "musl")),
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+pub(crate) fn is_protected(_path: &Path, _metadata: &Metadata) -> bool {
+    false
+}
+
+/// size and free space of the filesystem holding `path`
+#[cfg(unix)]
+// the statvfs fields are narrower than u64 on some platforms
+#[allow(clippy::useless_conversion)]
+pub(crate) fn disk_space(path: &Path) -> io::Result<Option<DiskSpace>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is nul terminated and statvfs only writes into the zeroed buffer
+    let buf = unsafe {
+        let mut buf = std::mem::zeroed::<libc::statvfs>();
+        if libc::statvfs(path.as_ptr(), &mut buf) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf
+    };
+    let block = u64::from(buf.f_frsize);
+    Ok(Some(DiskSpace {
+        total: u64::from(buf.f_blocks) * block,
+        available: u64::from(buf.f_bavail) * block,
+    }))
+}
+
+/// free space would need `GetDiskFreeSpaceExW`, capacity forecasts are disabled on windows
+#[cfg(not(unix))]
+pub(crate) fn disk_space(_path: &Path) -> io::Result<Option<DiskSpace>> {
+    Ok(None)
+}
+
+/// the file is open by another process that did not allow deleting it
+pub(crate) fn is_sharing_violation(e: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33))
+}
+
+/// run `op` until it no longer fails with a sharing violation, a few times at most
+pub(crate) fn retry_shared<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    #[cfg(windows)]
+    for delay in SHARING_RETRY_DELAYS {
+        match op() {
+            Err(e) if is_sharing_violation(&e) => std::thread::sleep(delay),
+            result => return result,
+        }
+    }
+    op()
+}
@@ -0,0 +1,94 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{segment::parse_segment_name, storage::Storage, CleanerError, Result};
+
+/// the parts of a media playlist the cleaner cares about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Playlist {
+    pub path: PathBuf,
+    pub target_duration: Duration,
+    pub media_sequence: usize,
+    pub has_end_list: bool,
+    pub segments: Vec<PlaylistSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistSegment {
+    pub uri: String,
+    pub duration: Duration,
+    /// `#EXT-X-DATERANGE` preceding the segment
+    pub date_range: Option<DateRange>,
+}
+
+/// an `#EXT-X-DATERANGE` tag, ad breaks are signaled by its `SCTE35-OUT` and `SCTE35-IN`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateRange {
+    pub id: String,
+    /// the whole tag line, carried into archived playlists as is
+    pub tag: String,
+    /// an ad break or program starts at this segment
+    pub scte35_out: bool,
+    /// the ad break ended, this segment is the first one after it
+    pub scte35_in: bool,
+}
+
+impl Playlist {
+    pub fn read(storage: &dyn Storage, path: &Path) -> Result<Self> {
+        Self::parse(path, &storage.read_to_string(path)?)
+    }
+
+    pub fn parse(path: &Path, content: &str) -> Result<Self> {
+        let playlist = hls_m3u8::MediaPlaylist::from_str(content).map_err(|source| {
+            CleanerError::PlaylistParse {
+                path: path.to_owned(),
+                source,
+            }
+        })?;
+        Ok(Self {
+            path: path.to_owned(),
+            target_duration: playlist.target_duration,
+            media_sequence: playlist.media_sequence,
+            has_end_list: playlist.has_end_list,
+            segments: playlist
+                .segments
+                .iter()
+                .map(|(_, seg)| PlaylistSegment {
+                    uri: seg.uri().to_string(),
+                    duration: seg.duration.duration(),
+                    date_range: seg.date_range.as_ref().map(|tag| DateRange {
+                        id: tag.id().to_string(),
+                        tag: tag.to_string(),
+                        scte35_out: tag.scte35_out().is_some(),
+                        scte35_in: tag.scte35_in().is_some(),
+                    }),
+                })
+                .collect(),
+        })
+    }
+
+    /// smallest sequence number referenced by the playlist
+    pub fn min_sequence(&self) -> Result<u32> {
+        self.segments
+            .iter()
+            .map(|seg| parse_segment_name(Path::new(&seg.uri)).map(|(_, num)| num))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .min()
+            .ok_or_else(|| CleanerError::EmptyPlaylist {
+                path: self.path.clone(),
+            })
+    }
+
+    /// whether a segment uri of the playlist ends in `file_name`
+    pub fn references(&self, file_name: &str) -> bool {
+        self.segments.iter().any(|seg| {
+            Path::new(&seg.uri)
+                .file_name()
+                .is_some_and(|n| n == file_name)
+        })
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{archive, encrypt::EncryptionKey, segment, storage::FileMeta, CleanerError};
+
+/// what happens to a fragment selected for removal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Action {
+    /// unlink the file
+    #[default]
+    Delete,
+    /// move the file under the root trash directory so it can be restored later
+    Trash,
+    /// move the file to the policy archive template, kept indefinitely
+    Archive,
+}
+
+impl std::str::FromStr for Action {
+    type Err = CleanerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Self::Delete),
+            "trash" => Ok(Self::Trash),
+            "archive" => Ok(Self::Archive),
+            _ => Err(CleanerError::Config(format!("unknown action {}", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Delete => "delete",
+            Self::Trash => "trash",
+            Self::Archive => "archive",
+        })
+    }
+}
+
+/// retention rules applied to every fragment
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// fragments without a playlist are deleted once older than this
+    pub orphan_max_age: Duration,
+    pub action: Action,
+    /// trashed fragments are purged by [`Cleaner::gc`](crate::Cleaner::gc) after this
+    pub trash_retention: Duration,
+    /// destination directory of archived fragments, see [`archive`](crate::archive)
+    pub archive_template: String,
+    /// zstd level archived fragments are compressed with, needs the `zstd` feature
+    pub archive_compression: Option<i32>,
+    /// seal archived fragments with this key, needs the `encryption` feature
+    pub archive_key: Option<EncryptionKey>,
+    /// stitch finished streams into one ts file below this template before removing their
+    /// fragments, see [`vod`](crate::vod)
+    pub stitch_template: Option<String>,
+    /// archived content stays local this long before it is uploaded to the remote store
+    pub local_retention: Duration,
+    /// remote copies are deleted once older than this, kept forever when unset
+    pub remote_retention: Option<Duration>,
+    /// keep a vod playlist next to archived fragments, see [`vod`](crate::vod), ignored when
+    /// they are compressed or encrypted
+    pub vod_playlists: bool,
+    /// fragments of an ad break without an `SCTE35-IN` are held back at most this long, see
+    /// [`Reason::Unreferenced`](crate::Reason::Unreferenced)
+    pub max_break_hold: Duration,
+    /// unreferenced fragments are kept this long after the playlist first moved past them,
+    /// measured with the [`lifecycle`](crate::lifecycle) store of the cleaner
+    pub unreferenced_grace: Duration,
+    /// only clean and upload the streams of this shard, every stream when unset
+    pub shard: Option<Shard>,
+    /// follow and delete files on other filesystems than the root, such as a volume mounted
+    /// below it, off by default
+    pub cross_devices: bool,
+    /// only files with these owners and permissions are removed, any by default
+    pub ownership: Ownership,
+    /// orphans timestamped further in the future age from when the cleaner first saw them
+    pub clock_skew_tolerance: Duration,
+    /// target durations without a playlist update, while fragments keep arriving, after which
+    /// a stream counts as stuck, 0 disables the detection
+    pub stuck_after: u32,
+    /// companion file a packager holds while it publishes a stream, `{stream}` is replaced by
+    /// the stream name and the path is relative to the fragments. orphans are kept while it
+    /// exists, unset by default
+    pub publisher_lock: Option<String>,
+    /// file name of the playlist of a stream next to its fragments, `{stream}` is replaced by
+    /// the stream name, `{stream}.m3u8` by default
+    pub playlist_template: String,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            orphan_max_age: Duration::from_secs(1800),
+            action: Action::default(),
+            trash_retention: Duration::from_secs(24 * 3600),
+            archive_template: archive::DEFAULT_TEMPLATE.to_owned(),
+            archive_compression: None,
+            archive_key: None,
+            stitch_template: None,
+            local_retention: Duration::ZERO,
+            remote_retention: None,
+            vod_playlists: false,
+            max_break_hold: Duration::from_secs(600),
+            unreferenced_grace: Duration::ZERO,
+            shard: None,
+            cross_devices: false,
+            ownership: Ownership::default(),
+            clock_skew_tolerance: Duration::from_secs(60),
+            stuck_after: 3,
+            publisher_lock: None,
+            playlist_template: segment::DEFAULT_PLAYLIST_TEMPLATE.to_owned(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn orphan_max_age(mut self, age: Duration) -> Self {
+        self.orphan_max_age = age;
+        self
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn trash_retention(mut self, retention: Duration) -> Self {
+        self.trash_retention = retention;
+        self
+    }
+
+    pub fn archive_template(mut self, template: impl Into<String>) -> Self {
+        self.archive_template = template.into();
+        self
+    }
+
+    pub fn archive_compression(mut self, level: Option<i32>) -> Self {
+        self.archive_compression = level;
+        self
+    }
+
+    pub fn archive_key(mut self, key: Option<EncryptionKey>) -> Self {
+        self.archive_key = key;
+        self
+    }
+
+    pub fn stitch_template(mut self, template: Option<String>) -> Self {
+        self.stitch_template = template;
+        self
+    }
+
+    pub fn local_retention(mut self, retention: Duration) -> Self {
+        self.local_retention = retention;
+        self
+    }
+
+    pub fn remote_retention(mut self, retention: Option<Duration>) -> Self {
+        self.remote_retention = retention;
+        self
+    }
+
+    pub fn vod_playlists(mut self, enabled: bool) -> Self {
+        self.vod_playlists = enabled;
+        self
+    }
+
+    pub fn max_break_hold(mut self, hold: Duration) -> Self {
+        self.max_break_hold = hold;
+        self
+    }
+
+    pub fn unreferenced_grace(mut self, grace: Duration) -> Self {
+        self.unreferenced_grace = grace;
+        self
+    }
+
+    pub fn shard(mut self, shard: Option<Shard>) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    pub fn cross_devices(mut self, enabled: bool) -> Self {
+        self.cross_devices = enabled;
+        self
+    }
+
+    pub fn ownership(mut self, ownership: Ownership) -> Self {
+        self.ownership = ownership;
+        self
+    }
+
+    pub fn clock_skew_tolerance(mut self, tolerance: Duration) -> Self {
+        self.clock_skew_tolerance = tolerance;
+        self
+    }
+
+    /// report a stream as stuck once its playlist was not updated for this many target
+    /// durations while fragments keep arriving, and keep the fragments written since. 3 by
+    /// default, 0 disables the detection
+    pub fn stuck_after(mut self, target_durations: u32) -> Self {
+        self.stuck_after = target_durations;
+        self
+    }
+
+    /// keep the orphaned fragments of a stream while this file exists next to them, such as
+    /// `{stream}.lock`, so a playlist missing during a rotation does not age them out
+    pub fn publisher_lock(mut self, name: Option<String>) -> Self {
+        self.publisher_lock = name;
+        self
+    }
+
+    /// `index.m3u8` for one stream per directory or `{stream}.m3u` for another extension, see
+    /// [`check_playlist_template`](crate::check_playlist_template)
+    pub fn playlist_template(mut self, template: impl Into<String>) -> Self {
+        self.playlist_template = template.into();
+        self
+    }
+
+    /// the publisher lock of `stream` for fragments in `dir`, expanded from the raw stream name
+    /// so it matches streams whose name is not utf-8 too
+    pub(crate) fn publisher_lock_path(&self, dir: &Path, stream: &OsStr) -> Option<PathBuf> {
+        let name = self.publisher_lock.as_ref()?;
+        Some(dir.join(segment::playlist_name(stream, name)))
+    }
+
+    /// whether `stream` belongs to the shard of the policy, files of an unknown stream belong
+    /// to the first shard so they are still reported once
+    pub(crate) fn owns(&self, stream: Option<&str>) -> bool {
+        match (self.shard, stream) {
+            (None, _) => true,
+            (Some(shard), Some(stream)) => shard.owns(stream),
+            (Some(shard), None) => shard.index() == 1,
+        }
+    }
+}
+
+/// one of `count` disjoint subsets of the stream names, `index` counts from 1
+///
+/// streams are assigned by an fnv-1a hash of their name so every instance of a fleet agrees
+/// on the owner of a stream without coordinating, `2/8` is the second of eight shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: u32,
+    count: u32,
+}
+
+impl Shard {
+    pub fn new(index: u32, count: u32) -> Result<Self, CleanerError> {
+        if count == 0 || index == 0 || index > count {
+            return Err(CleanerError::Config(format!(
+                "shard {}/{} out of range, the index counts from 1 to the shard count",
+                index, count
+            )));
+        }
+        Ok(Self { index, count })
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// whether the fragments of `stream` are cleaned by this shard
+    pub fn owns(&self, stream: &str) -> bool {
+        // fnv-1a, std hashers are seeded per process
+        let hash = stream.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        hash % u64::from(self.count) == u64::from(self.index - 1)
+    }
+}
+
+impl std::str::FromStr for Shard {
+    type Err = CleanerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CleanerError::Config(format!("invalid shard {}, expected index/count", s));
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        Self::new(
+            index.trim().parse().map_err(|_| invalid())?,
+            count.trim().parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// owners and permissions a file needs before the cleaner removes it
+///
+/// on a shared origin only the files of the packager's user should go, a recording dropped
+/// by root is never touched. with any restriction set, files whose owner the storage backend
+/// cannot tell are kept too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ownership {
+    /// allowed owners, any when empty
+    pub uids: Vec<u32>,
+    /// allowed groups, any when empty
+    pub gids: Vec<u32>,
+    /// permission bits a file must all have, `0o020` for group writable
+    pub mode: Option<u32>,
+}
+
+impl Ownership {
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uids.push(uid);
+        self
+    }
+
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gids.push(gid);
+        self
+    }
+
+    pub fn mode(mut self, mode: Option<u32>) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// whether a file with `metadata` may be removed
+    pub(crate) fn allows(&self, metadata: &FileMeta) -> bool {
+        let owner =
+            |ids: &[u32], id: Option<u32>| ids.is_empty() || id.is_some_and(|id| ids.contains(&id));
+        owner(&self.uids, metadata.uid)
+            && owner(&self.gids, metadata.gid)
+            && self
+                .mode
+                .is_none_or(|mode| metadata.mode.is_some_and(|m| m & mode == mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(stream: &str, count: u32) -> u32 {
+        let owners = (1..=count)
+            .filter(|&index| Shard::new(index, count).unwrap().owns(stream))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            owners.len(),
+            1,
+            "{} is owned by shards {:?}",
+            stream,
+            owners
+        );
+        owners[0]
+    }
+
+    #[test]
+    fn streams_keep_their_shard_across_releases() {
+        // fnv-1a of the name modulo the count, `a` hashes to 0xaf63dc4c8601ec8c
+        let expected = [
+            ("a", 1, 2),
+            ("live", 4, 2),
+            ("camera-1", 1, 2),
+            ("camera-2", 2, 2),
+            ("news", 3, 1),
+        ];
+        for (stream, of_four, of_three) in expected {
+            assert_eq!(owner(stream, 4), of_four, "{} of 4", stream);
+            assert_eq!(owner(stream, 3), of_three, "{} of 3", stream);
+            assert_eq!(owner(stream, 1), 1);
+        }
+    }
+
+    #[test]
+    fn shards_split_the_streams_disjointly_and_completely() {
+        let streams = (0..1000)
+            .map(|i| format!("stream{}", i))
+            .collect::<Vec<_>>();
+        for count in [2, 3, 8] {
+            let mut sizes = vec![0; count as usize];
+            for stream in &streams {
+                sizes[owner(stream, count) as usize - 1] += 1;
+            }
+            assert_eq!(sizes.iter().sum::<usize>(), streams.len());
+            assert!(
+                sizes.iter().all(|&n| n > 0),
+                "empty shard of {}: {:?}",
+                count,
+                sizes
+            );
+        }
+    }
+
+    #[test]
+    fn shards_parse_from_index_and_count() {
+        assert_eq!("2/8".parse::<Shard>().unwrap(), Shard::new(2, 8).unwrap());
+        assert_eq!(Shard::new(2, 8).unwrap().to_string(), "2/8");
+        for invalid in ["0/4", "5/4", "1/0", "1", "a/b"] {
+            assert!(invalid.parse::<Shard>().is_err(), "{}", invalid);
+        }
+    }
+}
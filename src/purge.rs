@@ -0,0 +1,277 @@
+//! immediate removal of every artifact of a stream
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{segment, storage::Storage, CleanerError, Ownership, Policy, Result};
+
+/// outcome of [`Cleaner::purge_stream`](crate::Cleaner::purge_stream)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeReport {
+    pub stream: String,
+    pub dry_run: bool,
+    /// removed files and directories, or the ones that would be with `dry_run`
+    pub removed: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+/// whether `file_name` is an artifact of `stream`
+///
+/// matches the playlist of the stream under a `playlist_template` naming the stream,
+/// `<stream>.<ext>` (keys, thumbnails), `<stream>-init.<ext>` (init segments) and
+/// `<stream>-<sequence>.<ext>` (fragments). only the extension is cut, so purging `live`
+/// leaves `live-hd.m3u8`, `live-hd-3.ts` and `live.hd-3.ts` alone. a playlist template without
+/// `{stream}` names a playlist shared by the directory, see [`artifacts`].
+pub(crate) fn is_artifact(file_name: &str, stream: &str, playlist_template: &str) -> bool {
+    let names_stream = playlist_template.contains("{stream}");
+    if names_stream && segment::playlist_name(stream, playlist_template) == file_name {
+        return true;
+    }
+    let stem = Path::new(file_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(file_name);
+    // unless it is the playlist of a stream named `<stream>-init`
+    let init = stem.strip_suffix("-init") == Some(stream)
+        && !(names_stream && segment::is_playlist(Path::new(file_name), playlist_template));
+    stem == stream || init || segment::parse_stem(stem).is_ok_and(|(base, _)| base == stream)
+}
+
+/// files and directories under `root` belonging to `stream`
+///
+/// a playlist shared by the directory is only included when every fragment next to it
+/// belongs to `stream`, it is the live playlist of the other streams otherwise.
+pub(crate) fn artifacts(
+    storage: &dyn Storage,
+    root: &Path,
+    stream: &str,
+    playlist_template: &str,
+) -> Result<Vec<PathBuf>> {
+    let entries = storage.list(root)?;
+    let shared = (!playlist_template.contains("{stream}"))
+        .then(|| root.join(playlist_template))
+        .filter(|_| {
+            let mut streams = entries
+                .iter()
+                .filter(|e| !e.is_dir)
+                .filter_map(|e| segment::parse_segment_name(&e.path).ok())
+                .map(|(base, _)| base)
+                .peekable();
+            streams.peek().is_some() && streams.all(|base| base == stream)
+        });
+    Ok(entries
+        .into_iter()
+        .filter(|e| {
+            let Some(name) = e.path.file_name().and_then(|n| n.to_str()) else {
+                return false;
+            };
+            match e.is_dir {
+                true => name == stream,
+                false => {
+                    shared.as_ref() == Some(&e.path) || is_artifact(name, stream, playlist_template)
+                }
+            }
+        })
+        .map(|e| e.path)
+        .collect())
+}
+
+pub(crate) fn purge(
+    storage: &dyn Storage,
+    root: &Path,
+    stream: &str,
+    dry_run: bool,
+    root_device: Option<u64>,
+    policy: &Policy,
+) -> Result<PurgeReport> {
+    let ownership = &policy.ownership;
+    if stream.is_empty() || stream.contains(['/', '\\']) || stream == "." || stream == ".." {
+        return Err(CleanerError::Config(format!(
+            "invalid stream name {:?}",
+            stream
+        )));
+    }
+    let mut report = PurgeReport {
+        stream: stream.to_owned(),
+        dry_run,
+        ..Default::default()
+    };
+    for path in artifacts(storage, root, stream, &policy.playlist_template)? {
+        let is_dir = root.join(stream) == path;
+        // a stream directory holding a mounted volume or foreign files is left alone as a whole
+        let outcome = match root_device.is_some() || *ownership != Ownership::default() {
+            true => refusal(storage, &path, is_dir, root_device, ownership),
+            false => Ok(None),
+        };
+        let outcome = outcome.and_then(|refusal| match refusal {
+            Some(e) => Err(e),
+            None if dry_run => Ok(()),
+            None if is_dir => storage.remove_dir_all(&path),
+            None => storage.remove(&path),
+        });
+        if dry_run && outcome.is_ok() {
+            report.removed.push(path);
+            continue;
+        }
+        match outcome {
+            Ok(()) => {
+                tracing::info!("purged {}", path.display());
+                report.removed.push(path);
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "unable to purge {} - {}",
+                    path.display(),
+                    crate::report::error_chain(&e)
+                );
+                report.errors.push(crate::report::error_chain(&e));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// why `path` or something below it must not be removed, the first file on another
+/// filesystem than `device` or not allowed by `ownership`
+fn refusal(
+    storage: &dyn Storage,
+    path: &Path,
+    is_dir: bool,
+    device: Option<u64>,
+    ownership: &Ownership,
+) -> Result<Option<CleanerError>> {
+    if let Ok(metadata) = storage.metadata(path) {
+        let path = path.to_owned();
+        if metadata.is_on_other_device(device) {
+            return Ok(Some(CleanerError::OtherDevice { path }));
+        }
+        if !is_dir && !ownership.allows(&metadata) {
+            return Ok(Some(CleanerError::Foreign { path }));
+        }
+    }
+    if !is_dir {
+        return Ok(None);
+    }
+    for entry in storage.list(path)? {
+        if let Some(e) = refusal(storage, &entry.path, entry.is_dir, device, ownership)? {
+            return Ok(Some(e));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{storage::MemoryStore, DEFAULT_PLAYLIST_TEMPLATE};
+
+    fn artifact(name: &str, stream: &str) -> bool {
+        is_artifact(name, stream, DEFAULT_PLAYLIST_TEMPLATE)
+    }
+
+    #[test]
+    fn other_streams_sharing_a_prefix_are_not_artifacts() {
+        for name in ["live.m3u8", "live-3.ts", "live.key", "live.jpg"] {
+            assert!(artifact(name, "live"), "{}", name);
+        }
+        for name in [
+            "live-hd.m3u8",
+            "live-hd-3.ts",
+            "live-hd.key",
+            "live-hd-init.mp4",
+        ] {
+            assert!(!artifact(name, "live"), "{}", name);
+        }
+        assert!(artifact("live-hd.m3u8", "live-hd"));
+        assert!(artifact("live-hd-3.ts", "live-hd"));
+    }
+
+    #[test]
+    fn init_segments_are_artifacts() {
+        for name in ["live-init.mp4", "live-init.m4s"] {
+            assert!(artifact(name, "live"), "{}", name);
+        }
+        assert!(artifact("live-hd-init.mp4", "live-hd"));
+        // the playlist and fragments of a stream named `live-init`
+        assert!(!artifact("live-init.m3u8", "live"));
+        assert!(!artifact("live-init-3.ts", "live"));
+    }
+
+    #[test]
+    fn dotted_stream_names() {
+        for name in ["live.hd.m3u8", "live.hd-2.ts"] {
+            assert!(!artifact(name, "live"), "{}", name);
+            assert!(artifact(name, "live.hd"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn playlist_template() {
+        assert!(is_artifact("live.m3u", "live", "{stream}.m3u"));
+        // shared by the directory, see `artifacts`
+        assert!(!is_artifact("index.m3u8", "live", "index.m3u8"));
+        assert!(!is_artifact(
+            "index.m3u8",
+            "live",
+            DEFAULT_PLAYLIST_TEMPLATE
+        ));
+    }
+
+    #[test]
+    fn shared_playlists_are_only_purged_with_the_last_stream() {
+        let store = MemoryStore::new();
+        let root = Path::new("/hls");
+        for name in ["index.m3u8", "live-1.ts", "live-init.mp4", "other-1.ts"] {
+            store.insert(root.join(name), "x", SystemTime::UNIX_EPOCH);
+        }
+        let removed = |stream| artifacts(&store, root, stream, "index.m3u8").unwrap();
+        assert_eq!(
+            removed("live"),
+            [root.join("live-1.ts"), root.join("live-init.mp4")]
+        );
+        store.remove(&root.join("other-1.ts")).unwrap();
+        assert_eq!(
+            removed("live"),
+            [
+                root.join("index.m3u8"),
+                root.join("live-1.ts"),
+                root.join("live-init.mp4")
+            ]
+        );
+        // a directory without fragments does not tell whose playlist it is
+        store.remove(&root.join("live-1.ts")).unwrap();
+        assert_eq!(removed("live"), [root.join("live-init.mp4")]);
+    }
+
+    #[test]
+    fn dry_run_lists_only_the_stream() {
+        let store = MemoryStore::new();
+        let root = Path::new("/hls");
+        let now = SystemTime::UNIX_EPOCH;
+        for name in [
+            "live.m3u8",
+            "live-1.ts",
+            "live-hd.m3u8",
+            "live-hd-1.ts",
+            "live.hd.m3u8",
+            "live.hd-1.ts",
+        ] {
+            store.insert(root.join(name), "x", now);
+        }
+        let report = purge(&store, root, "live", true, None, &Policy::default()).unwrap();
+        assert_eq!(
+            report.removed,
+            [root.join("live-1.ts"), root.join("live.m3u8")]
+        );
+        assert_eq!(store.paths().len(), 6);
+        let report = purge(&store, root, "live.hd", false, None, &Policy::default()).unwrap();
+        assert_eq!(
+            report.removed,
+            [root.join("live.hd-1.ts"), root.join("live.hd.m3u8")]
+        );
+        assert_eq!(store.paths().len(), 4);
+    }
+}
@@ -0,0 +1,168 @@
+//! python module, enabled by the `python` feature and built with maturin
+//!
+//! ```python
+//! from hls_fragment_cleaner import Cleaner, Policy
+//! cleaner = Cleaner("/var/hls", Policy(orphan_max_age_secs=600, action="trash"))
+//! plan = cleaner.plan()
+//! report = cleaner.apply([a for a in plan if a.stream != "keep"])
+//! ```
+
+use std::{path::PathBuf, time::Duration};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{Action, CleanReport, PlannedAction};
+
+#[pyclass(name = "Policy", from_py_object)]
+#[derive(Clone)]
+struct PyPolicy {
+    inner: crate::Policy,
+}
+
+#[pymethods]
+impl PyPolicy {
+    #[new]
+    #[pyo3(signature = (orphan_max_age_secs = 1800, action = "delete"))]
+    fn new(orphan_max_age_secs: u64, action: &str) -> PyResult<Self> {
+        let action = action
+            .parse::<Action>()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            inner: crate::Policy::default()
+                .orphan_max_age(Duration::from_secs(orphan_max_age_secs))
+                .action(action),
+        })
+    }
+}
+
+#[pyclass(name = "PlannedAction", get_all, from_py_object)]
+#[derive(Clone)]
+struct PyPlannedAction {
+    path: PathBuf,
+    stream: String,
+    sequence: u32,
+    size: u64,
+    action: String,
+    reason: &'static str,
+}
+
+#[pymethods]
+impl PyPlannedAction {
+    fn __repr__(&self) -> String {
+        format!(
+            "PlannedAction({} {} - {})",
+            self.action,
+            self.path.display(),
+            self.reason
+        )
+    }
+}
+
+impl PyPlannedAction {
+    fn from_action(planned: &PlannedAction) -> Self {
+        Self {
+            path: planned.segment.path.clone(),
+            stream: planned.segment.stream.clone(),
+            sequence: planned.segment.sequence,
+            size: planned.segment.size,
+            action: planned.action.to_string(),
+            reason: planned.reason.name(),
+        }
+    }
+}
+
+#[pyclass(name = "CleanReport", get_all)]
+struct PyCleanReport {
+    scanned: usize,
+    skipped: usize,
+    deleted: usize,
+    bytes_freed: u64,
+    errors: Vec<String>,
+    cancelled: bool,
+    duration_secs: f64,
+    json: String,
+}
+
+impl From<CleanReport> for PyCleanReport {
+    fn from(report: CleanReport) -> Self {
+        Self {
+            scanned: report.scanned,
+            skipped: report.skipped,
+            deleted: report.deleted,
+            bytes_freed: report.bytes_freed,
+            errors: report.errors.iter().map(|e| e.message.clone()).collect(),
+            cancelled: report.cancelled,
+            duration_secs: report.duration.as_secs_f64(),
+            json: serde_json::to_string(&report).unwrap_or_default(),
+        }
+    }
+}
+
+#[pyclass(name = "Cleaner")]
+struct PyCleaner {
+    inner: crate::Cleaner,
+    plans: Vec<PlannedAction>,
+}
+
+#[pymethods]
+impl PyCleaner {
+    #[new]
+    #[pyo3(signature = (root, policy = None))]
+    fn new(root: PathBuf, policy: Option<PyPolicy>) -> Self {
+        let inner = crate::Cleaner::builder()
+            .root(root)
+            .policy(policy.map(|p| p.inner).unwrap_or_default())
+            .build();
+        Self {
+            inner,
+            plans: Vec::new(),
+        }
+    }
+
+    /// run one cycle, blocking until it is done
+    fn clean_once(&self, py: Python<'_>) -> PyResult<PyCleanReport> {
+        let cleaner = self.inner.clone();
+        py.detach(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?
+                .block_on(cleaner.clean_once())
+                .map(PyCleanReport::from)
+                .map_err(|e| PyValueError::new_err(crate::report::error_chain(&e)))
+        })
+    }
+
+    /// actions the next cycle would take, nothing is touched
+    fn plan(&mut self) -> PyResult<Vec<PyPlannedAction>> {
+        self.plans = self
+            .inner
+            .plan()
+            .map_err(|e| PyValueError::new_err(crate::report::error_chain(&e)))?;
+        Ok(self
+            .plans
+            .iter()
+            .map(PyPlannedAction::from_action)
+            .collect())
+    }
+
+    /// apply a subset of the actions returned by the last `plan()` call
+    fn apply(&self, actions: Vec<PyPlannedAction>) -> PyCleanReport {
+        let plan = self
+            .plans
+            .iter()
+            .filter(|p| actions.iter().any(|a| a.path == p.segment.path))
+            .cloned()
+            .collect();
+        self.inner.apply(plan).into()
+    }
+}
+
+#[pymodule]
+fn hls_fragment_cleaner(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCleaner>()?;
+    m.add_class::<PyPolicy>()?;
+    m.add_class::<PyPlannedAction>()?;
+    m.add_class::<PyCleanReport>()?;
+    Ok(())
+}
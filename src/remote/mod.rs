@@ -0,0 +1,253 @@
+//! remote object stores archived content is pushed to
+//!
+//! after each cycle the cleaner uploads every file below the archive and stitch roots to the
+//! configured [`RemoteStore`] and only removes the local copy once the upload succeeded, so an
+//! interrupted upload is simply retried on the next cycle. manifests and vod playlists are
+//! uploaded along with their directory but kept locally.
+//!
+//! the policy tiers decide the timing, files stay local for the local retention before being
+//! uploaded and remote copies are deleted once older than the remote retention. each
+//! transition is appended to the manifest, see [`archive::tiers`].
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    archive::{self, ExpiryRecord, UploadRecord},
+    serde_time,
+    storage::Storage,
+    CleanReport, CleanerObserver, Policy, Result,
+};
+
+#[cfg(feature = "s3")]
+mod s3;
+
+#[cfg(feature = "s3")]
+pub use s3::S3Store;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub trait RemoteStore: Send + Sync {
+    /// upload the local file at `path` to `key`, replacing any existing object
+    ///
+    /// the file is read directly from the local filesystem, not through the cleaner storage.
+    fn put<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, Result<()>>;
+
+    /// delete the object at `key`, succeeds if it does not exist
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// human readable location of `key`, `s3://bucket/key`
+    fn url(&self, key: &str) -> String;
+}
+
+/// upload every file below `base` older than the local retention of `policy`, keys are
+/// relative to the parent of `base`, then expire remote copies older than the remote retention
+pub(crate) async fn upload_dir(
+    storage: &dyn Storage,
+    remote: &dyn RemoteStore,
+    base: &Path,
+    policy: &Policy,
+    now: SystemTime,
+    observer: &dyn CleanerObserver,
+    report: &mut CleanReport,
+) {
+    if !storage.exists(base) {
+        return;
+    }
+    let prefix = base.parent().unwrap_or(Path::new(""));
+    // the archive may well be a volume of its own, the walk stays on it
+    let device = match policy.cross_devices {
+        true => None,
+        false => storage.metadata(base).ok().and_then(|m| m.device),
+    };
+    let mut pending = vec![base.to_owned()];
+    while let Some(dir) = pending.pop() {
+        let entries = match storage.list(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("{}", e);
+                report.record_error(None, &e);
+                continue;
+            }
+        };
+        let (dirs, files): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .filter(|e| {
+                device.is_none()
+                    || !storage
+                        .metadata(&e.path)
+                        .is_ok_and(|m| m.is_on_other_device(device))
+            })
+            .partition(|e| e.is_dir);
+        pending.extend(dirs.into_iter().map(|e| e.path));
+        let (kept, media): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .map(|e| e.path)
+            .filter(|p| !p.to_string_lossy().ends_with(".tmp"))
+            .partition(|p| is_kept_locally(p));
+        // other shards upload the streams they own
+        let (kept, media) = match policy.shard {
+            Some(_) => {
+                let owned = |p: &PathBuf| policy.owns(stream_of(p, &kept));
+                let media = media.into_iter().filter(owned).collect::<Vec<_>>();
+                (kept.iter().filter(|p| owned(p)).cloned().collect(), media)
+            }
+            None => (kept, media),
+        };
+        let mut uploaded_any = false;
+        for path in media {
+            let modified = storage.metadata(&path).ok().and_then(|m| m.modified);
+            let age = modified.and_then(|m| now.duration_since(m).ok());
+            if age.is_none_or(|age| age < policy.local_retention) {
+                continue;
+            }
+            match upload_file(storage, remote, prefix, &path, &kept, now, observer).await {
+                Ok(size) => {
+                    uploaded_any = true;
+                    report.uploaded += 1;
+                    report.bytes_uploaded += size;
+                }
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    report.record_error(None, &e);
+                }
+            }
+        }
+        if let Some(retention) = policy.remote_retention {
+            for manifest in kept.iter().filter(|p| is_manifest(p)) {
+                if expire(storage, remote, manifest, retention, now, observer, report).await {
+                    uploaded_any = true;
+                }
+            }
+        }
+        if uploaded_any {
+            for path in kept {
+                if let Err(e) = remote.put(&object_key(prefix, &path), &path).await {
+                    tracing::warn!("{}", e);
+                    report.record_error(None, &e);
+                }
+            }
+        }
+    }
+}
+
+/// manifests and vod playlists keep changing and stay local, uploaded copies are refreshed
+fn is_kept_locally(path: &Path) -> bool {
+    is_manifest(path) || path.to_string_lossy().ends_with(".m3u8")
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".manifest.ndjson")
+}
+
+/// delete the remote copies listed in `manifest` that outlived the retention, returns whether
+/// the manifest changed
+async fn expire(
+    storage: &dyn Storage,
+    remote: &dyn RemoteStore,
+    manifest: &Path,
+    retention: Duration,
+    now: SystemTime,
+    observer: &dyn CleanerObserver,
+    report: &mut CleanReport,
+) -> bool {
+    let now_secs = serde_time::to_unix_secs(now);
+    let mut changed = false;
+    for (record, expired) in archive::uploads(storage, manifest) {
+        if expired || record.key.is_empty() {
+            continue;
+        }
+        if record.uploaded_at.saturating_add(retention.as_secs()) > now_secs {
+            continue;
+        }
+        let expiry = ExpiryRecord {
+            file: record.file,
+            expired_at: now_secs,
+        };
+        let result = match remote.delete(&record.key).await {
+            Ok(()) => archive::append_manifest(storage, manifest, &expiry),
+            Err(e) => Err(e),
+        };
+        if result.is_ok() {
+            let dir = manifest.parent().unwrap_or(Path::new(""));
+            observer.on_remote_expire(&dir.join(&expiry.file), &expiry);
+        }
+        match result {
+            Ok(()) => {
+                changed = true;
+                report.remote_deleted += 1;
+            }
+            Err(e) => {
+                tracing::warn!("{}", e);
+                report.record_error(None, &e);
+            }
+        }
+    }
+    changed
+}
+
+fn object_key(prefix: &Path, path: &Path) -> String {
+    path.strip_prefix(prefix)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// the manifest of the longest stream name the file starts with, `live-hd` over `live`,
+/// stitched files are named `<stream>.<first>-<last>.ts`
+fn manifest_for<'a>(file: &str, manifests: &'a [PathBuf]) -> Option<(&'a str, &'a PathBuf)> {
+    manifests
+        .iter()
+        .filter_map(|m| {
+            let name = m.file_name()?.to_str()?;
+            let stream = name.strip_suffix(".manifest.ndjson")?;
+            let rest = file.strip_prefix(stream)?;
+            (rest.starts_with('-') || rest.starts_with('.')).then_some((stream, m))
+        })
+        .max_by_key(|(stream, _)| stream.len())
+}
+
+/// stream a file of an archive directory belongs to
+fn stream_of<'a>(path: &'a Path, manifests: &'a [PathBuf]) -> Option<&'a str> {
+    let file = path.file_name()?.to_str()?;
+    file.strip_suffix(".manifest.ndjson")
+        .or_else(|| file.strip_suffix(".m3u8"))
+        .or_else(|| Some(manifest_for(file, manifests)?.0))
+}
+
+/// upload one media file, note it in its manifest and remove the local copy
+async fn upload_file(
+    storage: &dyn Storage,
+    remote: &dyn RemoteStore,
+    prefix: &Path,
+    path: &Path,
+    manifests: &[PathBuf],
+    now: SystemTime,
+    observer: &dyn CleanerObserver,
+) -> Result<u64> {
+    let size = storage.metadata(path)?.size;
+    let key = object_key(prefix, path);
+    remote.put(&key, path).await?;
+    let file = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some((_, manifest)) = manifest_for(&file, manifests) {
+        let record = UploadRecord {
+            file,
+            uploaded: remote.url(&key),
+            key,
+            uploaded_at: serde_time::to_unix_secs(now),
+        };
+        archive::append_manifest(storage, manifest, &record)?;
+        observer.on_upload(path, &record);
+    }
+    storage.remove(path)?;
+    Ok(size)
+}
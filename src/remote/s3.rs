@@ -0,0 +1,203 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart, StorageClass},
+    Client,
+};
+
+use super::{BoxFuture, RemoteStore};
+use crate::{CleanerError, Result};
+
+/// files above this are uploaded in parts
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+const PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// an s3 bucket, credentials and region come from the usual aws environment
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    storage_class: Option<StorageClass>,
+}
+
+impl S3Store {
+    /// `url` is `s3://bucket/optional/prefix`, requests are retried up to five times
+    pub async fn new(url: &str, storage_class: Option<&str>) -> Result<Self> {
+        let rest = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| CleanerError::Config(format!("{} is not an s3:// url", url)))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(CleanerError::Config(format!("{} has no bucket", url)));
+        }
+        let config = aws_config::from_env()
+            .retry_config(aws_config::retry::RetryConfig::standard().with_max_attempts(5))
+            .load()
+            .await;
+        Ok(Self {
+            client: Client::new(&config),
+            bucket: bucket.to_owned(),
+            prefix: prefix.trim_matches('/').to_owned(),
+            storage_class: storage_class.map(StorageClass::from),
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        match self.prefix.is_empty() {
+            true => key.to_owned(),
+            false => format!("{}/{}", self.prefix, key),
+        }
+    }
+
+    fn error(&self, key: &str, e: impl std::fmt::Display) -> CleanerError {
+        CleanerError::Storage(format!("upload to {} failed - {}", self.url(key), e))
+    }
+
+    async fn put_single(&self, key: &str, path: &Path) -> Result<()> {
+        let body = ByteStream::from_path(path)
+            .await
+            .map_err(|e| self.error(key, e))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .set_storage_class(self.storage_class.clone())
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| self.error(key, aws_sdk_s3::error::DisplayErrorContext(e)))?;
+        Ok(())
+    }
+
+    async fn put_multipart(&self, key: &str, path: &Path, size: u64) -> Result<()> {
+        let object_key = self.object_key(key);
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .set_storage_class(self.storage_class.clone())
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .send()
+            .await
+            .map_err(|e| self.error(key, aws_sdk_s3::error::DisplayErrorContext(e)))?;
+        let upload_id = upload.upload_id().unwrap_or_default().to_owned();
+        let result = self.upload_parts(key, path, size, &upload_id).await;
+        let parts = match result {
+            Ok(parts) => parts,
+            Err(e) => {
+                // leaving it would keep billing for the parts already uploaded
+                let _ = self
+                    .client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(&object_key)
+                    .upload_id(&upload_id)
+                    .send()
+                    .await;
+                return Err(e);
+            }
+        };
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| self.error(key, aws_sdk_s3::error::DisplayErrorContext(e)))?;
+        Ok(())
+    }
+
+    async fn upload_parts(
+        &self,
+        key: &str,
+        path: &Path,
+        size: u64,
+        upload_id: &str,
+    ) -> Result<Vec<CompletedPart>> {
+        let mut file = File::open(path).map_err(|e| CleanerError::io(path, e))?;
+        let mut parts = Vec::new();
+        let mut offset = 0;
+        let mut number = 1;
+        while offset < size {
+            let length = PART_SIZE.min(size - offset);
+            let mut chunk = vec![0; length as usize];
+            file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(&mut chunk))
+                .map_err(|e| CleanerError::io(path, e))?;
+            let body = ByteStream::from(chunk);
+            let part = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(self.object_key(key))
+                .upload_id(upload_id)
+                .part_number(number)
+                .checksum_algorithm(ChecksumAlgorithm::Sha256)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| self.error(key, aws_sdk_s3::error::DisplayErrorContext(e)))?;
+            parts.push(
+                CompletedPart::builder()
+                    .part_number(number)
+                    .set_e_tag(part.e_tag().map(str::to_owned))
+                    .set_checksum_sha256(part.checksum_sha256().map(str::to_owned))
+                    .build(),
+            );
+            offset += length;
+            number += 1;
+        }
+        Ok(parts)
+    }
+}
+
+impl RemoteStore for S3Store {
+    fn put<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let size = std::fs::metadata(path)
+                .map_err(|e| CleanerError::io(path, e))?
+                .len();
+            match size > MULTIPART_THRESHOLD {
+                true => self.put_multipart(key, path, size).await,
+                false => self.put_single(key, path).await,
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_object()
+                .bucket(&self.bucket)
+                .key(self.object_key(key))
+                .send()
+                .await
+                .map_err(|e| {
+                    CleanerError::Storage(format!(
+                        "deleting {} failed - {}",
+                        self.url(key),
+                        aws_sdk_s3::error::DisplayErrorContext(e)
+                    ))
+                })?;
+            Ok(())
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.bucket, self.object_key(key))
+    }
+}
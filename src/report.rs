@@ -0,0 +1,215 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{serde_time, Action, CapacityForecast, CleanerError, SegmentInfo, StuckStream};
+
+/// structured outcome of one cleanup cycle
+///
+/// returned by [`Cleaner::clean_once`](crate::Cleaner::clean_once) and handed to
+/// [`CleanerObserver::on_cycle_end`](crate::CleanerObserver::on_cycle_end).
+/// durations serialize as fractional seconds and timestamps as unix seconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CleanReport {
+    pub root: PathBuf,
+    #[serde(serialize_with = "serde_time::unix_secs")]
+    pub started_at: SystemTime,
+    pub scanned: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+    pub bytes_freed: u64,
+    /// selected for removal but left for later cycles by the deletion cap
+    pub deferred: usize,
+    /// archived files pushed to the remote store and removed locally
+    pub uploaded: usize,
+    pub bytes_uploaded: u64,
+    /// remote copies deleted after the remote retention
+    pub remote_deleted: usize,
+    pub errors: Vec<ReportError>,
+    pub streams: BTreeMap<String, StreamReport>,
+    /// the cycle stopped early because the cancellation token fired
+    pub cancelled: bool,
+    /// another replica held the distributed lock of the root, the cycle did nothing
+    pub lock_contended: bool,
+    /// the distributed lock could not be renewed and the cycle stopped early
+    pub lock_lost: bool,
+    /// how the shadow policy disagreed with the active one, when the cleaner has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowReport>,
+    /// streams whose playlist stopped updating while fragments keep arriving
+    pub stuck: Vec<StuckStream>,
+    /// free space of the filesystem of the root and when it runs out, when the storage knows
+    pub capacity: Option<CapacityForecast>,
+    #[serde(serialize_with = "serde_time::secs")]
+    pub plan_duration: Duration,
+    #[serde(serialize_with = "serde_time::secs")]
+    pub apply_duration: Duration,
+    #[serde(serialize_with = "serde_time::secs")]
+    pub duration: Duration,
+}
+
+/// per stream counters of a [`CleanReport`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StreamReport {
+    pub skipped: usize,
+    pub deleted: usize,
+    pub bytes_freed: u64,
+    pub errors: usize,
+}
+
+/// most disagreements kept as examples in a [`ShadowReport`]
+pub const SHADOW_EXAMPLES: usize = 10;
+
+/// fragments a shadow policy would have handled differently over one cycle, see
+/// [`CleanerBuilder::shadow_policy`](crate::CleanerBuilder::shadow_policy)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ShadowReport {
+    /// fragments evaluated by both policies
+    pub evaluated: usize,
+    /// kept by the active policy, removed by the shadow one
+    pub would_remove: usize,
+    pub would_remove_bytes: u64,
+    /// removed by the active policy, kept by the shadow one
+    pub would_keep: usize,
+    pub would_keep_bytes: u64,
+    /// removed by both policies, with another action
+    pub other_action: usize,
+    /// the first [`SHADOW_EXAMPLES`] disagreements
+    pub examples: Vec<ShadowExample>,
+}
+
+/// one fragment the policies disagree on, verdicts read `delete (orphaned)` or `keep (too_young)`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShadowExample {
+    pub path: PathBuf,
+    pub active: String,
+    pub shadow: String,
+}
+
+impl ShadowReport {
+    /// number of fragments the policies disagree on
+    pub fn disagreements(&self) -> usize {
+        self.would_remove + self.would_keep + self.other_action
+    }
+
+    /// compare the verdicts on `segment`, the action it is removed with or `None` when kept
+    /// along with the reason name
+    pub(crate) fn record(
+        &mut self,
+        segment: &SegmentInfo,
+        active: (Option<Action>, &str),
+        shadow: (Option<Action>, &str),
+    ) {
+        self.evaluated += 1;
+        match (active.0, shadow.0) {
+            (a, s) if a == s => return,
+            (None, _) => {
+                self.would_remove += 1;
+                self.would_remove_bytes += segment.size;
+            }
+            (_, None) => {
+                self.would_keep += 1;
+                self.would_keep_bytes += segment.size;
+            }
+            _ => self.other_action += 1,
+        }
+        if self.examples.len() < SHADOW_EXAMPLES {
+            let verdict = |(action, reason): (Option<Action>, &str)| match action {
+                Some(action) => format!("{} ({})", action, reason),
+                None => format!("keep ({})", reason),
+            };
+            self.examples.push(ShadowExample {
+                path: segment.path.clone(),
+                active: verdict(active),
+                shadow: verdict(shadow),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportError {
+    /// stream the error belongs to, if the fragment name could be parsed
+    pub stream: Option<String>,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl CleanReport {
+    pub fn new(root: impl Into<PathBuf>, started_at: SystemTime) -> Self {
+        Self {
+            root: root.into(),
+            started_at,
+            scanned: 0,
+            skipped: 0,
+            deleted: 0,
+            bytes_freed: 0,
+            deferred: 0,
+            uploaded: 0,
+            bytes_uploaded: 0,
+            remote_deleted: 0,
+            errors: Vec::new(),
+            streams: BTreeMap::new(),
+            cancelled: false,
+            lock_contended: false,
+            lock_lost: false,
+            shadow: None,
+            stuck: Vec::new(),
+            capacity: None,
+            plan_duration: Duration::ZERO,
+            apply_duration: Duration::ZERO,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// report of a cycle that could not run at all, holding only `error`
+    pub fn failed(root: impl Into<PathBuf>, started_at: SystemTime, error: &CleanerError) -> Self {
+        let mut report = Self::new(root, started_at);
+        report.record_error(None, error);
+        report
+    }
+
+    pub(crate) fn record_skip(&mut self, segment: &SegmentInfo) {
+        self.skipped += 1;
+        self.stream_mut(&segment.stream).skipped += 1;
+    }
+
+    pub(crate) fn record_delete(&mut self, segment: &SegmentInfo) {
+        self.deleted += 1;
+        self.bytes_freed += segment.size;
+        let stream = self.stream_mut(&segment.stream);
+        stream.deleted += 1;
+        stream.bytes_freed += segment.size;
+    }
+
+    pub(crate) fn record_error(&mut self, stream: Option<&str>, error: &CleanerError) {
+        if let Some(stream) = stream {
+            self.stream_mut(stream).errors += 1;
+        }
+        self.errors.push(ReportError {
+            stream: stream.map(str::to_owned),
+            message: error_chain(error),
+            retryable: error.is_retryable(),
+        });
+    }
+
+    fn stream_mut(&mut self, stream: &str) -> &mut StreamReport {
+        self.streams.entry(stream.to_owned()).or_default()
+    }
+}
+
+/// the error message followed by all its sources, `a - b - c`
+pub(crate) fn error_chain(error: &CleanerError) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        message.push_str(" - ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
@@ -0,0 +1,162 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{storage::Storage, CleanerError, Result};
+
+/// ts fragments directly inside `dir`
+pub(crate) fn list_segments(storage: &dyn Storage, dir: &Path) -> Result<Vec<PathBuf>> {
+    let ts_matcher = globset::GlobBuilder::new("*.ts")
+        .build()
+        .map_err(|e| CleanerError::Config(e.to_string()))?
+        .compile_matcher();
+    Ok(storage
+        .list(dir)?
+        .into_iter()
+        .filter(|e| !e.is_dir && e.path.file_name().is_some_and(|n| ts_matcher.is_match(n)))
+        .map(|e| e.path)
+        .collect())
+}
+
+/// ts fragments directly inside `dir` with their modification time when listed
+///
+/// a cycle only evaluates the fragments of the snapshot taken when it starts, fragments
+/// written or rewritten since are left for the next cycle.
+pub(crate) fn snapshot(
+    storage: &dyn Storage,
+    dir: &Path,
+) -> Result<Vec<(PathBuf, Option<SystemTime>)>> {
+    Ok(list_segments(storage, dir)?
+        .into_iter()
+        .map(|path| {
+            let modified = storage.metadata(&path).ok().and_then(|m| m.modified);
+            (path, modified)
+        })
+        .collect())
+}
+
+/// default [`Policy::playlist_template`](crate::Policy::playlist_template), `live.m3u8` for
+/// `live-42.ts`
+pub const DEFAULT_PLAYLIST_TEMPLATE: &str = "{stream}.m3u8";
+
+/// a playlist template is a file name with `{stream}` at most once, such as `index.m3u8` or
+/// `{stream}.m3u`
+pub fn check_playlist_template(template: &str) -> Result<()> {
+    let invalid =
+        |reason: &str| CleanerError::Config(format!("playlist template {} {}", template, reason));
+    if template.is_empty() || template.contains(['/', '\\']) {
+        return Err(invalid("is not a file name"));
+    }
+    if template.matches("{stream}").count() > 1 {
+        return Err(invalid("names the stream more than once"));
+    }
+    Ok(())
+}
+
+/// name of the playlist of `stream` under `template`
+pub(crate) fn playlist_name(stream: impl AsRef<OsStr>, template: &str) -> OsString {
+    let mut name = OsString::new();
+    let mut parts = template.split("{stream}");
+    name.push(parts.next().unwrap_or_default());
+    for part in parts {
+        name.push(stream.as_ref());
+        name.push(part);
+    }
+    name
+}
+
+/// playlist expected to reference the fragment at `path`, `template` in its directory
+pub(crate) fn playlist_path_for(
+    path: &Path,
+    stream: impl AsRef<OsStr>,
+    template: &str,
+) -> Result<PathBuf> {
+    Ok(path
+        .parent()
+        .ok_or_else(|| CleanerError::invalid_name(path.display().to_string(), "no parent"))?
+        .join(playlist_name(stream, template)))
+}
+
+/// whether the file at `path` is a playlist under `template`
+pub(crate) fn is_playlist(path: &Path, template: &str) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    match template.split_once("{stream}") {
+        Some((prefix, suffix)) => {
+            name.len() > prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+        None => name == template,
+    }
+}
+
+/// the stream the playlist at `path` is named after under `template`, `None` for templates
+/// that do not name it
+pub(crate) fn playlist_stream<'a>(path: &'a Path, template: &str) -> Option<&'a str> {
+    let (prefix, suffix) = template.split_once("{stream}")?;
+    let name = path.file_name()?.to_str()?;
+    match is_playlist(path, template) {
+        true => Some(&name[prefix.len()..name.len() - suffix.len()]),
+        false => None,
+    }
+}
+
+/// split `<stream>-<sequence>.ts` into its stream name and sequence number
+pub fn parse_segment_name(path: &Path) -> Result<(&str, u32)> {
+    let file_stem = path
+        .file_stem()
+        .ok_or_else(|| CleanerError::invalid_name(path.display().to_string(), "no file stem"))?
+        .to_str()
+        .ok_or_else(|| {
+            CleanerError::invalid_name(path.display().to_string(), "contains invalid character")
+        })?;
+    parse_stem(file_stem)
+}
+
+/// [`parse_segment_name`] for names that are not valid utf-8, only the `-<sequence>` suffix
+/// has to be, the stream is returned as it is on disk
+///
+/// windows names are always split as utf-8.
+pub(crate) fn parse_segment_os(path: &Path) -> Result<(&OsStr, u32)> {
+    let file_stem = path
+        .file_stem()
+        .ok_or_else(|| CleanerError::invalid_name(path.display().to_string(), "no file stem"))?;
+    if let Some(file_stem) = file_stem.to_str() {
+        return parse_stem(file_stem).map(|(stream, sequence)| (OsStr::new(stream), sequence));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let bytes = file_stem.as_bytes();
+        let invalid = |reason: &str| CleanerError::invalid_name(path.display().to_string(), reason);
+        let at = bytes
+            .iter()
+            .rposition(|&b| b == b'-')
+            .ok_or_else(|| invalid("missing sequence separator"))?;
+        let sequence_num = std::str::from_utf8(&bytes[at + 1..])
+            .map_err(|_| invalid("contains invalid character"))?
+            .parse::<u32>()
+            .map_err(|e| invalid(&format!("invalid sequence num {}", e)))?;
+        Ok((OsStr::from_bytes(&bytes[..at]), sequence_num))
+    }
+    #[cfg(not(unix))]
+    Err(CleanerError::invalid_name(
+        path.display().to_string(),
+        "contains invalid character",
+    ))
+}
+
+/// split `<stream>-<sequence>` into its stream name and sequence number
+pub(crate) fn parse_stem(file_stem: &str) -> Result<(&str, u32)> {
+    let (base, num) = file_stem
+        .rsplit_once('-')
+        .ok_or_else(|| CleanerError::invalid_name(file_stem, "missing sequence separator"))?;
+    let sequence_num = num.parse::<u32>().map_err(|e| {
+        CleanerError::invalid_name(file_stem, format!("invalid sequence num {}", e))
+    })?;
+    Ok((base, sequence_num))
+}
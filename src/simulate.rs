@@ -0,0 +1,270 @@
+//! synthetic hls workloads run against the cleaner in accelerated time
+//!
+//! a [`MemoryStore`] is filled by fake packagers writing rolling playlists, with occasional
+//! restarts and corrupt playlists, while a [`ManualClock`] jumps from one segment to the next.
+//! a cleanup cycle runs every cycle interval of simulated time and every deletion is checked
+//! against the playlists as they are at that moment: deleting a fragment a playlist still
+//! references is a violation.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    segment, storage::MemoryStore, Cleaner, CleanerObserver, ManualClock, Policy, Reason, Result,
+    SegmentInfo,
+};
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub streams: usize,
+    pub segment_duration: Duration,
+    /// fragments listed in each playlist
+    pub window: usize,
+    /// simulated time to run for
+    pub duration: Duration,
+    pub cycle_interval: Duration,
+    /// chance per stream and segment that the packager restarts
+    pub restart_probability: f64,
+    /// how long a restarting stream has no playlist
+    pub restart_gap: Duration,
+    /// chance per stream and segment that the playlist is written corrupt
+    pub corrupt_probability: f64,
+    pub seed: u64,
+    pub policy: Policy,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            streams: 4,
+            segment_duration: Duration::from_secs(2),
+            window: 5,
+            duration: Duration::from_secs(4 * 3600),
+            cycle_interval: Duration::from_secs(15),
+            restart_probability: 0.001,
+            restart_gap: Duration::from_secs(20),
+            corrupt_probability: 0.002,
+            seed: 1,
+            policy: Policy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SimulationReport {
+    pub cycles: usize,
+    pub segments_written: usize,
+    pub deleted: usize,
+    pub restarts: usize,
+    pub corrupt_playlists: usize,
+    pub cycle_errors: usize,
+    /// fragments still on disk at the end that no playlist references
+    pub lingering: usize,
+    /// deletions of fragments a playlist referenced at that moment
+    pub violations: Vec<String>,
+}
+
+/// xorshift64, good enough to make runs reproducible without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+struct Stream {
+    name: String,
+    next_sequence: u32,
+    /// simulated time until which the stream is restarting and has no playlist
+    down_until: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct DeletionLog(Mutex<Vec<PathBuf>>);
+
+impl CleanerObserver for DeletionLog {
+    fn on_delete(&self, segment: &SegmentInfo, _reason: Reason) {
+        self.0.lock().unwrap().push(segment.path.clone());
+    }
+}
+
+pub async fn simulate(config: &SimulationConfig) -> Result<SimulationReport> {
+    let root = Path::new("/sim");
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let end = start + config.duration;
+    let clock = Arc::new(ManualClock::new(start));
+    let store = Arc::new(MemoryStore::new());
+    let deletions = Arc::new(DeletionLog::default());
+    let cleaner = Cleaner::builder()
+        .root(root)
+        .policy(config.policy.clone())
+        .storage(store.clone())
+        .clock(clock.clone())
+        .observer(deletions.clone())
+        .build();
+    let mut rng = Rng::new(config.seed);
+    let mut streams = (0..config.streams)
+        .map(|i| Stream {
+            name: format!("stream{}", i),
+            next_sequence: 0,
+            down_until: None,
+        })
+        .collect::<Vec<_>>();
+    let mut report = SimulationReport::default();
+    let mut now = start;
+    let mut next_cycle = start + config.cycle_interval;
+    while now < end {
+        for stream in &mut streams {
+            write_segment(config, &store, root, stream, now, &mut rng, &mut report);
+        }
+        while next_cycle <= now + config.segment_duration && next_cycle < end {
+            clock.set(next_cycle);
+            let live = live_segments(&store, root, &config.policy.playlist_template);
+            let cycle = cleaner.clean_once().await?;
+            report.cycles += 1;
+            report.cycle_errors += cycle.errors.len();
+            for path in deletions.0.lock().unwrap().drain(..) {
+                report.deleted += 1;
+                if live.contains(&path) {
+                    report.violations.push(format!(
+                        "{} deleted at +{}s while referenced",
+                        path.display(),
+                        next_cycle
+                            .duration_since(start)
+                            .unwrap_or_default()
+                            .as_secs()
+                    ));
+                }
+            }
+            next_cycle += config.cycle_interval;
+        }
+        now += config.segment_duration;
+        clock.set(now);
+    }
+    let live = live_segments(&store, root, &config.policy.playlist_template);
+    report.lingering = store
+        .paths()
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "ts") && !live.contains(p))
+        .count();
+    Ok(report)
+}
+
+fn write_segment(
+    config: &SimulationConfig,
+    store: &MemoryStore,
+    root: &Path,
+    stream: &mut Stream,
+    now: SystemTime,
+    rng: &mut Rng,
+    report: &mut SimulationReport,
+) {
+    let playlist = root.join(segment::playlist_name(
+        &stream.name,
+        &config.policy.playlist_template,
+    ));
+    if let Some(until) = stream.down_until {
+        if now < until {
+            return;
+        }
+        stream.down_until = None;
+        stream.next_sequence = 0;
+    }
+    if rng.chance(config.restart_probability) {
+        report.restarts += 1;
+        // packagers drop the playlist while restarting and start over at sequence 0
+        let _ = crate::storage::Storage::remove(store, &playlist);
+        stream.down_until = Some(now + config.restart_gap);
+        return;
+    }
+    let sequence = stream.next_sequence;
+    stream.next_sequence += 1;
+    store.insert(
+        root.join(format!("{}-{}.ts", stream.name, sequence)),
+        vec![0u8; 188],
+        now,
+    );
+    report.segments_written += 1;
+    if rng.chance(config.corrupt_probability) {
+        report.corrupt_playlists += 1;
+        store.insert(&playlist, "#EXTM3U\n#EXT-X-TARGETDURATION:garbage\n", now);
+        return;
+    }
+    let first = (sequence + 1).saturating_sub(config.window as u32);
+    let secs = config.segment_duration.as_secs_f64();
+    let mut content = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+        secs.ceil() as u64,
+        first
+    );
+    for seq in first..=sequence {
+        content.push_str(&format!(
+            "#EXTINF:{:.3},\n{}-{}.ts\n",
+            secs, stream.name, seq
+        ));
+    }
+    store.insert(&playlist, content, now);
+}
+
+/// fragments referenced by the playlists currently in the store
+fn live_segments(store: &MemoryStore, root: &Path, playlist_template: &str) -> HashSet<PathBuf> {
+    store
+        .paths()
+        .into_iter()
+        .filter(|p| segment::is_playlist(p, playlist_template))
+        .filter_map(|p| crate::Playlist::read(store, &p).ok())
+        .flat_map(|playlist| playlist.segments.into_iter().map(|s| root.join(s.uri)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// half an hour with frequent restarts and corrupt playlists, so every path is taken
+    fn config(seed: u64) -> SimulationConfig {
+        SimulationConfig {
+            duration: Duration::from_secs(1800),
+            restart_probability: 0.01,
+            corrupt_probability: 0.02,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded_runs_never_delete_referenced_fragments() {
+        for seed in 1..=3 {
+            let report = simulate(&config(seed)).await.unwrap();
+            assert!(report.violations.is_empty(), "{:?}", report.violations);
+            assert_eq!(report.cycles, 119);
+            assert!(report.deleted > 0 && report.restarts > 0 && report.corrupt_playlists > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_are_reproducible() {
+        let config = SimulationConfig {
+            policy: Policy::default().unreferenced_grace(Duration::from_secs(30)),
+            ..config(7)
+        };
+        assert_eq!(
+            simulate(&config).await.unwrap(),
+            simulate(&config).await.unwrap()
+        );
+    }
+}
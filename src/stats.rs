@@ -0,0 +1,147 @@
+//! live per stream figures, the read-only counterpart of a cleanup cycle
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    segment::{list_segments, parse_segment_name, playlist_name, playlist_stream},
+    serde_time,
+    storage::Storage,
+    Playlist, Result,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StreamStats {
+    pub stream: String,
+    /// `.ts` fragments of the stream on disk
+    pub segments: usize,
+    pub playlist: Option<PathBuf>,
+    /// number of fragments listed in the playlist
+    pub window_segments: Option<usize>,
+    /// sum of the listed fragment durations
+    #[serde(serialize_with = "serde_time::opt_secs")]
+    pub window: Option<Duration>,
+    /// mean duration of the listed fragments
+    #[serde(serialize_with = "serde_time::opt_secs")]
+    pub average_segment_duration: Option<Duration>,
+    #[serde(serialize_with = "serde_time::opt_secs")]
+    pub target_duration: Option<Duration>,
+    /// `#EXT-X-MEDIA-SEQUENCE`, the sequence of the first listed fragment
+    pub media_sequence: Option<u64>,
+    /// the playlist ended with `#EXT-X-ENDLIST`
+    pub ended: Option<bool>,
+    #[serde(serialize_with = "serde_time::opt_unix_secs")]
+    pub playlist_updated: Option<SystemTime>,
+    #[serde(serialize_with = "serde_time::opt_unix_secs")]
+    pub newest_segment: Option<SystemTime>,
+    /// set when the playlist exists but could not be read
+    pub playlist_error: Option<String>,
+}
+
+/// current figures for every stream under `root`, found from fragments or playlists named
+/// after `playlist_template`
+pub fn stream_stats(
+    storage: &dyn Storage,
+    root: &Path,
+    playlist_template: &str,
+) -> Result<Vec<StreamStats>> {
+    let mut streams: BTreeMap<String, StreamStats> = BTreeMap::new();
+    for entry in storage.list(root)? {
+        if entry.is_dir {
+            continue;
+        }
+        if let Some(stream) = playlist_stream(&entry.path, playlist_template) {
+            streams
+                .entry(stream.to_owned())
+                .or_insert_with(|| empty(stream));
+        }
+    }
+    for path in list_segments(storage, root)? {
+        let Ok((stream, _)) = parse_segment_name(&path) else {
+            continue;
+        };
+        // a fragment removed since the listing no longer counts
+        let modified = match storage.metadata(&path) {
+            Err(e) if e.is_not_found() => continue,
+            metadata => metadata?.modified,
+        };
+        let stats = streams
+            .entry(stream.to_owned())
+            .or_insert_with(|| empty(stream));
+        stats.segments += 1;
+        stats.newest_segment = stats.newest_segment.max(modified);
+    }
+    for stats in streams.values_mut() {
+        let path = root.join(playlist_name(&stats.stream, playlist_template));
+        stats.playlist_updated = match storage.metadata(&path) {
+            Err(e) if e.is_not_found() => continue,
+            metadata => metadata?.modified,
+        };
+        match Playlist::read(storage, &path) {
+            Ok(playlist) => {
+                let window = playlist
+                    .segments
+                    .iter()
+                    .map(|s| s.duration)
+                    .sum::<Duration>();
+                stats.window_segments = Some(playlist.segments.len());
+                stats.window = Some(window);
+                stats.average_segment_duration = u32::try_from(playlist.segments.len())
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .map(|n| window / n);
+                stats.target_duration = Some(playlist.target_duration);
+                stats.media_sequence = Some(playlist.media_sequence as u64);
+                stats.ended = Some(playlist.has_end_list);
+            }
+            Err(e) => stats.playlist_error = Some(crate::report::error_chain(&e)),
+        }
+        stats.playlist = Some(path);
+    }
+    Ok(streams.into_values().collect())
+}
+
+fn empty(stream: &str) -> StreamStats {
+    StreamStats {
+        stream: stream.to_owned(),
+        segments: 0,
+        playlist: None,
+        window_segments: None,
+        window: None,
+        average_segment_duration: None,
+        target_duration: None,
+        media_sequence: None,
+        ended: None,
+        playlist_updated: None,
+        newest_segment: None,
+        playlist_error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+
+    #[test]
+    fn streams_without_a_playlist_have_no_playlist_figures() {
+        let storage = MemoryStore::new();
+        let root = Path::new("/hls");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        storage.insert(root.join("live-1.ts"), "x", time);
+        let stats = stream_stats(&storage, root, "{stream}.m3u8").unwrap();
+        assert_eq!(
+            stats,
+            vec![StreamStats {
+                segments: 1,
+                newest_segment: Some(time),
+                ..empty("live")
+            }]
+        );
+    }
+}
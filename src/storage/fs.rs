@@ -0,0 +1,126 @@
+use std::{
+    fs::{File, TryLockError},
+    io::{self, Write},
+    path::Path,
+};
+
+use super::{DiskSpace, Entry, FileMeta, LockGuard, Storage};
+use crate::{platform, CleanerError, Result};
+
+/// the local filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsStore;
+
+impl Storage for FsStore {
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        walkdir::WalkDir::new(dir)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .map(|e| {
+                let e = e.map_err(|e| match e.into_io_error() {
+                    Some(e) => CleanerError::io(dir, e),
+                    None => CleanerError::Storage(format!("unable to walk {}", dir.display())),
+                })?;
+                Ok(Entry {
+                    is_dir: e.file_type().is_dir(),
+                    path: e.into_path(),
+                })
+            })
+            .collect()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        std::fs::read_to_string(path).map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path).map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| CleanerError::io(parent, e))?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents))
+            .map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn metadata(&self, path: &Path) -> Result<FileMeta> {
+        let metadata = std::fs::metadata(path).map_err(|e| CleanerError::io(path, e))?;
+        let (uid, gid, mode) = platform::ownership(&metadata);
+        Ok(FileMeta {
+            size: metadata.len(),
+            accessed: metadata.accessed().ok(),
+            modified: metadata.modified().ok(),
+            aged_from: platform::aged_from(&metadata),
+            device: platform::device(&metadata),
+            protected: platform::is_protected(path, &metadata),
+            uid,
+            gid,
+            mode,
+        })
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        platform::retry_shared(|| std::fs::remove_file(path)).map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        std::fs::remove_dir(path).map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        std::fs::remove_dir_all(path).map_err(|e| CleanerError::io(path, e))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| CleanerError::io(parent, e))?;
+        }
+        match platform::retry_shared(|| std::fs::rename(from, to)) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                // the destination is on another filesystem, copy then unlink
+                if let Err(e) = std::fs::copy(from, to) {
+                    let _ = std::fs::remove_file(to);
+                    return Err(CleanerError::io(to, e));
+                }
+                platform::retry_shared(|| std::fs::remove_file(from))
+                    .map_err(|e| CleanerError::io(from, e))
+            }
+            result => result.map_err(|e| CleanerError::io(from, e)),
+        }
+    }
+
+    /// `flock` on unix and `LockFileEx` on windows, the file keeps the pid of the holder for diagnostics
+    fn try_lock(&self, path: &Path) -> Result<Option<LockGuard>> {
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| CleanerError::io(path, e))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => return Err(CleanerError::io(path, e)),
+        }
+        file.set_len(0)
+            .and_then(|()| writeln!(file, "{}", std::process::id()))
+            .map_err(|e| CleanerError::io(path, e))?;
+        Ok(Some(LockGuard::file(file)))
+    }
+
+    fn disk_space(&self, path: &Path) -> Result<Option<DiskSpace>> {
+        platform::disk_space(path).map_err(|e| CleanerError::io(path, e))
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use super::{Entry, FileMeta, LockGuard, Storage, TimeSource};
+use crate::{CleanerError, Result};
+
+#[derive(Debug, Clone)]
+struct MemFile {
+    contents: Vec<u8>,
+    accessed: SystemTime,
+    modified: SystemTime,
+}
+
+/// storage backed by a hashmap, for tests and policy prototyping
+///
+/// directories are implicit, a directory exists as long as a file below it does.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    files: Mutex<HashMap<PathBuf, MemFile>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add or replace a file, both timestamps are set to `time`
+    pub fn insert(&self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>, time: SystemTime) {
+        self.files.lock().unwrap().insert(
+            path.into(),
+            MemFile {
+                contents: contents.into(),
+                accessed: time,
+                modified: time,
+            },
+        );
+    }
+
+    /// update both timestamps of an existing file, returns false if it does not exist
+    pub fn touch(&self, path: &Path, time: SystemTime) -> bool {
+        match self.files.lock().unwrap().get_mut(path) {
+            Some(file) => {
+                file.accessed = time;
+                file.modified = time;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// every file currently stored, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    fn not_found(path: &Path) -> CleanerError {
+        CleanerError::io(path, io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+impl Storage for MemoryStore {
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let files = self.files.lock().unwrap();
+        let mut dirs = BTreeSet::new();
+        let mut entries = Vec::new();
+        for path in files.keys() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let mut components = relative.components();
+            let Some(first) = components.next() else {
+                continue;
+            };
+            match components.next() {
+                Some(_) => {
+                    dirs.insert(dir.join(first));
+                }
+                None => entries.push(Entry {
+                    path: path.clone(),
+                    is_dir: false,
+                }),
+            }
+        }
+        if entries.is_empty() && dirs.is_empty() {
+            return Err(Self::not_found(dir));
+        }
+        entries.extend(dirs.into_iter().map(|path| Entry { path, is_dir: true }));
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let files = self.files.lock().unwrap();
+        files.contains_key(path) || files.keys().any(|p| p.starts_with(path))
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(path).ok_or_else(|| Self::not_found(path))?;
+        String::from_utf8(file.contents.clone())
+            .map_err(|e| CleanerError::io(path, io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(path).ok_or_else(|| Self::not_found(path))?;
+        Ok(file.contents.clone())
+    }
+
+    /// appended files get the current system time, not a simulated one
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let now = SystemTime::now();
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(path.to_owned()).or_insert_with(|| MemFile {
+            contents: Vec::new(),
+            accessed: now,
+            modified: now,
+        });
+        file.contents.extend_from_slice(contents);
+        file.modified = now;
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Result<FileMeta> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(path).ok_or_else(|| Self::not_found(path))?;
+        Ok(FileMeta {
+            size: file.contents.len() as u64,
+            accessed: Some(file.accessed),
+            modified: Some(file.modified),
+            aged_from: Some((file.accessed, TimeSource::Accessed)),
+            device: None,
+            protected: false,
+            uid: None,
+            gid: None,
+            mode: None,
+        })
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| Self::not_found(path))
+    }
+
+    /// directories are implicit, this only fails if files remain below `path`
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        match self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|p| p.starts_with(path))
+        {
+            true => Err(CleanerError::io(
+                path,
+                io::Error::from(io::ErrorKind::DirectoryNotEmpty),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let before = files.len();
+        files.retain(|p, _| !p.starts_with(path));
+        match files.len() == before {
+            true => Err(Self::not_found(path)),
+            false => Ok(()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.remove(from).ok_or_else(|| Self::not_found(from))?;
+        files.insert(to.to_owned(), file);
+        Ok(())
+    }
+
+    /// a single process owns the store, locking always succeeds
+    fn try_lock(&self, _path: &Path) -> Result<Option<LockGuard>> {
+        Ok(Some(LockGuard::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn is_not_found(result: Result<impl std::fmt::Debug>) -> bool {
+        matches!(result, Err(CleanerError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound)
+    }
+
+    fn store() -> MemoryStore {
+        let store = MemoryStore::new();
+        store.insert("/hls/live-2.ts", "22", at(20));
+        store.insert("/hls/live-1.ts", "1", at(10));
+        store.insert("/hls/live.m3u8", "#EXTM3U", at(30));
+        store.insert("/hls/keys/live.key", "k", at(40));
+        store.insert("/hlsx/other-1.ts", "x", at(50));
+        store
+    }
+
+    #[test]
+    fn list_is_one_level_deep_and_sorted() {
+        let entries = store().list(Path::new("/hls")).unwrap();
+        let listed = entries
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.is_dir))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            [
+                ("/hls/keys", true),
+                ("/hls/live-1.ts", false),
+                ("/hls/live-2.ts", false),
+                ("/hls/live.m3u8", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn listing_a_directory_without_files_fails() {
+        let store = store();
+        assert!(is_not_found(store.list(Path::new("/vod"))));
+        assert!(is_not_found(store.list(Path::new("/hls/live-1.ts"))));
+    }
+
+    #[test]
+    fn metadata_ages_files_from_their_access_time() {
+        let store = store();
+        store.touch(Path::new("/hls/live-2.ts"), at(25));
+        let meta = store.metadata(Path::new("/hls/live-2.ts")).unwrap();
+        assert_eq!(meta.size, 2);
+        assert_eq!(meta.accessed, Some(at(25)));
+        assert_eq!(meta.modified, Some(at(25)));
+        assert_eq!(meta.aged_from, Some((at(25), TimeSource::Accessed)));
+        assert_eq!(meta.device, None);
+        assert!(is_not_found(store.metadata(Path::new("/hls/live-3.ts"))));
+        assert!(!store.touch(Path::new("/hls/live-3.ts"), at(25)));
+    }
+
+    #[test]
+    fn remove_takes_out_one_file() {
+        let store = store();
+        store.remove(Path::new("/hls/live-1.ts")).unwrap();
+        assert!(is_not_found(store.remove(Path::new("/hls/live-1.ts"))));
+        assert!(!store.exists(Path::new("/hls/live-1.ts")));
+        assert!(store.exists(Path::new("/hls/live-2.ts")));
+        assert_eq!(store.paths().len(), 4);
+    }
+
+    #[test]
+    fn directories_exist_while_files_remain_below() {
+        let store = store();
+        let keys = Path::new("/hls/keys");
+        assert!(store.exists(keys));
+        assert!(store.remove_dir(keys).is_err());
+        store.remove(&keys.join("live.key")).unwrap();
+        assert!(!store.exists(keys));
+        store.remove_dir(keys).unwrap();
+        store.remove_dir_all(Path::new("/hls")).unwrap();
+        assert_eq!(store.paths(), [PathBuf::from("/hlsx/other-1.ts")]);
+    }
+}
@@ -0,0 +1,153 @@
+//! filesystem access used by the cleaner
+//!
+//! every read and delete goes through [`Storage`] so the decision logic can run against
+//! something other than the local disk.
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::Result;
+
+mod fs;
+mod memory;
+
+pub use fs::FsStore;
+pub use memory::MemoryStore;
+
+/// a directory listing entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// the subset of file metadata the cleaner looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMeta {
+    pub size: u64,
+    pub accessed: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// the timestamp orphans age from, picked by the backend
+    pub aged_from: Option<(SystemTime, TimeSource)>,
+    /// filesystem holding the file, `st_dev` on unix, `None` when the backend cannot tell
+    pub device: Option<u64>,
+    /// flagged immutable or append-only, removing the file fails
+    pub protected: bool,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// permission bits, without the file type
+    pub mode: Option<u32>,
+}
+
+impl FileMeta {
+    /// whether the file is known to live on another filesystem than `device`
+    pub(crate) fn is_on_other_device(&self, device: Option<u64>) -> bool {
+        matches!((self.device, device), (Some(a), Some(b)) if a != b)
+    }
+}
+
+/// size and free space of a filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub total: u64,
+    /// free for unprivileged writers, without the blocks reserved for root
+    pub available: u64,
+}
+
+impl DiskSpace {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+}
+
+/// which file timestamp the orphan rule ages a fragment from
+///
+/// the local filesystem prefers the birth time on macos and the bsds, the access time
+/// elsewhere, and whichever of the access and modification times is more recent on windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+    Birth,
+    Modified,
+    Accessed,
+    /// when the cleaner first saw the fragment, for fragments whose timestamp is beyond the
+    /// [clock skew tolerance](crate::Policy::clock_skew_tolerance) in the future
+    FirstSeen,
+}
+
+impl std::fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Birth => "birth time",
+            Self::Modified => "modification time",
+            Self::Accessed => "access time",
+            Self::FirstSeen => "first sighting",
+        })
+    }
+}
+
+/// an advisory lock taken by [`Storage::try_lock`], released when dropped
+///
+/// the default guard holds nothing, for backends without locking.
+#[derive(Debug, Default)]
+pub struct LockGuard {
+    _file: Option<std::fs::File>,
+}
+
+impl LockGuard {
+    pub(crate) fn file(file: std::fs::File) -> Self {
+        Self { _file: Some(file) }
+    }
+}
+
+/// replace `path` with `contents` through a temporary file, readers never see a partial write
+pub(crate) fn write_atomic(storage: &dyn Storage, path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    if storage.exists(&tmp) {
+        storage.remove(&tmp)?;
+    }
+    storage.append(&tmp, contents)?;
+    storage.rename(&tmp, path)
+}
+
+pub trait Storage: Send + Sync {
+    /// entries directly inside `dir`, not recursive
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    fn read_to_string(&self, path: &Path) -> Result<String>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// append to a file, creating it and its parent directories as needed
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()>;
+
+    fn metadata(&self, path: &Path) -> Result<FileMeta>;
+
+    fn remove(&self, path: &Path) -> Result<()>;
+
+    /// remove an empty directory
+    fn remove_dir(&self, path: &Path) -> Result<()>;
+
+    /// remove a directory and everything below it
+    fn remove_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// move a file, creating the parent directories of `to` as needed
+    ///
+    /// backends fall back to copy and delete when `to` is on another filesystem.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// take an exclusive lock on `path`, creating it as needed, `None` while someone else
+    /// holds it
+    fn try_lock(&self, path: &Path) -> Result<Option<LockGuard>>;
+
+    /// size and free space of the filesystem holding `path`, `None` when the backend has no
+    /// notion of it
+    fn disk_space(&self, _path: &Path) -> Result<Option<DiskSpace>> {
+        Ok(None)
+    }
+}
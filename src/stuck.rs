@@ -0,0 +1,98 @@
+//! streams whose packager stopped rewriting the playlist while the encoder keeps writing
+//!
+//! a stream is stuck when its playlist was not updated for the policy's
+//! [`stuck_after`](crate::Policy::stuck_after) target durations although one of its fragments
+//! was written within that time. the fragments written since the last playlist update are kept
+//! while it lasts, the playlist no longer tells whether they are still wanted and an encoder
+//! restarting its sequence numbers below the stale window would lose them to the unreferenced
+//! rule.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    segment::{parse_segment_os, playlist_path_for},
+    serde_time,
+    storage::Storage,
+    Playlist,
+};
+
+/// entry of [`CleanReport::stuck`](crate::CleanReport::stuck)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StuckStream {
+    pub stream: String,
+    pub playlist: PathBuf,
+    #[serde(serialize_with = "serde_time::unix_secs")]
+    pub playlist_updated: SystemTime,
+    #[serde(serialize_with = "serde_time::unix_secs")]
+    pub newest_segment: SystemTime,
+    #[serde(serialize_with = "serde_time::secs")]
+    pub target_duration: Duration,
+}
+
+impl StuckStream {
+    /// whether a fragment modified at `modified` was written after the last playlist update
+    pub(crate) fn keeps(&self, modified: Option<SystemTime>) -> bool {
+        modified.is_some_and(|m| m > self.playlist_updated)
+    }
+}
+
+/// the stuck streams among the fragments of a snapshot, by stream name
+///
+/// streams `owns` rejects and playlists that cannot be read are left out, the cycle reports
+/// unreadable playlists on its own.
+pub(crate) fn detect<'a>(
+    storage: &dyn Storage,
+    snapshot: impl IntoIterator<Item = (&'a Path, Option<SystemTime>)>,
+    durations: u32,
+    playlist_template: &str,
+    owns: impl Fn(&str) -> bool,
+    now: SystemTime,
+) -> BTreeMap<String, StuckStream> {
+    let mut newest = BTreeMap::<String, (PathBuf, SystemTime)>::new();
+    for (path, modified) in snapshot {
+        let (Ok((stream, _)), Some(modified)) = (parse_segment_os(path), modified) else {
+            continue;
+        };
+        let Ok(playlist) = playlist_path_for(path, stream, playlist_template) else {
+            continue;
+        };
+        let stream = stream.to_string_lossy();
+        if !owns(&stream) {
+            continue;
+        }
+        match newest.get_mut(&*stream) {
+            Some((_, newest)) => *newest = (*newest).max(modified),
+            None => {
+                newest.insert(stream.into_owned(), (playlist, modified));
+            }
+        }
+    }
+    newest
+        .into_iter()
+        .filter_map(|(stream, (playlist, newest_segment))| {
+            let playlist_updated = storage.metadata(&playlist).ok()?.modified?;
+            let target_duration = Playlist::read(storage, &playlist).ok()?.target_duration;
+            let after = target_duration * durations;
+            if after.is_zero() {
+                return None;
+            }
+            let age = |time: SystemTime| now.duration_since(time).unwrap_or_default();
+            (age(playlist_updated) > after && age(newest_segment) <= after).then(|| {
+                let stuck = StuckStream {
+                    stream: stream.clone(),
+                    playlist,
+                    playlist_updated,
+                    newest_segment,
+                    target_duration,
+                };
+                (stream, stuck)
+            })
+        })
+        .collect()
+}
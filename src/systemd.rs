@@ -0,0 +1,138 @@
+//! systemd readiness and watchdog notifications
+//!
+//! [`SystemdNotifier`] is an observer sending `READY=1` once every root completed a cycle and
+//! `WATCHDOG=1` every time all of them completed another one, so a cycle hanging on a stuck
+//! mount stops the pings and systemd restarts the unit. `WatchdogSec=` has to leave room for a
+//! few cycle intervals. cycles failing as a whole are not counted, fragment errors are.
+
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    future::Future,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{CleanReport, CleanerObserver};
+
+/// send `state` to the socket systemd passed in `NOTIFY_SOCKET`, returns whether it was set
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    send(&path, state)?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn send(path: &OsStr, state: &str) -> io::Result<()> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    match path.to_string_lossy().strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract notify sockets only exist on linux",
+            ))
+        }
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "notify sockets need unix domain sockets",
+    ))
+}
+
+/// interval systemd expects watchdog pings at, from `WATCHDOG_USEC`
+///
+/// `None` when the watchdog is disabled or meant for another process.
+pub fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.trim().parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.trim().parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|t| !t.is_zero())
+}
+
+/// observer notifying systemd of the progress of the cleaners of `roots`
+#[derive(Debug)]
+pub struct SystemdNotifier {
+    roots: Vec<PathBuf>,
+    /// roots without a completed cycle since the last notification
+    pending: Mutex<HashSet<PathBuf>>,
+    ready: AtomicBool,
+    watchdog: bool,
+}
+
+impl SystemdNotifier {
+    /// `None` when not started by systemd with `Type=notify`
+    pub fn from_env(roots: impl IntoIterator<Item = PathBuf>) -> Option<Self> {
+        std::env::var_os("NOTIFY_SOCKET")?;
+        let roots = roots.into_iter().collect::<Vec<_>>();
+        Some(Self {
+            pending: Mutex::new(roots.iter().cloned().collect()),
+            roots,
+            ready: AtomicBool::new(false),
+            watchdog: watchdog_timeout().is_some(),
+        })
+    }
+
+    /// keep the watchdog fed while `idle` runs, for replicas standing by without cycles
+    pub async fn idle<F: Future>(&self, idle: F) -> F::Output {
+        let Some(timeout) = watchdog_timeout() else {
+            self.progress();
+            return idle.await;
+        };
+        tokio::pin!(idle);
+        let mut ping = tokio::time::interval(timeout / 2);
+        loop {
+            tokio::select! {
+                output = &mut idle => return output,
+                _ = ping.tick() => self.progress(),
+            }
+        }
+    }
+
+    fn progress(&self) {
+        let state = match self.ready.swap(true, Ordering::Relaxed) {
+            false => "READY=1",
+            true if self.watchdog => "WATCHDOG=1",
+            true => return,
+        };
+        if let Err(e) = notify(state) {
+            tracing::warn!("unable to notify systemd - {}", e);
+        }
+    }
+}
+
+impl CleanerObserver for SystemdNotifier {
+    fn on_cycle_end(&self, report: &CleanReport) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.remove(&report.root);
+        if pending.is_empty() {
+            pending.extend(self.roots.iter().cloned());
+            drop(pending);
+            self.progress();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! quarantine layout used by [`Action::Trash`](crate::Action::Trash)
+//!
+//! fragments are moved to `<root>/.trash/<stream>/<unix seconds>/<file name>` so they can be
+//! found per stream and by the time they were trashed.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{archive, serde_time, storage::Storage, Result, SegmentInfo};
+
+/// directory name of the trash, relative to the root
+pub const TRASH_DIR: &str = ".trash";
+
+/// fails for a stream name that would lead outside the trash, like `..`
+pub(crate) fn trash_path(root: &Path, segment: &SegmentInfo, now: SystemTime) -> Result<PathBuf> {
+    archive::check_relative(
+        &format!("stream {}", segment.stream),
+        Path::new(&segment.stream),
+    )?;
+    let trashed_at = serde_time::to_unix_secs(now);
+    let mut path = root
+        .join(TRASH_DIR)
+        .join(&segment.stream)
+        .join(trashed_at.to_string());
+    if let Some(name) = segment.path.file_name() {
+        path.push(name);
+    }
+    Ok(path)
+}
+
+/// a fragment sitting in the trash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrashedSegment {
+    pub path: PathBuf,
+    pub stream: String,
+    #[serde(serialize_with = "serde_time::unix_secs")]
+    pub trashed_at: SystemTime,
+    /// where the fragment lived before it was trashed
+    pub original: PathBuf,
+}
+
+/// outcome of [`Cleaner::restore`](crate::Cleaner::restore)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    pub restored: Vec<PathBuf>,
+    /// fragments left in the trash, or only in the archive, because the original path is taken
+    /// again
+    pub conflicts: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+/// every trashed fragment of `stream`, or of all streams
+pub fn list(
+    storage: &dyn Storage,
+    root: &Path,
+    stream: Option<&str>,
+) -> Result<Vec<TrashedSegment>> {
+    let trash = root.join(TRASH_DIR);
+    if !storage.exists(&trash) {
+        return Ok(Vec::new());
+    }
+    let mut trashed = Vec::new();
+    for stream_dir in storage.list(&trash)?.into_iter().filter(|e| e.is_dir) {
+        let Some(name) = stream_dir.path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if stream.is_some_and(|s| s != name) {
+            continue;
+        }
+        for batch in storage
+            .list(&stream_dir.path)?
+            .into_iter()
+            .filter(|e| e.is_dir)
+        {
+            let Some(secs) = batch
+                .path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let trashed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            for file in storage.list(&batch.path)?.into_iter().filter(|e| !e.is_dir) {
+                let Some(file_name) = file.path.file_name() else {
+                    continue;
+                };
+                trashed.push(TrashedSegment {
+                    original: root.join(file_name),
+                    stream: name.to_owned(),
+                    trashed_at,
+                    path: file.path,
+                });
+            }
+        }
+    }
+    trashed.sort_by(|a, b| a.trashed_at.cmp(&b.trashed_at).then(a.path.cmp(&b.path)));
+    Ok(trashed)
+}
+
+/// move fragments of `stream` trashed at or after `since` back into place
+pub(crate) fn restore(
+    storage: &dyn Storage,
+    root: &Path,
+    stream: &str,
+    since: Option<SystemTime>,
+) -> Result<RestoreReport> {
+    let mut report = RestoreReport::default();
+    for trashed in list(storage, root, Some(stream))? {
+        if since.is_some_and(|since| trashed.trashed_at < since) {
+            continue;
+        }
+        if storage.exists(&trashed.original) {
+            tracing::warn!(
+                "{} already exists, leaving {} in the trash",
+                trashed.original.display(),
+                trashed.path.display()
+            );
+            report.conflicts.push(trashed.path);
+            continue;
+        }
+        match storage.rename(&trashed.path, &trashed.original) {
+            Ok(()) => {
+                tracing::info!("restored {}", trashed.original.display());
+                report.restored.push(trashed.original);
+            }
+            Err(e) => report.errors.push(crate::report::error_chain(&e)),
+        }
+    }
+    Ok(report)
+}
+
+/// outcome of [`Cleaner::gc`](crate::Cleaner::gc)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    /// trashed fragments deleted for good
+    pub purged: Vec<PathBuf>,
+    pub bytes_freed: u64,
+    pub removed_dirs: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+/// delete trashed fragments older than `retention` and drop the directories left empty
+pub(crate) fn gc(
+    storage: &dyn Storage,
+    root: &Path,
+    retention: Duration,
+    now: SystemTime,
+) -> Result<GcReport> {
+    let mut report = GcReport::default();
+    for trashed in list(storage, root, None)? {
+        let expired = now
+            .duration_since(trashed.trashed_at)
+            .is_ok_and(|age| age > retention);
+        if !expired {
+            continue;
+        }
+        let size = storage.metadata(&trashed.path).map_or(0, |m| m.size);
+        match storage.remove(&trashed.path) {
+            Ok(()) => {
+                report.bytes_freed += size;
+                report.purged.push(trashed.path);
+            }
+            Err(e) => report.errors.push(crate::report::error_chain(&e)),
+        }
+    }
+    let trash = root.join(TRASH_DIR);
+    if storage.exists(&trash) {
+        remove_empty_dirs(storage, &trash, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// depth first, returns whether `dir` ended up empty, the trash root itself is kept
+fn remove_empty_dirs(storage: &dyn Storage, dir: &Path, report: &mut GcReport) -> Result<bool> {
+    let mut empty = true;
+    for entry in storage.list(dir)? {
+        if !entry.is_dir {
+            empty = false;
+            continue;
+        }
+        match remove_empty_dirs(storage, &entry.path, report)? {
+            true => match storage.remove_dir(&entry.path) {
+                Ok(()) => report.removed_dirs.push(entry.path),
+                Err(e) => {
+                    empty = false;
+                    report.errors.push(crate::report::error_chain(&e));
+                }
+            },
+            false => empty = false,
+        }
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(stream: &str) -> SegmentInfo {
+        SegmentInfo {
+            path: Path::new("/hls").join(format!("{}-1.ts", stream)),
+            stream: stream.to_owned(),
+            sequence: 1,
+            size: 0,
+            modified: None,
+            date_range: None,
+        }
+    }
+
+    #[test]
+    fn fragments_go_below_their_stream() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        assert_eq!(
+            trash_path(Path::new("/hls"), &segment("live"), now).unwrap(),
+            Path::new("/hls/.trash/live/60/live-1.ts")
+        );
+    }
+
+    #[test]
+    fn streams_leading_outside_the_trash_are_refused() {
+        let now = SystemTime::UNIX_EPOCH;
+        assert!(trash_path(Path::new("/hls"), &segment(".."), now).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! playlist and fragment consistency checks
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    segment::{is_playlist, list_segments},
+    storage::Storage,
+    Playlist, Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    UnparsablePlaylist {
+        playlist: PathBuf,
+        error: String,
+    },
+    MissingSegment {
+        playlist: PathBuf,
+        segment: PathBuf,
+    },
+    EmptySegment {
+        playlist: PathBuf,
+        segment: PathBuf,
+    },
+    /// a variant or rendition a master playlist lists is not on disk
+    MissingVariant {
+        playlist: PathBuf,
+        variant: PathBuf,
+    },
+    /// a fragment no playlist references, expected briefly between two cleanup cycles
+    UnreferencedSegment {
+        segment: PathBuf,
+    },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnreferencedSegment { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnparsablePlaylist { playlist, error } => {
+                write!(f, "{} does not parse - {}", playlist.display(), error)
+            }
+            Self::MissingSegment { playlist, segment } => write!(
+                f,
+                "{} references missing {}",
+                playlist.display(),
+                segment.display()
+            ),
+            Self::EmptySegment { playlist, segment } => write!(
+                f,
+                "{} references empty {}",
+                playlist.display(),
+                segment.display()
+            ),
+            Self::MissingVariant { playlist, variant } => write!(
+                f,
+                "{} lists missing variant {}",
+                playlist.display(),
+                variant.display()
+            ),
+            Self::UnreferencedSegment { segment } => {
+                write!(f, "{} is not referenced by any playlist", segment.display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Validation {
+    pub root: PathBuf,
+    /// media and master playlists
+    pub playlists: usize,
+    pub segments: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Validation {
+    /// whether any issue is at least `severity`
+    pub fn has_issues(&self, severity: Severity) -> bool {
+        self.issues.iter().any(|i| i.severity() >= severity)
+    }
+}
+
+/// check every playlist under `root` parses and that its fragments exist and are not empty,
+/// then flag fragments on disk that no playlist references
+///
+/// master playlists only have their variant and rendition playlists checked for existence,
+/// the variants in the root are validated as media playlists on their own.
+pub fn validate(storage: &dyn Storage, root: &Path, playlist_template: &str) -> Result<Validation> {
+    let mut validation = Validation {
+        root: root.to_owned(),
+        playlists: 0,
+        segments: 0,
+        issues: Vec::new(),
+    };
+    let mut referenced = HashSet::new();
+    for entry in storage.list(root)? {
+        if entry.is_dir || !is_playlist(&entry.path, playlist_template) {
+            continue;
+        }
+        validation.playlists += 1;
+        let playlist = match PlaylistKind::read(storage, &entry.path) {
+            Ok(PlaylistKind::Media(playlist)) => playlist,
+            Ok(PlaylistKind::Master(variants)) => {
+                for uri in variants.into_iter().filter(|uri| !uri.contains("://")) {
+                    let variant = root.join(uri);
+                    if !storage.exists(&variant) {
+                        validation.issues.push(Issue::MissingVariant {
+                            playlist: entry.path.clone(),
+                            variant,
+                        });
+                    }
+                }
+                continue;
+            }
+            Err(e) => {
+                validation.issues.push(Issue::UnparsablePlaylist {
+                    playlist: entry.path,
+                    error: crate::report::error_chain(&e),
+                });
+                continue;
+            }
+        };
+        for seg in &playlist.segments {
+            if seg.uri.contains("://") {
+                continue;
+            }
+            let segment = root.join(&seg.uri);
+            match storage.metadata(&segment) {
+                Ok(meta) if meta.size == 0 => validation.issues.push(Issue::EmptySegment {
+                    playlist: entry.path.clone(),
+                    segment: segment.clone(),
+                }),
+                Ok(_) => {}
+                Err(_) => validation.issues.push(Issue::MissingSegment {
+                    playlist: entry.path.clone(),
+                    segment: segment.clone(),
+                }),
+            }
+            referenced.insert(segment);
+        }
+    }
+    for segment in list_segments(storage, root)? {
+        validation.segments += 1;
+        if !referenced.contains(&segment) {
+            validation
+                .issues
+                .push(Issue::UnreferencedSegment { segment });
+        }
+    }
+    Ok(validation)
+}
+
+enum PlaylistKind {
+    Media(Playlist),
+    /// the uris of its variants and renditions
+    Master(Vec<String>),
+}
+
+impl PlaylistKind {
+    fn read(storage: &dyn Storage, path: &Path) -> Result<Self> {
+        let content = storage.read_to_string(path)?;
+        match content.contains("#EXT-X-STREAM-INF") {
+            true => Ok(Self::Master(variants(&content))),
+            false => Playlist::parse(path, &content).map(Self::Media),
+        }
+    }
+}
+
+/// uris of a master playlist, the line following each `#EXT-X-STREAM-INF` and the `URI`
+/// attribute of `#EXT-X-MEDIA` and `#EXT-X-I-FRAME-STREAM-INF`
+fn variants(content: &str) -> Vec<String> {
+    let mut uris = Vec::new();
+    let mut lines = content.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line.starts_with("#EXT-X-STREAM-INF") {
+            if let Some(uri) = lines.find(|l| !l.is_empty() && !l.starts_with('#')) {
+                uris.push(uri.to_owned());
+            }
+        } else if line.starts_with("#EXT-X-MEDIA:")
+            || line.starts_with("#EXT-X-I-FRAME-STREAM-INF:")
+        {
+            if let Some((_, rest)) = line.split_once("URI=\"") {
+                uris.extend(rest.split('"').next().map(str::to_owned));
+            }
+        }
+    }
+    uris
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{segment::DEFAULT_PLAYLIST_TEMPLATE, storage::MemoryStore};
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"en\",URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aac\"
+live.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,AUDIO=\"aac\"
+hd/index.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,URI=\"iframes.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000
+https://cdn.example.com/low.m3u8
+";
+
+    fn validated(store: &MemoryStore) -> Validation {
+        validate(store, Path::new("/hls"), DEFAULT_PLAYLIST_TEMPLATE).unwrap()
+    }
+
+    #[test]
+    fn master_playlists_have_their_variants_checked() {
+        let store = MemoryStore::new();
+        let now = SystemTime::now();
+        store.insert("/hls/master.m3u8", MASTER, now);
+        store.insert(
+            "/hls/live.m3u8",
+            "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXTINF:2,\nlive-1.ts\n",
+            now,
+        );
+        store.insert("/hls/live-1.ts", "ts", now);
+        store.insert("/hls/hd/index.m3u8", "#EXTM3U", now);
+        let validation = validated(&store);
+        assert_eq!(validation.playlists, 2);
+        assert_eq!(
+            validation.issues,
+            [
+                Issue::MissingVariant {
+                    playlist: "/hls/master.m3u8".into(),
+                    variant: "/hls/audio.m3u8".into(),
+                },
+                Issue::MissingVariant {
+                    playlist: "/hls/master.m3u8".into(),
+                    variant: "/hls/iframes.m3u8".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn media_playlists_are_still_parsed() {
+        let store = MemoryStore::new();
+        store.insert(
+            "/hls/live.m3u8",
+            "#EXTM3U\n#EXTINF:nope\n",
+            SystemTime::now(),
+        );
+        assert!(matches!(
+            &validated(&store).issues[..],
+            [Issue::UnparsablePlaylist { .. }]
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+//! live to vod, stitching the fragments of a finished stream into a single file
+//!
+//! a stream is finished when its playlist is gone and every one of its fragments is about to
+//! be removed as an orphan. mpeg-ts fragments are concatenated byte for byte in sequence order,
+//! which is the order the playlist listed them in, into `<template>/<stream>.<first>-<last>.ts`
+//! where the template is expanded like the [`archive`](crate::archive) template. the template
+//! must not expand to the root itself or the stitched file would be picked up as a fragment.
+//!
+//! with [`Action::Archive`](crate::Action::Archive) a frozen `<stream>.m3u8` is also kept in
+//! every archive directory, listing the archived fragments of the stream in sequence order with
+//! `#EXT-X-ENDLIST` so the archive plays as hls. fragment durations are carried over from the
+//! previous snapshot or the live playlist, falling back to the live target duration since
+//! expired fragments are no longer listed anywhere. `#EXT-X-DATERANGE` tags recorded in the
+//! manifest are written before their fragment so ad markers survive archiving.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    archive::{self, ManifestEntry},
+    segment::parse_segment_name,
+    serde_time,
+    storage::{self, Storage},
+    CleanerError, CleanerObserver, Playlist, Result, SegmentInfo,
+};
+
+/// default stitch template, relative to the root
+pub const DEFAULT_TEMPLATE: &str = ".vod/%Y/%m/%d";
+
+/// concatenate `segments`, sorted by sequence, and verify the result before returning its path
+pub(crate) fn stitch(
+    storage: &dyn Storage,
+    root: &Path,
+    template: &str,
+    segments: &[&SegmentInfo],
+    now: SystemTime,
+    observer: &dyn CleanerObserver,
+) -> Result<PathBuf> {
+    let (Some(first), Some(last)) = (segments.first(), segments.last()) else {
+        return Err(CleanerError::Storage("nothing to stitch".to_owned()));
+    };
+    let path = archive::expand_dir(template, root, &first.stream, now)?.join(format!(
+        "{}.{}-{}.ts",
+        first.stream, first.sequence, last.sequence
+    ));
+    // a previous attempt may have left a partial file behind
+    if storage.exists(&path) {
+        storage.remove(&path)?;
+    }
+    let mut expected = 0;
+    let mut hasher = Sha256::new();
+    for segment in segments {
+        let contents = storage.read(&segment.path)?;
+        expected += contents.len() as u64;
+        hasher.update(&contents);
+        storage.append(&path, &contents)?;
+    }
+    let written = storage.metadata(&path)?.size;
+    if written != expected {
+        let _ = storage.remove(&path);
+        return Err(CleanerError::io(
+            &path,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("stitched {} bytes, expected {}", written, expected),
+            ),
+        ));
+    }
+    // recorded like archived fragments so uploads and remote expiry can track it
+    if let (Some(dir), Some(file)) = (path.parent(), path.file_name()) {
+        let entry = ManifestEntry {
+            file: file.to_string_lossy().into_owned(),
+            size: written,
+            stored_size: written,
+            archived_at: serde_time::to_unix_secs(now),
+            sha256: Some(archive::hex(&hasher.finalize())),
+            source: None,
+            encrypted: false,
+            date_ranges: segments
+                .iter()
+                .filter_map(|s| s.date_range.clone())
+                .collect(),
+        };
+        archive::append_manifest(storage, &archive::manifest_path(dir, &first.stream), &entry)?;
+        observer.on_archive(segments, &path, &entry);
+    }
+    Ok(path)
+}
+
+/// rewrite the vod playlist of `stream` in the archive directory `dir`
+pub(crate) fn write_playlist(
+    storage: &dyn Storage,
+    dir: &Path,
+    stream: &str,
+    live: Option<&Playlist>,
+) -> Result<PathBuf> {
+    let path = dir.join(format!("{}.m3u8", stream));
+    let previous = match storage.exists(&path) {
+        true => Playlist::read(storage, &path).ok(),
+        false => None,
+    };
+    let fallback = live
+        .or(previous.as_ref())
+        .map(|p| p.target_duration)
+        .unwrap_or(Duration::from_secs(10));
+    let durations = previous
+        .iter()
+        .chain(live)
+        .flat_map(|p| &p.segments)
+        .map(|s| (s.uri.rsplit('/').next().unwrap_or(&s.uri), s.duration))
+        .collect::<HashMap<_, _>>();
+    let manifest = archive::manifest_path(dir, stream);
+    let date_ranges = archive::tiers(storage, &manifest)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(entry, _)| Some((entry.file, entry.date_ranges.into_iter().next()?)))
+        .collect::<HashMap<_, _>>();
+    // uploaded fragments are gone locally but still belong to the playlist
+    let uploaded = archive::uploaded_files(storage, &manifest)
+        .into_iter()
+        .map(|file| dir.join(file));
+    let mut segments = storage
+        .list(dir)?
+        .into_iter()
+        .filter(|e| !e.is_dir)
+        .map(|e| e.path)
+        .chain(uploaded)
+        .filter(|p| p.extension().is_some_and(|ext| ext == "ts"))
+        .filter_map(|p| match parse_segment_name(&p) {
+            Ok((s, sequence)) if s == stream => Some((sequence, p)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    segments.sort();
+    segments.dedup();
+    let Some(&(first, _)) = segments.first() else {
+        return Err(CleanerError::EmptyPlaylist { path });
+    };
+    let mut body = String::new();
+    let mut target = Duration::ZERO;
+    let mut expected = first;
+    for (sequence, segment) in &segments {
+        let name = segment
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        let duration = durations.get(name.as_ref()).copied().unwrap_or(fallback);
+        target = target.max(duration);
+        // a gap in the sequence means the packager restarted
+        if *sequence != expected {
+            body.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        if let Some(tag) = date_ranges.get(name.as_ref()) {
+            let _ = writeln!(body, "{}", tag);
+        }
+        expected = sequence + 1;
+        let _ = writeln!(body, "#EXTINF:{:.3},\n{}", duration.as_secs_f64(), name);
+    }
+    let content = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-TARGETDURATION:{}\n\
+         #EXT-X-MEDIA-SEQUENCE:{}\n{}#EXT-X-ENDLIST\n",
+        target.as_secs_f64().ceil() as u64,
+        first,
+        body
+    );
+    storage::write_atomic(storage, &path, content.as_bytes())?;
+    Ok(path)
+}